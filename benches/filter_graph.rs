@@ -0,0 +1,124 @@
+//! Push the same 2160p frames through several graphs, as `DeepProbe` does with its orders,
+//! with `FilterGraph::process` and with the routing it replaced, to compare them.
+//!
+//! Run with `cargo bench --bench filter_graph`.
+
+use stainless_ffmpeg::{prelude::*, tools::rational::Rational};
+use std::time::{Duration, Instant};
+
+const GRAPHS: usize = 10;
+const FRAMES: i64 = 250;
+
+fn new_graphs() -> Vec<FilterGraph> {
+  (0..GRAPHS)
+    .map(|_| {
+      let mut graph = FilterGraph::new().unwrap();
+      graph
+        .add_video_input(
+          "source",
+          3840,
+          2160,
+          "yuv422p10le",
+          Rational::new(1, 25),
+          Rational::new(1, 1),
+        )
+        .unwrap();
+      graph.add_video_output("output").unwrap();
+      graph.parse("[source]null[output]").unwrap();
+      graph.validate().unwrap();
+      graph
+    })
+    .collect()
+}
+
+/// Routing before the input indexes: inputs are found by comparing labels, each frame is
+/// cloned for each input, and a frame is allocated for each read of the sinks.
+fn process_with_copies(graph: &FilterGraph, frames: &[Frame]) -> Vec<Frame> {
+  let mut output_frames = vec![];
+  unsafe {
+    for frame in frames {
+      for input in &graph.video_inputs {
+        if frame.name.as_deref() == Some(input.get_label().as_str()) {
+          let mut clone = av_frame_clone(frame.frame);
+          let result = av_buffersrc_add_frame_flags(input.context, clone, AV_BUFFERSRC_FLAG_PUSH);
+          av_frame_free(&mut clone);
+          assert!(result >= 0);
+        }
+      }
+    }
+    for (index, output) in graph.video_outputs.iter().enumerate() {
+      loop {
+        let frame = Frame {
+          name: Some(output.get_label()),
+          frame: av_frame_alloc(),
+          index,
+        };
+        let result =
+          av_buffersink_get_frame_flags(output.context, frame.frame, AV_BUFFERSINK_FLAG_NO_REQUEST);
+        if result < 0 {
+          break;
+        }
+        output_frames.push(frame);
+      }
+    }
+  }
+  output_frames
+}
+
+fn run(frame: &Frame, mut process: impl FnMut(usize) -> usize) -> Duration {
+  let start = Instant::now();
+  for pts in 0..FRAMES {
+    unsafe {
+      (*frame.frame).pts = pts;
+    }
+    for graph in 0..GRAPHS {
+      assert_eq!(process(graph), 1);
+    }
+  }
+  start.elapsed()
+}
+
+fn main() {
+  unsafe {
+    av_log_set_level(AV_LOG_ERROR);
+  }
+
+  let frame = unsafe {
+    let frame = av_frame_alloc();
+    (*frame).width = 3840;
+    (*frame).height = 2160;
+    (*frame).format = AVPixelFormat::AV_PIX_FMT_YUV422P10LE as i32;
+    assert_eq!(av_frame_get_buffer(frame, 0), 0);
+    Frame {
+      name: Some("source".to_string()),
+      frame,
+      index: 0,
+    }
+  };
+  let frames = std::slice::from_ref(&frame);
+
+  let graphs = new_graphs();
+  let with_copies = run(&frame, |graph| {
+    process_with_copies(&graphs[graph], frames).len()
+  });
+
+  let mut graphs = new_graphs();
+  let with_references = run(&frame, |graph| {
+    graphs[graph].process(&[], frames).unwrap().1.len()
+  });
+
+  let count = FRAMES as u32 * GRAPHS as u32;
+  println!("{FRAMES} frames through {GRAPHS} graphs:");
+  println!(
+    "  copies and label lookups: {with_copies:?}, {:?} per frame and graph",
+    with_copies / count
+  );
+  println!(
+    "  references and indexes:   {with_references:?}, {:?} per frame and graph",
+    with_references / count
+  );
+  println!(
+    "  {:.2}x faster",
+    with_copies.as_secs_f64() / with_references.as_secs_f64()
+  );
+}
@@ -43,10 +43,24 @@ fn main() {
         None
       }
     }
+    60 => {
+      if avcodec_version_minor >= 31 {
+        Some("ffmpeg_6_1")
+      } else {
+        Some("ffmpeg_6_0")
+      }
+    }
+    61 => {
+      if avcodec_version_minor >= 19 {
+        Some("ffmpeg_7_1")
+      } else {
+        Some("ffmpeg_7_0")
+      }
+    }
     _ => None,
   };
 
-  println!("cargo:rustc-check-cfg=cfg(ffmpeg_4_0, ffmpeg_4_1, ffmpeg_4_2, ffmpeg_4_3, ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1)");
+  println!("cargo:rustc-check-cfg=cfg(ffmpeg_4_0, ffmpeg_4_1, ffmpeg_4_2, ffmpeg_4_3, ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)");
   if let Some(ffmpeg_version) = ffmpeg_version {
     // println!("cargo:warning={}", ffmpeg_version);
     println!("cargo:rustc-cfg={ffmpeg_version}");
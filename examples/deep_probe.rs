@@ -206,6 +206,7 @@ fn main() {
       dualmono_detect: Some(dualmono_params),
       sine_detect: Some(sine_params),
       freeze_detect: Some(freeze_params),
+      caption_detect: None,
     };
     probe.process(LevelFilter::Off, check).unwrap();
     let result = serde_json::to_string(&probe).unwrap();
@@ -1,8 +1,8 @@
 use cpal::{SampleRate, Stream};
 use env_logger::Builder;
 use ringbuf::{Consumer, RingBuffer};
-use stainless_ffmpeg::prelude::*;
-use std::{collections::HashMap, convert::TryInto, env};
+use stainless_ffmpeg::{prelude::*, resampler::Resampler};
+use std::{convert::TryInto, env, slice};
 
 const SAMPLE_RATE: SampleRate = SampleRate(48_000);
 
@@ -40,40 +40,12 @@ fn main() {
 
     log::info!("{}", audio_decoder.get_sample_fmt_name());
 
-    let mut graph = FilterGraph::new().unwrap();
-
-    graph
-      .add_input_from_audio_decoder("source_audio", &audio_decoder)
-      .unwrap();
-
-    let mut parameters = HashMap::new();
-    parameters.insert(
-      "sample_rates".to_string(),
-      ParameterValue::String("48000".to_string()),
-    );
-    parameters.insert(
-      "channel_layouts".to_string(),
-      ParameterValue::String("stereo".to_string()),
-    );
-    parameters.insert(
-      "sample_fmts".to_string(),
-      ParameterValue::String("s32".to_string()),
-    );
-
-    let filter = Filter {
-      name: "aformat".to_string(),
-      label: Some("Format audio samples".to_string()),
-      parameters,
-      inputs: None,
-      outputs: None,
-    };
-
-    let filter = graph.add_filter(&filter).unwrap();
-    graph.add_audio_output("main_audio").unwrap();
-
-    graph.connect_input("source_audio", 0, &filter, 0).unwrap();
-    graph.connect_output(&filter, 0, "main_audio", 0).unwrap();
-    graph.validate().unwrap();
+    let mut resampler = Resampler::new(
+      "stereo".parse().unwrap(),
+      SAMPLE_RATE.0 as i32,
+      "flt".parse().unwrap(),
+    )
+    .unwrap();
 
     while let Ok(packet) = format_context.next_packet() {
       if packet.get_stream_index() != first_audio_stream {
@@ -81,33 +53,24 @@ fn main() {
       }
 
       let frame = audio_decoder.decode(&packet).unwrap();
-
-      let (frames, _) = graph.process(&[frame], &[]).unwrap();
-
-      let frame = frames.first().unwrap();
+      let frame = resampler.resample(&frame).unwrap();
 
       unsafe {
-        let size = ((*frame.frame).channels * (*frame.frame).nb_samples) as usize;
+        let channels = ChannelLayout::from_frame(frame.frame).get_nb_channels();
+        let size = (channels * (*frame.frame).nb_samples) as usize;
         let sample_format: SampleFormat = (*frame.frame).format.try_into().unwrap();
 
         log::info!(
           "Frame {} samples, {} channels, {:?}, {} bytes // {} bytes",
           (*frame.frame).nb_samples,
-          (*frame.frame).channels,
+          channels,
           sample_format,
           (*frame.frame).linesize[0],
           size,
         );
 
-        let samples: Vec<i32> = Vec::from_raw_parts((*frame.frame).data[0] as _, size, size);
-
-        let float_samples: Vec<f32> = samples
-          .iter()
-          .map(|value| (*value as f32) / i32::MAX as f32)
-          .collect();
-
-        producer.push_slice(&float_samples);
-        std::mem::forget(samples);
+        let samples = slice::from_raw_parts((*frame.frame).data[0] as *const f32, size);
+        producer.push_slice(samples);
       }
     }
   }
@@ -1,4 +1,6 @@
-use crate::{format_context::FormatContext, frame::Frame, packet::Packet, tools};
+use crate::{
+  channel_layout::ChannelLayout, format_context::FormatContext, frame::Frame, packet::Packet, tools,
+};
 use ffmpeg_sys_next::*;
 use std::ptr::null_mut;
 
@@ -45,11 +47,11 @@ impl AudioDecoder {
   }
 
   pub fn get_nb_channels(&self) -> i32 {
-    unsafe { (*self.codec_context).channels }
+    self.get_channel_layout().get_nb_channels()
   }
 
-  pub fn get_channel_layout(&self) -> u64 {
-    unsafe { (*self.codec_context).channel_layout }
+  pub fn get_channel_layout(&self) -> ChannelLayout {
+    unsafe { ChannelLayout::from_codec_context(self.codec_context) }
   }
 
   pub fn get_sample_fmt_name(&self) -> String {
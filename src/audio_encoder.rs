@@ -1,14 +1,20 @@
 use crate::{
+  channel_layout::ChannelLayout,
+  codec::{join, Codec},
   frame::Frame,
   order::{
-    output::{ChannelLayout, OutputStream, SampleFormat},
-    parameters::ParameterValue,
+    output::{OutputStream, SampleFormat},
+    parameters::{set_codec_options, ParameterValue},
   },
   packet::Packet,
   tools,
 };
 use ffmpeg_sys_next::*;
-use std::{collections::HashMap, ptr::null_mut};
+use libc::c_void;
+use std::{collections::HashMap, convert::TryFrom, ptr::null_mut};
+
+/// Parameters mapped to codec context fields, the other ones are set as codec options.
+const AUDIO_ENCODER_PARAMETERS: [&str; 3] = ["sample_rate", "sample_fmt", "channel_layout"];
 
 #[derive(Debug)]
 pub struct AudioEncoder {
@@ -16,6 +22,12 @@ pub struct AudioEncoder {
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
   pub codec: *const AVCodec,
+  /// Time base of the frames sent to the encoder, the one of the filter graph output.
+  input_time_base: AVRational,
+  /// Samples waiting to be encoded, for codecs which take frames of exactly `frame_size` samples.
+  fifo: *mut AVAudioFifo,
+  /// Timestamp of the next frame read from the FIFO, in the encoder time base.
+  next_pts: i64,
 }
 
 impl AudioEncoder {
@@ -29,73 +41,331 @@ impl AudioEncoder {
       if codec.is_null() {
         return Err(format!("Unable to found codec {}", output_stream.codec));
       }
+      let parameters = &output_stream.parameters;
+      let channel_layout = AudioEncoder::select_channel_layout(codec, parameters)?;
+      let sample_format = AudioEncoder::select_sample_format(&Codec { codec }, parameters)?;
+      let sample_rate = AudioEncoder::select_sample_rate(&Codec { codec }, parameters)?;
+
       let mut codec_context = avcodec_alloc_context3(codec);
 
-      let parameters = &output_stream.parameters;
-      if let Some(ParameterValue::Rational(data)) = parameters.get("sample_rate") {
-        (*codec_context).time_base = data.clone().invert().into();
-        (*codec_context).sample_rate = data.num / data.den;
-      }
+      (*codec_context).time_base = AVRational {
+        num: 1,
+        den: sample_rate,
+      };
+      (*codec_context).sample_rate = sample_rate;
+      (*codec_context).sample_fmt = sample_format.into();
 
-      if let Some(ParameterValue::String(data)) = parameters.get("sample_fmt") {
-        let sample_fmt: SampleFormat = data.parse().unwrap();
-        (*codec_context).sample_fmt = sample_fmt.into();
-      }
+      channel_layout
+        .set_to_codec_context(codec_context)
+        .map_err(|error| {
+          avcodec_free_context(&mut codec_context);
+          error
+        })?;
 
-      (*codec_context).channel_layout = AudioEncoder::select_channel_layout(codec, parameters);
-      (*codec_context).channels =
-        av_get_channel_layout_nb_channels((*codec_context).channel_layout);
+      if let Err(msg) = set_codec_options(codec_context, parameters, &AUDIO_ENCODER_PARAMETERS) {
+        avcodec_free_context(&mut codec_context);
+        return Err(msg);
+      }
 
       check_result!(avcodec_open2(codec_context, codec, null_mut()), {
         avcodec_free_context(&mut codec_context);
       });
 
+      let fifo = if (*codec_context).frame_size > 0
+        && (*codec).capabilities as u32 & AV_CODEC_CAP_VARIABLE_FRAME_SIZE == 0
+      {
+        let fifo = av_audio_fifo_alloc(
+          (*codec_context).sample_fmt,
+          channel_layout.get_nb_channels(),
+          (*codec_context).frame_size,
+        );
+        if fifo.is_null() {
+          avcodec_free_context(&mut codec_context);
+          return Err("Unable to allocate the audio FIFO".to_string());
+        }
+        fifo
+      } else {
+        null_mut()
+      };
+
       Ok(AudioEncoder {
         identifier,
         stream_index,
         codec_context,
         codec,
+        input_time_base: (*codec_context).time_base,
+        fifo,
+        next_pts: 0,
       })
     }
   }
 
-  pub fn encode(&self, frame: &Frame, packet: &Packet) -> Result<bool, String> {
+  /// Parameters of the `aformat` filter converting frames to the encoder sample format,
+  /// sample rate and channel layout.
+  pub fn get_input_format(&self) -> HashMap<String, ParameterValue> {
+    let mut parameters = HashMap::new();
     unsafe {
-      check_result!(avcodec_send_frame(self.codec_context, frame.frame));
-      let ret = avcodec_receive_packet(self.codec_context, packet.packet as *mut _);
+      if let Ok(format) = SampleFormat::try_from((*self.codec_context).sample_fmt as i32) {
+        parameters.insert(
+          "sample_fmts".to_string(),
+          ParameterValue::String(format.to_string()),
+        );
+      }
+      parameters.insert(
+        "sample_rates".to_string(),
+        ParameterValue::String((*self.codec_context).sample_rate.to_string()),
+      );
+      parameters.insert(
+        "channel_layouts".to_string(),
+        ParameterValue::String(ChannelLayout::from_codec_context(self.codec_context).to_string()),
+      );
+    }
+    parameters
+  }
 
-      if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
-        let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
-        av_strerror(ret, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
-        trace!("{}", tools::to_string(data.as_ptr()));
-        return Ok(false);
+  /// Set the time base of the incoming frames, by default the encoder time base.
+  pub fn set_input_time_base(&mut self, time_base: AVRational) {
+    if time_base.num > 0 && time_base.den > 0 {
+      self.input_time_base = time_base;
+    }
+  }
+
+  /// Encode a frame coming from the filter graph. Its samples are re-chunked into frames of
+  /// `frame_size` samples when the codec requires it, so zero or several packets can be returned.
+  pub fn encode(&mut self, frame: &Frame) -> Result<Vec<Packet>, String> {
+    unsafe {
+      let input_pts = (*frame.frame).pts;
+      let pts = if input_pts == AV_NOPTS_VALUE {
+        AV_NOPTS_VALUE
+      } else {
+        av_rescale_q(
+          input_pts,
+          self.input_time_base,
+          (*self.codec_context).time_base,
+        )
+      };
+
+      if self.fifo.is_null() {
+        (*frame.frame).pts = pts;
+        let ret = avcodec_send_frame(self.codec_context, frame.frame);
+        // the frame can be shared with other encoders
+        (*frame.frame).pts = input_pts;
+        check_result!(ret);
+        return self.receive_packets();
+      }
+
+      // the FIFO reads the samples in the sample format and channels of the encoder
+      let nb_channels = ChannelLayout::from_codec_context(self.codec_context).get_nb_channels();
+      if (*frame.frame).format != (*self.codec_context).sample_fmt as i32
+        || ChannelLayout::from_frame(frame.frame).get_nb_channels() != nb_channels
+      {
+        return Err(format!(
+          "The {} frames are not in the sample format and channels of their encoder",
+          self.identifier
+        ));
+      }
+
+      if av_audio_fifo_size(self.fifo) == 0 && pts != AV_NOPTS_VALUE {
+        self.next_pts = pts;
       }
+      check_result!(av_audio_fifo_write(
+        self.fifo,
+        (*frame.frame).extended_data as *mut *mut c_void,
+        (*frame.frame).nb_samples
+      ));
 
-      check_result!(ret);
+      let mut packets = vec![];
+      while av_audio_fifo_size(self.fifo) >= (*self.codec_context).frame_size {
+        packets.extend(self.encode_fifo_frame()?);
+      }
+      Ok(packets)
+    }
+  }
 
-      trace!(
-        "received encoded packet with {} bytes",
-        (*packet.packet).size
+  /// Encode the remaining samples, the last frame padded with silence, and drain the encoder.
+  pub fn flush(&mut self) -> Result<Vec<Packet>, String> {
+    let mut packets = vec![];
+    unsafe {
+      if !self.fifo.is_null() && av_audio_fifo_size(self.fifo) > 0 {
+        packets.extend(self.encode_fifo_frame()?);
+      }
+      check_result!(avcodec_send_frame(self.codec_context, null_mut()));
+    }
+    packets.extend(self.receive_packets()?);
+    Ok(packets)
+  }
+
+  unsafe fn encode_fifo_frame(&mut self) -> Result<Vec<Packet>, String> {
+    let frame_size = (*self.codec_context).frame_size;
+    let nb_samples = av_audio_fifo_size(self.fifo).min(frame_size);
+    let channel_layout = ChannelLayout::from_codec_context(self.codec_context);
+
+    let frame = Frame {
+      name: Some(self.identifier.clone()),
+      frame: av_frame_alloc(),
+      index: 0,
+    };
+    if frame.frame.is_null() {
+      return Err("Unable to allocate an audio frame".to_string());
+    }
+    (*frame.frame).nb_samples = frame_size;
+    (*frame.frame).format = (*self.codec_context).sample_fmt as i32;
+    (*frame.frame).sample_rate = (*self.codec_context).sample_rate;
+    channel_layout.set_to_frame(frame.frame)?;
+    check_result!(av_frame_get_buffer(frame.frame, 0));
+
+    check_result!(av_audio_fifo_read(
+      self.fifo,
+      (*frame.frame).extended_data as *mut *mut c_void,
+      nb_samples
+    ));
+    if nb_samples < frame_size {
+      av_samples_set_silence(
+        (*frame.frame).extended_data,
+        nb_samples,
+        frame_size - nb_samples,
+        channel_layout.get_nb_channels(),
+        (*self.codec_context).sample_fmt,
       );
-      Ok(true)
     }
+
+    (*frame.frame).pts = self.next_pts;
+    self.next_pts += av_rescale_q(
+      i64::from(frame_size),
+      AVRational {
+        num: 1,
+        den: (*self.codec_context).sample_rate,
+      },
+      (*self.codec_context).time_base,
+    );
+
+    check_result!(avcodec_send_frame(self.codec_context, frame.frame));
+    self.receive_packets()
   }
 
+  fn receive_packets(&self) -> Result<Vec<Packet>, String> {
+    let mut packets = vec![];
+    unsafe {
+      loop {
+        let packet = Packet {
+          name: Some(self.identifier.clone()),
+          packet: av_packet_alloc(),
+        };
+        let ret = avcodec_receive_packet(self.codec_context, packet.packet);
+
+        if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
+          let mut data = [0; AV_ERROR_MAX_STRING_SIZE];
+          av_strerror(ret, data.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
+          trace!("{}", tools::to_string(data.as_ptr()));
+          break;
+        }
+
+        check_result!(ret);
+
+        trace!(
+          "received encoded packet with {} bytes",
+          (*packet.packet).size
+        );
+        packets.push(packet);
+      }
+    }
+    Ok(packets)
+  }
+
+  /// The requested sample format checked against the codec, or the first one it supports.
+  fn select_sample_format(
+    codec: &Codec,
+    parameters: &HashMap<String, ParameterValue>,
+  ) -> Result<SampleFormat, String> {
+    let supported_formats = codec.get_sample_formats();
+    match parameters.get("sample_fmt") {
+      Some(ParameterValue::String(data)) => {
+        let format: SampleFormat = data.parse()?;
+        if !codec.supports_sample_format(&format) {
+          return Err(format!(
+            "Sample format {format} is not supported by the {} encoder, supported formats: {}",
+            codec.get_name(),
+            join(&supported_formats)
+          ));
+        }
+        Ok(format)
+      }
+      _ => {
+        let format = supported_formats.first().copied().ok_or_else(|| {
+          format!(
+            "A sample_fmt parameter is required by the {} encoder",
+            codec.get_name()
+          )
+        })?;
+        info!(
+          "Use {format} sample format for the {} encoder",
+          codec.get_name()
+        );
+        Ok(format)
+      }
+    }
+  }
+
+  /// The requested sample rate checked against the codec, or 48 kHz when supported.
+  fn select_sample_rate(
+    codec: &Codec,
+    parameters: &HashMap<String, ParameterValue>,
+  ) -> Result<i32, String> {
+    let supported_rates = codec.get_sample_rates();
+    match parameters.get("sample_rate") {
+      Some(ParameterValue::Rational(data)) if data.den != 0 => {
+        let sample_rate = data.num / data.den;
+        if !codec.supports_sample_rate(sample_rate) {
+          return Err(format!(
+            "Sample rate {sample_rate} is not supported by the {} encoder, supported rates: {}",
+            codec.get_name(),
+            join(&supported_rates)
+          ));
+        }
+        Ok(sample_rate)
+      }
+      _ => {
+        let sample_rate = if codec.supports_sample_rate(48000) {
+          48000
+        } else {
+          supported_rates[0]
+        };
+        info!(
+          "Use {sample_rate} Hz sample rate for the {} encoder",
+          codec.get_name()
+        );
+        Ok(sample_rate)
+      }
+    }
+  }
+
+  /// The requested layout, or stereo, checked against the layouts supported by the codec.
   fn select_channel_layout(
     codec: *const AVCodec,
     parameters: &HashMap<String, ParameterValue>,
-  ) -> u64 {
+  ) -> Result<ChannelLayout, String> {
     unsafe {
-      if codec.is_null() || (*codec).channel_layouts.is_null() {
-        if let Some(ParameterValue::String(data)) = parameters.get("channel_layout") {
-          let layout: ChannelLayout = data.parse().unwrap();
-          layout.into()
-        } else {
-          AV_CH_LAYOUT_STEREO
+      let supported_layouts = ChannelLayout::get_codec_layouts(codec);
+      let layout = match parameters.get("channel_layout") {
+        Some(ParameterValue::String(data)) => data.parse()?,
+        _ => {
+          let layout = ChannelLayout::default();
+          if layout.is_supported_by(codec) {
+            layout
+          } else {
+            supported_layouts[0].clone()
+          }
         }
-      } else {
-        AV_CH_LAYOUT_STEREO
+      };
+
+      if !layout.is_supported_by(codec) {
+        return Err(format!(
+          "Channel layout {layout} is not supported by the {} encoder, supported layouts: {}",
+          tools::to_string((*codec).name),
+          join(&supported_layouts)
+        ));
       }
+      Ok(layout)
     }
   }
 }
@@ -103,6 +373,9 @@ impl AudioEncoder {
 impl Drop for AudioEncoder {
   fn drop(&mut self) {
     unsafe {
+      if !self.fifo.is_null() {
+        av_audio_fifo_free(self.fifo);
+      }
       if !self.codec_context.is_null() {
         avcodec_close(self.codec_context);
         avcodec_free_context(&mut self.codec_context);
@@ -110,3 +383,73 @@ impl Drop for AudioEncoder {
     }
   }
 }
+
+#[test]
+fn rechunk_fixed_size_frames() {
+  let output_stream = OutputStream {
+    label: None,
+    codec: "mp2".to_string(),
+    parameters: HashMap::new(),
+    timing: vec![],
+    two_pass: false,
+  };
+  let mut encoder = AudioEncoder::new("audio".to_string(), 0, &output_stream).unwrap();
+  let frame_size = unsafe { (*encoder.codec_context).frame_size };
+  assert_eq!(frame_size, 1152);
+
+  let mut packets = vec![];
+  for index in 0..3 {
+    let frame = unsafe {
+      let frame = av_frame_alloc();
+      ChannelLayout::default().set_to_frame(frame).unwrap();
+      (*frame).sample_rate = 48_000;
+      (*frame).format = AVSampleFormat::AV_SAMPLE_FMT_S16 as i32;
+      (*frame).nb_samples = 1000;
+      (*frame).pts = index * 1000;
+      assert_eq!(av_frame_get_buffer(frame, 0), 0);
+      Frame {
+        name: Some("audio".to_string()),
+        frame,
+        index: 0,
+      }
+    };
+    packets.extend(encoder.encode(&frame).unwrap());
+  }
+  assert_eq!(packets.len(), 2);
+
+  let frame = unsafe {
+    let frame = av_frame_alloc();
+    ChannelLayout::default_for(1).set_to_frame(frame).unwrap();
+    (*frame).sample_rate = 48_000;
+    (*frame).format = AVSampleFormat::AV_SAMPLE_FMT_S16 as i32;
+    (*frame).nb_samples = 1000;
+    assert_eq!(av_frame_get_buffer(frame, 0), 0);
+    Frame {
+      name: Some("audio".to_string()),
+      frame,
+      index: 0,
+    }
+  };
+  let error = "The audio frames are not in the sample format and channels of their encoder";
+  assert_eq!(encoder.encode(&frame).err(), Some(error.to_string()));
+  unsafe {
+    ChannelLayout::default().set_to_frame(frame.frame).unwrap();
+    (*frame.frame).format = AVSampleFormat::AV_SAMPLE_FMT_FLTP as i32;
+  }
+  assert_eq!(encoder.encode(&frame).err(), Some(error.to_string()));
+
+  assert_eq!(
+    unsafe { av_audio_fifo_size(encoder.fifo) },
+    3000 - 2 * frame_size
+  );
+
+  // the remaining samples are padded into a last full frame
+  packets.extend(encoder.flush().unwrap());
+  assert_eq!(unsafe { av_audio_fifo_size(encoder.fifo) }, 0);
+  assert_eq!(encoder.next_pts, 3 * i64::from(frame_size));
+  let timestamps: Vec<(i64, i64)> = packets
+    .iter()
+    .map(|packet| unsafe { ((*packet.packet).pts, (*packet.packet).duration) })
+    .collect();
+  assert_eq!(timestamps, vec![(0, 1152), (1152, 1152), (2304, 1152)]);
+}
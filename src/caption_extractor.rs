@@ -0,0 +1,129 @@
+use crate::{frame::Frame, packet::Packet, subtitle::Subtitle, video_decoder::VideoDecoder};
+use ffmpeg_sys_next::*;
+use std::{
+  ffi::CString,
+  mem,
+  ptr::{copy_nonoverlapping, null_mut},
+};
+
+/// Decode the closed captions (CEA-608/708) carried as A53 side data of video frames.
+#[derive(Debug)]
+pub struct CaptionExtractor {
+  pub identifier: String,
+  pub video_identifier: String,
+  pub stream_index: isize,
+  pub codec_context: *mut AVCodecContext,
+}
+
+impl CaptionExtractor {
+  pub fn new(identifier: String, video_decoder: &VideoDecoder) -> Result<Self, String> {
+    unsafe {
+      let codec_name = CString::new("cc_dec").unwrap();
+      let codec = avcodec_find_decoder_by_name(codec_name.as_ptr());
+      if codec.is_null() {
+        return Err("Unable to find the closed caption decoder".to_string());
+      }
+      let mut codec_context = avcodec_alloc_context3(codec);
+
+      let time_base = video_decoder.get_time_base().into();
+      (*codec_context).time_base = time_base;
+      (*codec_context).pkt_timebase = time_base;
+
+      check_result!(avcodec_open2(codec_context, codec, null_mut()), {
+        avcodec_free_context(&mut codec_context);
+      });
+
+      Ok(CaptionExtractor {
+        identifier,
+        video_identifier: video_decoder.identifier.clone(),
+        stream_index: video_decoder.stream_index,
+        codec_context,
+      })
+    }
+  }
+
+  /// Returns a subtitle for each frame carrying captions. Its packet keeps the raw
+  /// cc_data, to be copied into `eia_608` streams, and its rects are empty until the
+  /// decoded caption changes.
+  pub fn extract(&self, frame: &Frame) -> Result<Option<Subtitle>, String> {
+    let cc_data = match frame.get_a53_closed_captions() {
+      Some(cc_data) => cc_data,
+      None => return Ok(None),
+    };
+
+    unsafe {
+      let mut packet = av_packet_alloc();
+      check_result!(av_new_packet(packet, cc_data.len() as i32), {
+        av_packet_free(&mut packet);
+      });
+      copy_nonoverlapping(cc_data.as_ptr(), (*packet).data, cc_data.len());
+      (*packet).pts = if (*frame.frame).pts == AV_NOPTS_VALUE {
+        (*frame.frame).best_effort_timestamp
+      } else {
+        (*frame.frame).pts
+      };
+      (*packet).dts = (*packet).pts;
+      let packet = Packet {
+        name: Some(self.identifier.clone()),
+        packet,
+      };
+
+      let milliseconds = AVRational { num: 1, den: 1000 };
+      let pts = av_rescale_q(
+        (*packet.packet).pts,
+        (*self.codec_context).pkt_timebase,
+        milliseconds,
+      );
+
+      let mut av_subtitle: AVSubtitle = mem::zeroed();
+      let mut got_subtitle = 0;
+      check_result!(avcodec_decode_subtitle2(
+        self.codec_context,
+        &mut av_subtitle,
+        &mut got_subtitle,
+        packet.packet
+      ));
+
+      let mut subtitle = if got_subtitle != 0 {
+        let subtitle = Subtitle::from_av_subtitle(
+          &av_subtitle,
+          Some(self.identifier.clone()),
+          self.stream_index as usize,
+          pts,
+        );
+        avsubtitle_free(&mut av_subtitle);
+        subtitle
+      } else {
+        Subtitle {
+          name: Some(self.identifier.clone()),
+          index: self.stream_index as usize,
+          start: pts,
+          end: pts,
+          rects: vec![],
+          packet: None,
+        }
+      };
+
+      trace!(
+        "extracted {} bytes of captions at {} ms for {}",
+        cc_data.len(),
+        pts,
+        self.identifier
+      );
+
+      subtitle.packet = Some(packet);
+      Ok(Some(subtitle))
+    }
+  }
+}
+
+impl Drop for CaptionExtractor {
+  fn drop(&mut self) {
+    unsafe {
+      if !self.codec_context.is_null() {
+        avcodec_close(self.codec_context);
+        avcodec_free_context(&mut self.codec_context);
+      }
+    }
+  }
+}
@@ -0,0 +1,577 @@
+use crate::tools;
+use ffmpeg_sys_next::*;
+use libc::c_void;
+use std::{convert::TryFrom, ffi::CString, fmt, str::FromStr};
+#[cfg(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+use std::{mem, ptr::null_mut};
+
+/// Audio channel layout, backed by `AVChannelLayout` since FFmpeg 5.1 and by the
+/// channel mask and channel count before.
+#[cfg(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+pub struct ChannelLayout {
+  layout: AVChannelLayout,
+}
+
+/// Audio channel layout, backed by `AVChannelLayout` since FFmpeg 5.1 and by the
+/// channel mask and channel count before.
+#[cfg(not(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ChannelLayout {
+  mask: u64,
+  nb_channels: i32,
+}
+
+#[cfg(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+impl ChannelLayout {
+  pub fn from_mask(mask: u64) -> Result<Self, String> {
+    unsafe {
+      let mut layout: AVChannelLayout = mem::zeroed();
+      check_result!(av_channel_layout_from_mask(&mut layout, mask));
+      Ok(ChannelLayout { layout })
+    }
+  }
+
+  /// Default layout for a number of channels, unspecified when FFmpeg has none.
+  pub fn default_for(nb_channels: i32) -> Self {
+    unsafe {
+      let mut layout: AVChannelLayout = mem::zeroed();
+      av_channel_layout_default(&mut layout, nb_channels);
+      ChannelLayout { layout }
+    }
+  }
+
+  pub fn get_nb_channels(&self) -> i32 {
+    self.layout.nb_channels
+  }
+
+  /// Channel mask, only defined for layouts in the native channel order.
+  pub fn get_mask(&self) -> Option<u64> {
+    if self.layout.order == AVChannelOrder::AV_CHANNEL_ORDER_NATIVE {
+      unsafe { Some(self.layout.u.mask) }
+    } else {
+      None
+    }
+  }
+
+  /// # Safety
+  /// `codec_context` must point to a valid codec context.
+  pub unsafe fn from_codec_context(codec_context: *const AVCodecContext) -> Self {
+    Self::copy_from(&(*codec_context).ch_layout)
+  }
+
+  /// # Safety
+  /// `codec_parameters` must point to valid codec parameters.
+  pub unsafe fn from_codec_parameters(codec_parameters: *const AVCodecParameters) -> Self {
+    Self::copy_from(&(*codec_parameters).ch_layout)
+  }
+
+  /// # Safety
+  /// `frame` must point to a valid frame.
+  pub unsafe fn from_frame(frame: *const AVFrame) -> Self {
+    Self::copy_from(&(*frame).ch_layout)
+  }
+
+  /// # Safety
+  /// `link` must point to a valid filter link.
+  pub unsafe fn from_filter_link(link: *const AVFilterLink) -> Self {
+    Self::copy_from(&(*link).ch_layout)
+  }
+
+  /// # Safety
+  /// `codec_context` must point to a valid codec context.
+  pub unsafe fn set_to_codec_context(
+    &self,
+    codec_context: *mut AVCodecContext,
+  ) -> Result<(), String> {
+    check_result!(av_channel_layout_copy(
+      &mut (*codec_context).ch_layout,
+      &self.layout
+    ));
+    Ok(())
+  }
+
+  /// # Safety
+  /// `frame` must point to a valid frame.
+  pub unsafe fn set_to_frame(&self, frame: *mut AVFrame) -> Result<(), String> {
+    check_result!(av_channel_layout_copy(
+      &mut (*frame).ch_layout,
+      &self.layout
+    ));
+    Ok(())
+  }
+
+  /// Set the `in` or `out` layout of a resampler.
+  ///
+  /// # Safety
+  /// `context` must point to a valid resampler context.
+  pub unsafe fn set_to_resampler(
+    &self,
+    context: *mut SwrContext,
+    prefix: &str,
+  ) -> Result<(), String> {
+    let name = CString::new(format!("{prefix}_chlayout")).unwrap();
+    check_result!(av_opt_set_chlayout(
+      context as *mut c_void,
+      name.as_ptr(),
+      &self.layout,
+      0
+    ));
+    Ok(())
+  }
+
+  /// Layouts supported by an encoder, empty when the encoder accepts any layout.
+  ///
+  /// # Safety
+  /// `codec` must be null or point to a valid codec.
+  pub unsafe fn get_codec_layouts(codec: *const AVCodec) -> Vec<Self> {
+    let mut layouts = vec![];
+    if codec.is_null() || (*codec).ch_layouts.is_null() {
+      return layouts;
+    }
+    let mut layout = (*codec).ch_layouts;
+    while (*layout).nb_channels != 0 {
+      layouts.push(Self::copy_from(layout));
+      layout = layout.add(1);
+    }
+    layouts
+  }
+
+  /// Layout with the channels in the given order, e.g. `["FR", "FL", "LFE"]`.
+  pub fn from_channels(channels: &[&str]) -> Result<Self, String> {
+    if channels.is_empty() {
+      return Err("A channel layout needs at least one channel".to_string());
+    }
+    unsafe {
+      let map =
+        av_calloc(channels.len(), mem::size_of::<AVChannelCustom>()) as *mut AVChannelCustom;
+      if map.is_null() {
+        return Err("Unable to allocate the channel map".to_string());
+      }
+      let mut layout: AVChannelLayout = mem::zeroed();
+      layout.order = AVChannelOrder::AV_CHANNEL_ORDER_CUSTOM;
+      layout.nb_channels = channels.len() as i32;
+      layout.u.map = map;
+      // owns the map from now on
+      let layout = ChannelLayout { layout };
+
+      for (index, name) in channels.iter().enumerate() {
+        (*map.add(index)).id =
+          channel_from_name(name).ok_or_else(|| format!("'{name}' is not a valid channel"))?;
+      }
+      Ok(layout)
+    }
+  }
+
+  /// All the layouts FFmpeg knows by name.
+  pub fn get_standard_layouts() -> Vec<Self> {
+    let mut layouts = vec![];
+    let mut opaque = null_mut();
+    unsafe {
+      loop {
+        let layout = av_channel_layout_standard(&mut opaque);
+        if layout.is_null() {
+          break;
+        }
+        layouts.push(Self::copy_from(layout));
+      }
+    }
+    layouts
+  }
+
+  /// Name of the channel at `index`, e.g. `FL`.
+  pub fn get_channel_name(&self, index: i32) -> Option<String> {
+    if index < 0 {
+      return None;
+    }
+    unsafe {
+      let channel = av_channel_layout_channel_from_index(&self.layout, index as u32);
+      if channel == AVChannel::AV_CHAN_NONE {
+        return None;
+      }
+      let mut buffer = [0; 32];
+      av_channel_name(buffer.as_mut_ptr(), buffer.len(), channel);
+      Some(tools::to_string(buffer.as_ptr()))
+    }
+  }
+
+  /// Index of a named channel, e.g. `LFE`.
+  pub fn get_channel_index(&self, name: &str) -> Option<i32> {
+    let name = CString::new(name).ok()?;
+    let index = unsafe { av_channel_layout_index_from_string(&self.layout, name.as_ptr()) };
+    if index < 0 {
+      None
+    } else {
+      Some(index)
+    }
+  }
+
+  unsafe fn copy_from(source: *const AVChannelLayout) -> Self {
+    let mut layout: AVChannelLayout = mem::zeroed();
+    if !source.is_null() {
+      av_channel_layout_copy(&mut layout, source);
+    }
+    ChannelLayout { layout }
+  }
+
+  fn describe(&self) -> String {
+    let mut buffer = [0; 128];
+    unsafe {
+      av_channel_layout_describe(&self.layout, buffer.as_mut_ptr(), buffer.len());
+    }
+    tools::to_string(buffer.as_ptr())
+  }
+
+  fn parse(name: &str) -> Option<Self> {
+    let name = CString::new(name).ok()?;
+    unsafe {
+      let mut layout: AVChannelLayout = mem::zeroed();
+      if av_channel_layout_from_string(&mut layout, name.as_ptr()) < 0 {
+        return None;
+      }
+      Some(ChannelLayout { layout })
+    }
+  }
+}
+
+#[cfg(not(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+impl ChannelLayout {
+  pub fn from_mask(mask: u64) -> Result<Self, String> {
+    let nb_channels = unsafe { av_get_channel_layout_nb_channels(mask) };
+    if nb_channels <= 0 {
+      return Err(format!("Invalid channel layout mask {mask:#x}"));
+    }
+    Ok(ChannelLayout { mask, nb_channels })
+  }
+
+  /// Default layout for a number of channels, unspecified when FFmpeg has none.
+  pub fn default_for(nb_channels: i32) -> Self {
+    let mask = unsafe { av_get_default_channel_layout(nb_channels) } as u64;
+    ChannelLayout { mask, nb_channels }
+  }
+
+  pub fn get_nb_channels(&self) -> i32 {
+    self.nb_channels
+  }
+
+  /// Channel mask, only defined for layouts in the native channel order.
+  pub fn get_mask(&self) -> Option<u64> {
+    if self.mask == 0 {
+      None
+    } else {
+      Some(self.mask)
+    }
+  }
+
+  /// # Safety
+  /// `codec_context` must point to a valid codec context.
+  pub unsafe fn from_codec_context(codec_context: *const AVCodecContext) -> Self {
+    ChannelLayout {
+      mask: (*codec_context).channel_layout,
+      nb_channels: (*codec_context).channels,
+    }
+  }
+
+  /// # Safety
+  /// `codec_parameters` must point to valid codec parameters.
+  pub unsafe fn from_codec_parameters(codec_parameters: *const AVCodecParameters) -> Self {
+    ChannelLayout {
+      mask: (*codec_parameters).channel_layout,
+      nb_channels: (*codec_parameters).channels,
+    }
+  }
+
+  /// # Safety
+  /// `frame` must point to a valid frame.
+  pub unsafe fn from_frame(frame: *const AVFrame) -> Self {
+    ChannelLayout {
+      mask: (*frame).channel_layout,
+      nb_channels: (*frame).channels,
+    }
+  }
+
+  /// # Safety
+  /// `link` must point to a valid filter link.
+  pub unsafe fn from_filter_link(link: *const AVFilterLink) -> Self {
+    ChannelLayout {
+      mask: (*link).channel_layout,
+      nb_channels: (*link).channels,
+    }
+  }
+
+  /// # Safety
+  /// `codec_context` must point to a valid codec context.
+  pub unsafe fn set_to_codec_context(
+    &self,
+    codec_context: *mut AVCodecContext,
+  ) -> Result<(), String> {
+    (*codec_context).channel_layout = self.mask;
+    (*codec_context).channels = self.nb_channels;
+    Ok(())
+  }
+
+  /// # Safety
+  /// `frame` must point to a valid frame.
+  pub unsafe fn set_to_frame(&self, frame: *mut AVFrame) -> Result<(), String> {
+    (*frame).channel_layout = self.mask;
+    (*frame).channels = self.nb_channels;
+    Ok(())
+  }
+
+  /// Set the `in` or `out` layout of a resampler.
+  ///
+  /// # Safety
+  /// `context` must point to a valid resampler context.
+  pub unsafe fn set_to_resampler(
+    &self,
+    context: *mut SwrContext,
+    prefix: &str,
+  ) -> Result<(), String> {
+    let name = CString::new(format!("{prefix}_channel_layout")).unwrap();
+    check_result!(av_opt_set_channel_layout(
+      context as *mut c_void,
+      name.as_ptr(),
+      self.mask as i64,
+      0
+    ));
+    let name = CString::new(format!("{prefix}_channel_count")).unwrap();
+    check_result!(av_opt_set_int(
+      context as *mut c_void,
+      name.as_ptr(),
+      i64::from(self.nb_channels),
+      0
+    ));
+    Ok(())
+  }
+
+  /// Layouts supported by an encoder, empty when the encoder accepts any layout.
+  ///
+  /// # Safety
+  /// `codec` must be null or point to a valid codec.
+  pub unsafe fn get_codec_layouts(codec: *const AVCodec) -> Vec<Self> {
+    let mut layouts = vec![];
+    if codec.is_null() || (*codec).channel_layouts.is_null() {
+      return layouts;
+    }
+    let mut mask = (*codec).channel_layouts;
+    while *mask != 0 {
+      if let Ok(layout) = Self::from_mask(*mask) {
+        layouts.push(layout);
+      }
+      mask = mask.add(1);
+    }
+    layouts
+  }
+
+  /// Layout with the channels in the given order, which has to be the native one
+  /// before FFmpeg 5.1.
+  pub fn from_channels(channels: &[&str]) -> Result<Self, String> {
+    let mut mask = 0;
+    for name in channels {
+      let channel =
+        channel_from_name(name).ok_or_else(|| format!("'{name}' is not a valid channel"))?;
+      if mask & !(channel - 1) != 0 {
+        return Err("Custom channel orders need FFmpeg 5.1 or later".to_string());
+      }
+      mask |= channel;
+    }
+    Self::from_mask(mask)
+  }
+
+  /// All the layouts FFmpeg knows by name.
+  pub fn get_standard_layouts() -> Vec<Self> {
+    let mut layouts = vec![];
+    let mut index = 0;
+    let mut mask = 0;
+    let mut name = std::ptr::null();
+    while unsafe { av_get_standard_channel_layout(index, &mut mask, &mut name) } == 0 {
+      if let Ok(layout) = Self::from_mask(mask) {
+        layouts.push(layout);
+      }
+      index += 1;
+    }
+    layouts
+  }
+
+  /// Name of the channel at `index`, e.g. `FL`.
+  pub fn get_channel_name(&self, index: i32) -> Option<String> {
+    unsafe {
+      let channel = av_channel_layout_extract_channel(self.mask, index);
+      if channel == 0 {
+        return None;
+      }
+      Some(tools::to_string(av_get_channel_name(channel)))
+    }
+  }
+
+  /// Index of a named channel, e.g. `LFE`.
+  pub fn get_channel_index(&self, name: &str) -> Option<i32> {
+    let channel = channel_from_name(name)?;
+    let index = unsafe { av_get_channel_layout_channel_index(self.mask, channel) };
+    if index < 0 {
+      None
+    } else {
+      Some(index)
+    }
+  }
+
+  fn describe(&self) -> String {
+    let mut buffer = [0; 128];
+    unsafe {
+      av_get_channel_layout_string(
+        buffer.as_mut_ptr(),
+        buffer.len() as i32,
+        self.nb_channels,
+        self.mask,
+      );
+    }
+    tools::to_string(buffer.as_ptr())
+  }
+
+  fn parse(name: &str) -> Option<Self> {
+    let name = CString::new(name).ok()?;
+    let mask = unsafe { av_get_channel_layout(name.as_ptr()) };
+    Self::from_mask(mask).ok()
+  }
+}
+
+#[cfg(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+impl Clone for ChannelLayout {
+  fn clone(&self) -> Self {
+    unsafe { Self::copy_from(&self.layout) }
+  }
+}
+
+#[cfg(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+impl Drop for ChannelLayout {
+  fn drop(&mut self) {
+    unsafe {
+      av_channel_layout_uninit(&mut self.layout);
+    }
+  }
+}
+
+#[cfg(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+impl PartialEq for ChannelLayout {
+  fn eq(&self, other: &Self) -> bool {
+    unsafe { av_channel_layout_compare(&self.layout, &other.layout) == 0 }
+  }
+}
+
+#[cfg(not(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+impl PartialEq for ChannelLayout {
+  fn eq(&self, other: &Self) -> bool {
+    self.mask == other.mask && self.nb_channels == other.nb_channels
+  }
+}
+
+#[cfg(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+fn channel_from_name(name: &str) -> Option<AVChannel> {
+  let name = CString::new(name).ok()?;
+  let channel = unsafe { av_channel_from_string(name.as_ptr()) };
+  if channel == AVChannel::AV_CHAN_NONE {
+    None
+  } else {
+    Some(channel)
+  }
+}
+
+#[cfg(not(any(ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+fn channel_from_name(name: &str) -> Option<u64> {
+  let name = CString::new(name).ok()?;
+  let channel = unsafe { av_get_channel_layout(name.as_ptr()) };
+  if channel.count_ones() == 1 {
+    Some(channel)
+  } else {
+    None
+  }
+}
+
+impl ChannelLayout {
+  /// Names of the channels, in order.
+  pub fn get_channel_names(&self) -> Vec<String> {
+    (0..self.get_nb_channels())
+      .filter_map(|index| self.get_channel_name(index))
+      .collect()
+  }
+
+  /// Whether an encoder accepts this layout.
+  ///
+  /// # Safety
+  /// `codec` must be null or point to a valid codec.
+  pub unsafe fn is_supported_by(&self, codec: *const AVCodec) -> bool {
+    let layouts = Self::get_codec_layouts(codec);
+    layouts.is_empty() || layouts.contains(self)
+  }
+}
+
+impl Default for ChannelLayout {
+  fn default() -> Self {
+    Self::default_for(2)
+  }
+}
+
+/// Parse a layout name (`stereo`, `5.1(side)`), a channel list (`FL+FR+LFE`)
+/// or a channel mask.
+impl FromStr for ChannelLayout {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::parse(s).ok_or_else(|| format!("'{s}' is not a valid value for ChannelLayout"))
+  }
+}
+
+impl TryFrom<String> for ChannelLayout {
+  type Error = String;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+/// Format as FFmpeg describes the layout, which can be parsed back.
+impl fmt::Display for ChannelLayout {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.describe())
+  }
+}
+
+impl fmt::Debug for ChannelLayout {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ChannelLayout({})", self.describe())
+  }
+}
+
+#[test]
+fn channel_layout() {
+  let layout: ChannelLayout = "stereo".parse().unwrap();
+  assert_eq!(layout.get_nb_channels(), 2);
+  assert_eq!(layout.get_mask(), Some(AV_CH_LAYOUT_STEREO));
+  assert_eq!(layout.to_string(), "stereo");
+  assert_eq!(layout, ChannelLayout::default());
+
+  let layout = ChannelLayout::from_mask(AV_CH_LAYOUT_5POINT1).unwrap();
+  assert_eq!(layout.get_nb_channels(), 6);
+  assert_eq!(layout.to_string().parse::<ChannelLayout>().unwrap(), layout);
+
+  assert!("not a layout".parse::<ChannelLayout>().is_err());
+
+  let layout: ChannelLayout = "5.1(side)".parse().unwrap();
+  assert_eq!(layout.get_channel_index("LFE"), Some(3));
+  assert_eq!(layout.get_channel_name(4).as_deref(), Some("SL"));
+  assert_eq!(layout.get_channel_index("BL"), None);
+  assert_eq!(
+    layout.get_channel_names(),
+    vec!["FL", "FR", "FC", "LFE", "SL", "SR"]
+  );
+
+  let standard_layouts = ChannelLayout::get_standard_layouts();
+  assert!(standard_layouts.contains(&"7.1(wide)".parse().unwrap()));
+
+  assert!(ChannelLayout::from_channels(&["FL", "XX"]).is_err());
+  let layout = ChannelLayout::from_channels(&["FL", "FR", "LFE"]).unwrap();
+  assert_eq!(layout.get_nb_channels(), 3);
+  assert_eq!(layout.get_channel_index("LFE"), Some(2));
+}
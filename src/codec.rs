@@ -0,0 +1,150 @@
+use crate::{
+  channel_layout::ChannelLayout,
+  order::output::{PixelFormat, SampleFormat},
+  tools,
+};
+use ffmpeg_sys_next::*;
+use std::convert::TryFrom;
+
+/// Marks the end of the profile list of a codec.
+#[cfg(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+const PROFILE_UNKNOWN: i32 = AV_PROFILE_UNKNOWN;
+#[cfg(not(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+const PROFILE_UNKNOWN: i32 = FF_PROFILE_UNKNOWN;
+
+/// Capabilities of an encoder: the formats, rates, layouts and profiles it accepts.
+/// Empty lists mean the codec does not restrict them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Codec {
+  pub codec: *const AVCodec,
+}
+
+impl Codec {
+  pub fn find_encoder(name: &str) -> Result<Self, String> {
+    let codec = tools::get_codec(name);
+    if codec.is_null() {
+      return Err(format!("Unable to found codec {name}"));
+    }
+    Ok(Codec { codec })
+  }
+
+  pub fn get_name(&self) -> String {
+    unsafe { tools::to_string((*self.codec).name) }
+  }
+
+  pub fn get_long_name(&self) -> String {
+    unsafe { tools::to_string((*self.codec).long_name) }
+  }
+
+  pub fn get_pixel_formats(&self) -> Vec<PixelFormat> {
+    let mut formats = vec![];
+    unsafe {
+      let mut format = (*self.codec).pix_fmts;
+      while !format.is_null() && *format != AVPixelFormat::AV_PIX_FMT_NONE {
+        if let Ok(pixel_format) = PixelFormat::try_from(*format as i32) {
+          formats.push(pixel_format);
+        }
+        format = format.add(1);
+      }
+    }
+    formats
+  }
+
+  pub fn get_sample_formats(&self) -> Vec<SampleFormat> {
+    let mut formats = vec![];
+    unsafe {
+      let mut format = (*self.codec).sample_fmts;
+      while !format.is_null() && *format != AVSampleFormat::AV_SAMPLE_FMT_NONE {
+        if let Ok(sample_format) = SampleFormat::try_from(*format as i32) {
+          formats.push(sample_format);
+        }
+        format = format.add(1);
+      }
+    }
+    formats
+  }
+
+  pub fn get_sample_rates(&self) -> Vec<i32> {
+    let mut sample_rates = vec![];
+    unsafe {
+      let mut sample_rate = (*self.codec).supported_samplerates;
+      while !sample_rate.is_null() && *sample_rate != 0 {
+        sample_rates.push(*sample_rate);
+        sample_rate = sample_rate.add(1);
+      }
+    }
+    sample_rates
+  }
+
+  pub fn get_channel_layouts(&self) -> Vec<ChannelLayout> {
+    unsafe { ChannelLayout::get_codec_layouts(self.codec) }
+  }
+
+  pub fn get_profiles(&self) -> Vec<String> {
+    let mut profiles = vec![];
+    unsafe {
+      let mut profile = (*self.codec).profiles;
+      while !profile.is_null() && (*profile).profile != PROFILE_UNKNOWN {
+        profiles.push(tools::to_string((*profile).name));
+        profile = profile.add(1);
+      }
+    }
+    profiles
+  }
+
+  pub fn supports_pixel_format(&self, pixel_format: &PixelFormat) -> bool {
+    let formats = self.get_pixel_formats();
+    formats.is_empty() || formats.contains(pixel_format)
+  }
+
+  pub fn supports_sample_format(&self, sample_format: &SampleFormat) -> bool {
+    let formats = self.get_sample_formats();
+    formats.is_empty() || formats.contains(sample_format)
+  }
+
+  pub fn supports_sample_rate(&self, sample_rate: i32) -> bool {
+    let sample_rates = self.get_sample_rates();
+    sample_rates.is_empty() || sample_rates.contains(&sample_rate)
+  }
+}
+
+/// Join the values for error messages and logs.
+pub(crate) fn join<T: ToString>(values: &[T]) -> String {
+  values
+    .iter()
+    .map(|value| value.to_string())
+    .collect::<Vec<String>>()
+    .join(", ")
+}
+
+#[test]
+fn encoder_capabilities() {
+  assert!(Codec::find_encoder("not_an_encoder").is_err());
+
+  let codec = Codec::find_encoder("pcm_s16le").unwrap();
+  assert_eq!(codec.get_name(), "pcm_s16le");
+  assert_eq!(
+    codec.get_sample_formats(),
+    vec!["s16".parse::<SampleFormat>().unwrap()]
+  );
+  assert!(codec.supports_sample_format(&"s16".parse().unwrap()));
+  assert!(!codec.supports_sample_format(&"flt".parse().unwrap()));
+  // no list of sample rates means any of them
+  assert!(codec.get_sample_rates().is_empty());
+  assert!(codec.supports_sample_rate(44_100));
+  assert!(codec.get_pixel_formats().is_empty());
+  assert!(codec.supports_pixel_format(&"yuv420p".parse().unwrap()));
+
+  let codec = Codec::find_encoder("mp2").unwrap();
+  assert!(codec.supports_sample_rate(48_000));
+  assert!(!codec.supports_sample_rate(96_000));
+
+  let codec = Codec::find_encoder("mpeg2video").unwrap();
+  assert!(codec.supports_pixel_format(&"yuv422p".parse().unwrap()));
+  assert!(!codec.supports_pixel_format(&"rgb24".parse().unwrap()));
+
+  let codec = Codec::find_encoder("prores_ks").unwrap();
+  let profiles = codec.get_profiles();
+  assert!(profiles.contains(&"HQ".to_string()));
+  assert!(profiles.contains(&"4444".to_string()));
+}
@@ -1,8 +1,12 @@
-use crate::tools;
+use crate::{
+  order::output::{PixelFormat, SampleFormat},
+  tools,
+  tools::rational::Rational,
+};
 use ffmpeg_sys_next::AVOptionType::*;
 use ffmpeg_sys_next::*;
 use libc::c_char;
-use std::{ffi::CString, fmt, ptr::null_mut};
+use std::{convert::TryFrom, ffi::CString, fmt, ptr::null_mut};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Filter {
@@ -57,6 +61,41 @@ impl Filter {
     }
   }
 
+  /// Time base of the frames returned by a configured buffer sink.
+  pub fn get_time_base(&self) -> Rational {
+    unsafe {
+      let time_base = av_buffersink_get_time_base(self.context);
+      Rational::new(time_base.num, time_base.den)
+    }
+  }
+
+  /// Negotiated format of a configured buffer sink, e.g. `yuv420p 1920x1080, time base 1/25`.
+  pub fn describe_sink(&self) -> String {
+    unsafe {
+      let format = av_buffersink_get_format(self.context);
+      let time_base = self.get_time_base();
+      match av_buffersink_get_type(self.context) {
+        AVMediaType::AVMEDIA_TYPE_VIDEO => format!(
+          "{} {}x{}, time base {}/{}",
+          PixelFormat::try_from(format).map_or("none".to_string(), |format| format.to_string()),
+          av_buffersink_get_w(self.context),
+          av_buffersink_get_h(self.context),
+          time_base.num,
+          time_base.den
+        ),
+        AVMediaType::AVMEDIA_TYPE_AUDIO => format!(
+          "{} {} Hz {} channels, time base {}/{}",
+          SampleFormat::try_from(format).map_or("none".to_string(), |format| format.to_string()),
+          av_buffersink_get_sample_rate(self.context),
+          av_buffersink_get_channels(self.context),
+          time_base.num,
+          time_base.den
+        ),
+        _ => format!("time base {}/{}", time_base.num, time_base.den),
+      }
+    }
+  }
+
   pub fn init(&self) -> Result<(), String> {
     unsafe {
       check_result!(avfilter_init_str(self.context, null_mut()));
@@ -1,14 +1,33 @@
 use crate::{
-  audio_decoder::AudioDecoder, filter::Filter, frame::Frame, order::*, tools,
+  audio_decoder::AudioDecoder,
+  channel_layout::ChannelLayout,
+  filter::Filter,
+  frame::Frame,
+  order::{
+    output::{PixelFormat, SampleFormat},
+    subtitle_timing::Segment,
+    *,
+  },
+  subtitle::{Subtitle, SubtitleRect},
+  subtitle_decoder::SubtitleDecoder,
+  tools,
+  tools::rational::Rational,
   video_decoder::VideoDecoder,
 };
 use ffmpeg_sys_next::*;
 use libc::c_void;
-use std::{fmt, ptr::null_mut};
+use std::{
+  collections::HashMap,
+  ffi::CString,
+  fmt,
+  ptr::{null_mut, write_bytes},
+};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Deserialize, PartialEq, Eq)]
 pub enum GraphKind {
+  #[serde(rename = "video")]
   Video,
+  #[serde(rename = "audio")]
   Audio,
 }
 
@@ -20,6 +39,31 @@ pub struct FilterGraph {
   pub audio_outputs: Vec<Filter>,
   pub video_inputs: Vec<Filter>,
   pub video_outputs: Vec<Filter>,
+  pub subtitle_inputs: Vec<SubtitleInput>,
+  /// Format conversion filters in front of outputs, with the label of their output.
+  /// The first one of an output is the one fed by the graph.
+  pub output_format_filters: Vec<(String, Filter)>,
+  /// Labels of frames produced outside of the graph, with the label of the output they come from.
+  pub output_aliases: Vec<(String, String)>,
+  /// Index of the audio and video inputs by label, to route frames without scanning inputs.
+  audio_input_indexes: HashMap<String, usize>,
+  video_input_indexes: HashMap<String, usize>,
+  /// Frame read from the sinks, kept for the next read when they have nothing.
+  sink_frame: *mut AVFrame,
+}
+
+/// Bitmap subtitles rendered as RGBA video frames, timed on a video input.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SubtitleInput {
+  pub filter: Filter,
+  pub video_label: String,
+  pub width: i32,
+  pub height: i32,
+  video_time_base: Rational,
+  /// Rendered subtitle currently displayed, null when nothing is displayed.
+  canvas: *mut AVFrame,
+  end: i64,
+  last_pts: i64,
 }
 
 impl Default for FilterGraph {
@@ -43,6 +87,12 @@ impl FilterGraph {
         audio_outputs: vec![],
         video_inputs: vec![],
         video_outputs: vec![],
+        subtitle_inputs: vec![],
+        output_format_filters: vec![],
+        output_aliases: vec![],
+        audio_input_indexes: HashMap::new(),
+        video_input_indexes: HashMap::new(),
+        sink_frame: null_mut(),
       })
     }
   }
@@ -52,29 +102,43 @@ impl FilterGraph {
     label: &str,
     video_decoder: &VideoDecoder,
   ) -> Result<(), String> {
-    let buffer = unsafe { Filter::new_with_label(self.graph, "buffer", label)? };
-
-    let width = ParameterValue::Int64(i64::from(video_decoder.get_width()));
-    width.set("width", buffer.context as *mut c_void)?;
-
-    let height = ParameterValue::Int64(i64::from(video_decoder.get_height()));
-    height.set("height", buffer.context as *mut c_void)?;
-
-    let mut tbc = video_decoder.get_time_base();
-    if tbc.num == 0 {
-      tbc.num = 1;
-      tbc.den = 25;
+    let mut time_base = video_decoder.get_time_base();
+    if time_base.num == 0 {
+      time_base = Rational::new(1, 25);
     }
-    let time_base = ParameterValue::Rational(tbc);
-    time_base.set("time_base", buffer.context as *mut c_void)?;
+    self.add_video_input(
+      label,
+      video_decoder.get_width(),
+      video_decoder.get_height(),
+      &video_decoder.get_pix_fmt_name(),
+      time_base,
+      video_decoder.get_aspect_ratio(),
+    )
+  }
 
-    let pixel_aspect = ParameterValue::Rational(video_decoder.get_aspect_ratio());
-    pixel_aspect.set("pixel_aspect", buffer.context as *mut c_void)?;
+  /// Add a video input receiving frames of the given format.
+  pub fn add_video_input(
+    &mut self,
+    label: &str,
+    width: i32,
+    height: i32,
+    pixel_format: &str,
+    time_base: Rational,
+    pixel_aspect: Rational,
+  ) -> Result<(), String> {
+    let buffer = unsafe { Filter::new_with_label(self.graph, "buffer", label)? };
 
-    let pix_fmt = ParameterValue::String(video_decoder.get_pix_fmt_name());
-    pix_fmt.set("pix_fmt", buffer.context as *mut c_void)?;
+    ParameterValue::Int64(i64::from(width)).set("width", buffer.context as *mut c_void)?;
+    ParameterValue::Int64(i64::from(height)).set("height", buffer.context as *mut c_void)?;
+    ParameterValue::Rational(time_base).set("time_base", buffer.context as *mut c_void)?;
+    ParameterValue::Rational(pixel_aspect).set("pixel_aspect", buffer.context as *mut c_void)?;
+    ParameterValue::String(pixel_format.to_string())
+      .set("pix_fmt", buffer.context as *mut c_void)?;
     buffer.init()?;
 
+    self
+      .video_input_indexes
+      .insert(label.to_string(), self.video_inputs.len());
     self.video_inputs.push(buffer);
     Ok(())
   }
@@ -87,15 +151,15 @@ impl FilterGraph {
     let abuffer = unsafe { Filter::new_with_label(self.graph, "abuffer", label)? };
 
     let layout = audio_decoder.get_channel_layout();
-    if layout > 0 {
-      let channel_layout = ParameterValue::ChannelLayout(layout);
+    if layout.get_mask().is_some() {
+      let channel_layout = ParameterValue::String(layout.to_string());
       channel_layout.set("channel_layout", abuffer.context as *mut c_void)?;
     }
 
     let sample_rate = ParameterValue::Int64(i64::from(audio_decoder.get_sample_rate()));
     sample_rate.set("sample_rate", abuffer.context as *mut c_void)?;
 
-    let channels = ParameterValue::Int64(i64::from(audio_decoder.get_nb_channels()));
+    let channels = ParameterValue::Int64(i64::from(layout.get_nb_channels()));
     channels.set("channels", abuffer.context as *mut c_void)?;
 
     let sample_fmt = ParameterValue::String(audio_decoder.get_sample_fmt_name());
@@ -103,10 +167,97 @@ impl FilterGraph {
 
     abuffer.init()?;
 
+    self
+      .audio_input_indexes
+      .insert(label.to_string(), self.audio_inputs.len());
     self.audio_inputs.push(abuffer);
     Ok(())
   }
 
+  /// Add a canvas source for bitmap subtitles, with the size of the subtitle stream
+  /// (or the video if not specified) and a millisecond time base.
+  pub fn add_input_from_subtitle_decoder(
+    &mut self,
+    label: &str,
+    subtitle_decoder: &SubtitleDecoder,
+    video_label: &str,
+    video_decoder: &VideoDecoder,
+  ) -> Result<(), String> {
+    let (width, height) = if subtitle_decoder.get_width() > 0 && subtitle_decoder.get_height() > 0 {
+      (subtitle_decoder.get_width(), subtitle_decoder.get_height())
+    } else {
+      (video_decoder.get_width(), video_decoder.get_height())
+    };
+    let mut video_time_base = video_decoder.get_time_base();
+    if video_time_base.num == 0 {
+      video_time_base = Rational::new(1, 25);
+    }
+    self.add_subtitle_input(label, width, height, video_label, video_time_base)
+  }
+
+  /// Add an input rendering bitmap subtitles on a transparent canvas, repeated for each
+  /// frame of the video input labeled `video_label`.
+  pub fn add_subtitle_input(
+    &mut self,
+    label: &str,
+    width: i32,
+    height: i32,
+    video_label: &str,
+    video_time_base: Rational,
+  ) -> Result<(), String> {
+    let buffer = unsafe { Filter::new_with_label(self.graph, "buffer", label)? };
+
+    ParameterValue::Int64(i64::from(width)).set("width", buffer.context as *mut c_void)?;
+    ParameterValue::Int64(i64::from(height)).set("height", buffer.context as *mut c_void)?;
+    ParameterValue::Rational(Rational::new(1, 1000))
+      .set("time_base", buffer.context as *mut c_void)?;
+    ParameterValue::Rational(Rational::new(1, 1))
+      .set("pixel_aspect", buffer.context as *mut c_void)?;
+    ParameterValue::String("rgba".to_string()).set("pix_fmt", buffer.context as *mut c_void)?;
+    buffer.init()?;
+
+    self.subtitle_inputs.push(SubtitleInput {
+      filter: buffer,
+      video_label: video_label.to_string(),
+      width,
+      height,
+      video_time_base,
+      canvas: null_mut(),
+      end: 0,
+      last_pts: -1,
+    });
+    Ok(())
+  }
+
+  /// Render the subtitle on the canvas of its subtitle input, if any.
+  pub fn push_subtitle(&mut self, subtitle: &Subtitle) -> Result<(), String> {
+    for input in &mut self.subtitle_inputs {
+      if let Some(label) = &subtitle.name {
+        if input.filter.get_label() == *label {
+          // the previous subtitle may end before this one starts
+          if input.end < subtitle.start {
+            input.clear_ended(subtitle.start)?;
+          }
+          input.render(subtitle)?;
+          input.send(subtitle.start)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Clear the displayed subtitles and close the subtitle inputs, once all the video
+  /// frames are pushed.
+  pub fn close_subtitle_inputs(&mut self) -> Result<(), String> {
+    for input in &mut self.subtitle_inputs {
+      input.clear_ended(i64::MAX)?;
+      unsafe {
+        check_result!(av_buffersrc_add_frame(input.filter.context, null_mut()));
+      }
+    }
+    Ok(())
+  }
+
   pub fn add_video_output(&mut self, label: &str) -> Result<(), String> {
     let buffersink = unsafe { Filter::new_with_label(self.graph, "buffersink", label)? };
     buffersink.init()?;
@@ -123,6 +274,222 @@ impl FilterGraph {
     Ok(())
   }
 
+  /// Frames of `label` are produced outside of the graph from the frames of the `source`
+  /// output, like by an order stage, and share its format and time base.
+  pub fn add_output_alias(&mut self, label: &str, source: &str) {
+    self
+      .output_aliases
+      .push((label.to_string(), source.to_string()));
+  }
+
+  pub fn is_output_alias(&self, label: &str) -> bool {
+    self.output_aliases.iter().any(|(alias, _)| alias == label)
+  }
+
+  /// Label of the graph output producing the frames of `label`, through chained aliases.
+  fn get_source_label<'a>(&'a self, label: &'a str) -> &'a str {
+    match self.output_aliases.iter().find(|(alias, _)| alias == label) {
+      Some((_, source)) => self.get_source_label(source),
+      None => label,
+    }
+  }
+
+  fn get_output(&self, label: &str) -> Option<&Filter> {
+    if let Some((_, source)) = self.output_aliases.iter().find(|(alias, _)| alias == label) {
+      return self.get_output(source);
+    }
+    self
+      .audio_outputs
+      .iter()
+      .chain(self.video_outputs.iter())
+      .find(|output| output.get_label() == label)
+  }
+
+  /// Time base of an output, once the graph is validated.
+  pub fn get_output_time_base(&self, label: &str) -> Option<Rational> {
+    self.get_output(label).map(|output| output.get_time_base())
+  }
+
+  /// Intervals of the source kept by the `trim` filters in front of a video output, in
+  /// milliseconds and in their order in the output, once the graph is validated.
+  /// None when the frames are not trimmed.
+  pub fn get_cut_segments(&self, label: &str) -> Result<Option<Vec<Segment>>, String> {
+    let output = self
+      .get_output(label)
+      .ok_or_else(|| format!("Unable to find the {label} output"))?;
+    unsafe { get_cut_segments(output.context, 0) }
+  }
+
+  /// Format of the frames of an output, once the graph is validated.
+  pub fn describe_output(&self, label: &str) -> Option<String> {
+    self.get_output(label).map(|output| output.describe_sink())
+  }
+
+  /// Insert a `format` or `aformat` filter in front of an output, so the frames are
+  /// converted to what its encoder accepts. Nothing is converted when formats already match.
+  /// Frames of an alias are converted on its source output, before going through the stages.
+  pub fn set_output_format(
+    &mut self,
+    label: &str,
+    filter_name: &str,
+    parameters: &HashMap<String, ParameterValue>,
+  ) -> Result<(), String> {
+    let label = self.get_source_label(label).to_string();
+    let label = label.as_str();
+    let filter_label = format!("{label}_{filter_name}");
+    if self
+      .output_format_filters
+      .iter()
+      .any(|(_, filter)| filter.get_label() == filter_label)
+    {
+      return Err(format!(
+        "The {label} frames already go through a {filter_name} filter for another encoder"
+      ));
+    }
+    let output = self.get_output_head(label)?;
+
+    let filter = unsafe { Filter::new_with_label(self.graph, filter_name, &filter_label)? };
+    set_parameters(filter.context as *mut c_void, parameters)?;
+    filter.init()?;
+    unsafe {
+      check_result!(avfilter_link(filter.context, 0, output, 0));
+    }
+
+    self
+      .output_format_filters
+      .insert(0, (label.to_string(), filter));
+    Ok(())
+  }
+
+  /// Feed outputs with silence following the timing of the `source` output, like the audio
+  /// tracks completing a delivery. The frames of `source` drive them, as sources are only
+  /// pulled by the frames pushed to the graph.
+  pub fn add_silent_outputs(&mut self, source: &str, labels: &[String]) -> Result<(), String> {
+    let source_head = self.get_output_head(source)?;
+    let mut split_parameters = HashMap::new();
+    split_parameters.insert(
+      "outputs".to_string(),
+      ParameterValue::Int64(labels.len() as i64 + 1),
+    );
+    let split =
+      unsafe { Filter::new_with_label(self.graph, "asplit", &format!("{source}_asplit"))? };
+    set_parameters(split.context as *mut c_void, &split_parameters)?;
+    split.init()?;
+    unsafe {
+      check_result!(avfilter_link(split.context, 0, source_head, 0));
+    }
+
+    let mut mute_parameters = HashMap::new();
+    mute_parameters.insert("volume".to_string(), ParameterValue::Float(0.0));
+    for (index, label) in labels.iter().enumerate() {
+      let output = self.get_output_head(label)?;
+      let mute = unsafe { Filter::new_with_label(self.graph, "volume", &format!("{label}_mute"))? };
+      set_parameters(mute.context as *mut c_void, &mute_parameters)?;
+      mute.init()?;
+      unsafe {
+        check_result!(avfilter_link(
+          split.context,
+          index as u32 + 1,
+          mute.context,
+          0
+        ));
+        check_result!(avfilter_link(mute.context, 0, output, 0));
+      }
+      self.output_format_filters.insert(0, (label.clone(), mute));
+    }
+
+    self
+      .output_format_filters
+      .insert(0, (source.to_string(), split));
+    Ok(())
+  }
+
+  /// First filter fed for an output, its format filters included.
+  fn get_output_head(&self, label: &str) -> Result<*mut AVFilterContext, String> {
+    if let Some((_, filter)) = self
+      .output_format_filters
+      .iter()
+      .find(|(output_label, _)| output_label == label)
+    {
+      return Ok(filter.context);
+    }
+    self
+      .get_output(label)
+      .map(|output| output.context)
+      .ok_or_else(|| format!("Unable to find the {label} output"))
+  }
+
+  /// Add the filters of a libavfilter graph description, like
+  /// `[audio]aformat=sample_fmts=s32[output]`. Its labels bind to the inputs and outputs
+  /// of the graph which are not connected yet, and all of them must end up connected.
+  pub fn parse(&mut self, description: &str) -> Result<(), String> {
+    let sources: Vec<&Filter> = self
+      .audio_inputs
+      .iter()
+      .chain(self.video_inputs.iter())
+      .chain(self.subtitle_inputs.iter().map(|input| &input.filter))
+      .collect();
+    let sinks: Vec<(String, *mut AVFilterContext)> = self
+      .audio_outputs
+      .iter()
+      .chain(self.video_outputs.iter())
+      .map(|output| {
+        let label = output.get_label();
+        let context = self
+          .output_format_filters
+          .iter()
+          .find(|(output_label, _)| *output_label == label)
+          .map_or(output.context, |(_, format_filter)| format_filter.context);
+        (label, context)
+      })
+      .collect();
+
+    let description =
+      CString::new(description).map_err(|_| "Invalid filter graph description".to_string())?;
+
+    unsafe {
+      let mut outputs = null_mut();
+      for source in sources {
+        if (*source.context).nb_outputs > 0 && (*(*source.context).outputs).is_null() {
+          outputs = new_in_out(&source.get_label(), source.context, outputs)?;
+        }
+      }
+      let mut inputs = null_mut();
+      for (label, context) in sinks {
+        if (*context).nb_inputs > 0 && (*(*context).inputs).is_null() {
+          inputs = match new_in_out(&label, context, inputs) {
+            Ok(inputs) => inputs,
+            Err(msg) => {
+              avfilter_inout_free(&mut outputs);
+              return Err(msg);
+            }
+          };
+        }
+      }
+
+      let result = avfilter_graph_parse_ptr(
+        self.graph,
+        description.as_ptr(),
+        &mut inputs,
+        &mut outputs,
+        null_mut(),
+      );
+      let mut unconnected = get_in_out_names(inputs);
+      unconnected.extend(get_in_out_names(outputs));
+      avfilter_inout_free(&mut inputs);
+      avfilter_inout_free(&mut outputs);
+      check_result!(result);
+
+      if !unconnected.is_empty() {
+        return Err(format!(
+          "Unconnected labels in the filter graph: {}",
+          unconnected.join(", ")
+        ));
+      }
+    }
+    Ok(())
+  }
+
   pub fn add_filter(&self, args: &filter::Filter) -> Result<Filter, String> {
     let filter = if let Some(ref label) = args.label {
       unsafe { Filter::new_with_label(self.graph, &args.name, label)? }
@@ -189,6 +556,20 @@ impl FilterGraph {
       }
     }
 
+    for subtitle_input in &self.subtitle_inputs {
+      if subtitle_input.filter.get_label() == label {
+        unsafe {
+          check_result!(avfilter_link(
+            subtitle_input.filter.context,
+            src_index,
+            dst.context,
+            dst_index
+          ));
+        }
+        return Ok(());
+      }
+    }
+
     Err("Unable to connect".to_string())
   }
 
@@ -199,6 +580,20 @@ impl FilterGraph {
     label: &str,
     dst_index: u32,
   ) -> Result<(), String> {
+    for (output_label, format_filter) in &self.output_format_filters {
+      if output_label == label {
+        unsafe {
+          check_result!(avfilter_link(
+            src.context,
+            src_index,
+            format_filter.context,
+            dst_index
+          ));
+        }
+        return Ok(());
+      }
+    }
+
     for audio_output in &self.audio_outputs {
       if audio_output.get_label() == label {
         unsafe {
@@ -230,6 +625,73 @@ impl FilterGraph {
     Err("Unable to connect".to_string())
   }
 
+  /// Send a command to the filters matching the target (label, filter name or `all`)
+  /// and return the response of the filter.
+  pub fn send_command(
+    &self,
+    target: &str,
+    command: &str,
+    argument: &str,
+  ) -> Result<String, String> {
+    let (target, command, argument) = command_strings(target, command, argument)?;
+    let mut response = [0; 1024];
+    unsafe {
+      check_result!(avfilter_graph_send_command(
+        self.graph,
+        target.as_ptr(),
+        command.as_ptr(),
+        argument.as_ptr(),
+        response.as_mut_ptr(),
+        response.len() as i32,
+        0
+      ));
+      Ok(tools::to_string(response.as_ptr()))
+    }
+  }
+
+  /// Queue a command for the filters matching the target, run once they process
+  /// a frame at `time` seconds or later.
+  pub fn queue_command(
+    &self,
+    target: &str,
+    command: &str,
+    argument: &str,
+    time: f64,
+  ) -> Result<(), String> {
+    // queued commands are only matched against the filters once they run
+    if !self.has_command_target(target) {
+      return Err(format!(
+        "No filter of the graph matches the command target {target}"
+      ));
+    }
+    let (target, command, argument) = command_strings(target, command, argument)?;
+    unsafe {
+      check_result!(avfilter_graph_queue_command(
+        self.graph,
+        target.as_ptr(),
+        command.as_ptr(),
+        argument.as_ptr(),
+        0,
+        time
+      ));
+    }
+    Ok(())
+  }
+
+  /// Whether commands to the target reach a filter, matched as libavfilter does.
+  fn has_command_target(&self, target: &str) -> bool {
+    if target == "all" {
+      return true;
+    }
+    unsafe {
+      let filters = tools::from_buf_raw((*self.graph).filters, (*self.graph).nb_filters as usize);
+      filters.iter().any(|filter| {
+        Filter { context: *filter }.get_label() == target
+          || tools::to_string((*(**filter).filter).name) == target
+      })
+    }
+  }
+
   pub fn validate(&mut self) -> Result<(), String> {
     unsafe {
       check_result!(avfilter_graph_config(self.graph, null_mut()));
@@ -237,86 +699,470 @@ impl FilterGraph {
     }
   }
 
+  /// Graphviz DOT export of the filters and how their pads are linked, with the negotiated
+  /// formats once the graph is validated. Pads which are not linked show as dashed edges.
+  pub fn to_dot(&self) -> String {
+    let mut dot = "digraph filter_graph {\n  rankdir=LR;\n  node [shape=box];\n".to_string();
+    unsafe {
+      let filters = tools::from_buf_raw((*self.graph).filters, (*self.graph).nb_filters as usize);
+      for (index, filter) in filters.iter().enumerate() {
+        let name = tools::to_string((*(**filter).filter).name);
+        let label = Filter { context: *filter }.get_label();
+        dot += &format!(
+          "  filter{index} [label=\"{}\\n{}\"];\n",
+          escape_dot(&name),
+          escape_dot(&label)
+        );
+      }
+      for (index, filter) in filters.iter().enumerate() {
+        let inputs = tools::from_buf_raw((**filter).inputs, (**filter).nb_inputs as usize);
+        for (pad, link) in inputs.iter().enumerate() {
+          if link.is_null() {
+            let pad_name =
+              tools::to_string(avfilter_pad_get_name((**filter).input_pads, pad as i32));
+            dot += &format!("  filter{index}_in{pad} [shape=point];\n");
+            dot += &format!(
+              "  filter{index}_in{pad} -> filter{index} [style=dashed, label=\"{} not connected\"];\n",
+              escape_dot(&pad_name)
+            );
+          }
+        }
+        let outputs = tools::from_buf_raw((**filter).outputs, (**filter).nb_outputs as usize);
+        for (pad, link) in outputs.iter().enumerate() {
+          let pad_name =
+            tools::to_string(avfilter_pad_get_name((**filter).output_pads, pad as i32));
+          if link.is_null() {
+            dot += &format!("  filter{index}_out{pad} [shape=point];\n");
+            dot += &format!(
+              "  filter{index} -> filter{index}_out{pad} [style=dashed, label=\"{} not connected\"];\n",
+              escape_dot(&pad_name)
+            );
+            continue;
+          }
+          let Some(destination) = filters.iter().position(|filter| *filter == (**link).dst) else {
+            continue;
+          };
+          let destination_pad = tools::to_string(avfilter_pad_get_name((**link).dstpad, 0));
+          dot += &format!(
+            "  filter{index} -> filter{destination} [label=\"{}:{}\\n{}\"];\n",
+            escape_dot(&pad_name),
+            escape_dot(&destination_pad),
+            escape_dot(&describe_link(*link))
+          );
+        }
+      }
+    }
+    dot += "}\n";
+    dot
+  }
+
+  /// Textual dump of the graph by libavfilter. All the pads of the filters must be linked.
+  pub fn dump(&self) -> Result<String, String> {
+    unsafe {
+      let filters = tools::from_buf_raw((*self.graph).filters, (*self.graph).nb_filters as usize);
+      for filter in filters {
+        let links = tools::from_buf_raw((*filter).inputs, (*filter).nb_inputs as usize)
+          .into_iter()
+          .chain(tools::from_buf_raw(
+            (*filter).outputs,
+            (*filter).nb_outputs as usize,
+          ));
+        for link in links {
+          if link.is_null() {
+            return Err(format!(
+              "Unable to dump the graph, {} has pads which are not connected",
+              Filter { context: filter }.get_label()
+            ));
+          }
+        }
+      }
+
+      let dump = avfilter_graph_dump(self.graph, null_mut());
+      if dump.is_null() {
+        return Err("Unable to dump the graph".to_string());
+      }
+      let text = tools::to_string(dump);
+      av_free(dump as *mut c_void);
+      Ok(text)
+    }
+  }
+
+  /// Push the frames to the inputs matching their label and read the frames of the outputs.
+  /// Inputs keep their own reference of the frames, so the same frames can be pushed to
+  /// several graphs without copying them.
   pub fn process(
-    &self,
+    &mut self,
     in_audio_frames: &[Frame],
     in_video_frames: &[Frame],
   ) -> Result<(Vec<Frame>, Vec<Frame>), String> {
+    let flags = AV_BUFFERSRC_FLAG_KEEP_REF | AV_BUFFERSRC_FLAG_PUSH;
+
+    for frame in in_audio_frames {
+      let index = frame
+        .name
+        .as_ref()
+        .and_then(|label| self.audio_input_indexes.get(label));
+      if let Some(index) = index {
+        unsafe {
+          check_result!(av_buffersrc_add_frame_flags(
+            self.audio_inputs[*index].context,
+            frame.frame,
+            flags
+          ));
+        }
+      }
+    }
+    for frame in in_video_frames {
+      for subtitle_input in &mut self.subtitle_inputs {
+        if frame.name.as_ref() == Some(&subtitle_input.video_label) {
+          subtitle_input.heartbeat(frame.get_pts())?;
+        }
+      }
+      let index = frame
+        .name
+        .as_ref()
+        .and_then(|label| self.video_input_indexes.get(label));
+      if let Some(index) = index {
+        unsafe {
+          check_result!(av_buffersrc_add_frame_flags(
+            self.video_inputs[*index].context,
+            frame.frame,
+            flags
+          ));
+        }
+      }
+    }
+
     let mut output_audio_frames = vec![];
+    for (index, output_filter) in self.audio_outputs.iter().enumerate() {
+      read_sink(
+        output_filter,
+        index,
+        &mut self.sink_frame,
+        &mut output_audio_frames,
+      )?;
+    }
     let mut output_video_frames = vec![];
+    for (index, output_filter) in self.video_outputs.iter().enumerate() {
+      read_sink(
+        output_filter,
+        index,
+        &mut self.sink_frame,
+        &mut output_video_frames,
+      )?;
+    }
 
-    unsafe {
-      for frame in in_audio_frames {
-        for input in &self.audio_inputs {
-          if let Some(label) = &frame.name {
-            if input.get_label() == *label {
-              check_result!(av_buffersrc_add_frame_flags(
-                input.context,
-                av_frame_clone(frame.frame),
-                4
-              ));
-            }
-          }
-        }
+    Ok((output_audio_frames, output_video_frames))
+  }
+}
+
+/// C strings of a command, which must not contain NUL bytes.
+fn command_strings(
+  target: &str,
+  command: &str,
+  argument: &str,
+) -> Result<(CString, CString, CString), String> {
+  let to_c_string = |name: &str, value: &str| {
+    CString::new(value).map_err(|_| format!("Invalid command {name} {}", value.escape_default()))
+  };
+  Ok((
+    to_c_string("target", target)?,
+    to_c_string("name", command)?,
+    to_c_string("argument", argument)?,
+  ))
+}
+
+/// Prepend a filter pad to a list of open inputs or outputs.
+unsafe fn new_in_out(
+  label: &str,
+  context: *mut AVFilterContext,
+  mut next: *mut AVFilterInOut,
+) -> Result<*mut AVFilterInOut, String> {
+  let in_out = avfilter_inout_alloc();
+  if in_out.is_null() {
+    avfilter_inout_free(&mut next);
+    return Err("Unable to allocate filter graph label".to_string());
+  }
+  let name = CString::new(label).unwrap();
+  (*in_out).name = av_strdup(name.as_ptr());
+  (*in_out).filter_ctx = context;
+  (*in_out).pad_idx = 0;
+  (*in_out).next = next;
+  Ok(in_out)
+}
+
+unsafe fn get_in_out_names(mut in_out: *mut AVFilterInOut) -> Vec<String> {
+  let mut names = vec![];
+  while !in_out.is_null() {
+    names.push(tools::to_string((*in_out).name));
+    in_out = (*in_out).next;
+  }
+  names
+}
+
+/// Format of the frames going through a link, when negotiated.
+unsafe fn describe_link(link: *const AVFilterLink) -> String {
+  let format = (*link).format;
+  match (*link).type_ {
+    AVMediaType::AVMEDIA_TYPE_VIDEO => PixelFormat::try_from(format).map_or_else(
+      |_| "video".to_string(),
+      |format| format!("{format} {}x{}", (*link).w, (*link).h),
+    ),
+    AVMediaType::AVMEDIA_TYPE_AUDIO => SampleFormat::try_from(format).map_or_else(
+      |_| "audio".to_string(),
+      |format| {
+        format!(
+          "{format} {} Hz {}",
+          (*link).sample_rate,
+          ChannelLayout::from_filter_link(link)
+        )
+      },
+    ),
+    media_type => tools::to_string(av_get_media_type_string(media_type)),
+  }
+}
+
+fn escape_dot(text: &str) -> String {
+  text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Intervals kept by the trims in front of an output pad of a filter. The segments of a
+/// `concat` filter follow each other, other filters are followed through their first input.
+unsafe fn get_cut_segments(
+  context: *mut AVFilterContext,
+  pad: usize,
+) -> Result<Option<Vec<Segment>>, String> {
+  let inputs = tools::from_buf_raw((*context).inputs, (*context).nb_inputs as usize);
+  let get_input_segments = |index: usize| match inputs.get(index) {
+    Some(link) if !link.is_null() => {
+      let source = (**link).src;
+      let outputs = tools::from_buf_raw((*source).outputs, (*source).nb_outputs as usize);
+      let pad = outputs
+        .iter()
+        .position(|output| output == link)
+        .unwrap_or(0);
+      get_cut_segments(source, pad)
+    }
+    _ => Ok(None),
+  };
+
+  match tools::to_string((*(*context).filter).name).as_str() {
+    "trim" => {
+      if get_input_segments(0)?.is_some() {
+        return Err("Unable to follow the cut of trimmed frames trimmed again".to_string());
       }
-      for frame in in_video_frames {
-        for input in &self.video_inputs {
-          if let Some(label) = &frame.name {
-            if input.get_label() == *label {
-              check_result!(av_buffersrc_add_frame_flags(
-                input.context,
-                av_frame_clone(frame.frame),
-                4
-              ));
-            }
+      Ok(Some(vec![get_trim_segment(context, inputs[0])?]))
+    }
+    "concat" => {
+      let mut segments = vec![];
+      for index in (pad..inputs.len()).step_by((*context).nb_outputs.max(1) as usize) {
+        match get_input_segments(index)? {
+          Some(cut) => segments.extend(cut),
+          None => {
+            return Err(
+              "Unable to follow the cut of concatenated frames which are not trimmed".to_string(),
+            )
           }
         }
       }
+      Ok(Some(segments))
+    }
+    _ => get_input_segments(0),
+  }
+}
 
-      for (index, output_filter) in self.audio_outputs.iter().enumerate() {
-        let mut result = 0;
-        while result >= 0 {
-          let output_frame = av_frame_alloc();
-          result = av_buffersink_get_frame_flags(output_filter.context, output_frame, 2);
-          if result == AVERROR(EAGAIN) || result == AVERROR_EOF {
-            break;
-          } else {
-            check_result!(result);
-          }
-          output_audio_frames.push(Frame {
-            name: Some(output_filter.get_label()),
-            frame: output_frame,
-            index,
-          });
+/// Interval kept by a configured `trim` filter, which merges its time options into
+/// `start_pts` and `end_pts` in the time base of its input.
+unsafe fn get_trim_segment(
+  context: *mut AVFilterContext,
+  input: *mut AVFilterLink,
+) -> Result<Segment, String> {
+  let get_option = |name: &str| -> Result<i64, String> {
+    let key = CString::new(name).unwrap();
+    let mut value = 0;
+    check_result!(av_opt_get_int(
+      context as *mut c_void,
+      key.as_ptr(),
+      AV_OPT_SEARCH_CHILDREN,
+      &mut value
+    ));
+    Ok(value)
+  };
+  if get_option("start_frame")? >= 0 || get_option("end_frame")? != i64::MAX {
+    return Err("Unable to follow the cut of a trim on frame numbers".to_string());
+  }
+  let milliseconds = AVRational { num: 1, den: 1000 };
+  let to_milliseconds = |pts: i64| av_rescale_q(pts, (*input).time_base, milliseconds);
+
+  let start = match get_option("start_pts")? {
+    AV_NOPTS_VALUE => 0,
+    start_pts => to_milliseconds(start_pts),
+  };
+  let mut end = match get_option("end_pts")? {
+    AV_NOPTS_VALUE => i64::MAX,
+    end_pts => to_milliseconds(end_pts),
+  };
+  let duration = get_option("duration")?;
+  if duration > 0 {
+    end = end.min(start + duration / 1000);
+  }
+  Ok(Segment { start, end })
+}
+
+/// Read the available frames of a sink. The frame it is read into is only allocated
+/// when the previous one was returned, as sinks have nothing most of the time.
+fn read_sink(
+  output_filter: &Filter,
+  index: usize,
+  sink_frame: &mut *mut AVFrame,
+  frames: &mut Vec<Frame>,
+) -> Result<(), String> {
+  unsafe {
+    loop {
+      if sink_frame.is_null() {
+        *sink_frame = av_frame_alloc();
+        if sink_frame.is_null() {
+          return Err("Unable to allocate frame".to_string());
         }
       }
+      let result = av_buffersink_get_frame_flags(
+        output_filter.context,
+        *sink_frame,
+        AV_BUFFERSINK_FLAG_NO_REQUEST,
+      );
+      if result == AVERROR(EAGAIN) || result == AVERROR_EOF {
+        return Ok(());
+      }
+      check_result!(result);
+      frames.push(Frame {
+        name: Some(output_filter.get_label()),
+        frame: *sink_frame,
+        index,
+      });
+      *sink_frame = null_mut();
+    }
+  }
+}
 
-      for (index, output_filter) in self.video_outputs.iter().enumerate() {
-        let mut result = 0;
-        while result >= 0 {
-          let output_frame = av_frame_alloc();
-          result = av_buffersink_get_frame_flags(output_filter.context, output_frame, 2);
-          if result == AVERROR(EAGAIN) || result == AVERROR_EOF {
-            break;
-          } else {
-            check_result!(result);
+impl SubtitleInput {
+  fn render(&mut self, subtitle: &Subtitle) -> Result<(), String> {
+    unsafe {
+      let canvas = self.new_frame()?;
+      let linesize = (*canvas).linesize[0];
+      for rect in &subtitle.rects {
+        if let SubtitleRect::Bitmap(bitmap) = rect {
+          for y in 0..bitmap.height {
+            for x in 0..bitmap.width {
+              let (canvas_x, canvas_y) = (bitmap.x + x, bitmap.y + y);
+              if canvas_x < 0 || canvas_y < 0 || canvas_x >= self.width || canvas_y >= self.height {
+                continue;
+              }
+              if let Some(argb) = bitmap.get_pixel(x, y) {
+                let pixel = (*canvas).data[0].offset((canvas_y * linesize + canvas_x * 4) as isize);
+                *pixel = (argb >> 16) as u8;
+                *pixel.offset(1) = (argb >> 8) as u8;
+                *pixel.offset(2) = argb as u8;
+                *pixel.offset(3) = (argb >> 24) as u8;
+              }
+            }
           }
-          output_video_frames.push(Frame {
-            name: Some(output_filter.get_label()),
-            frame: output_frame,
-            index,
-          });
         }
       }
+
+      if !self.canvas.is_null() {
+        av_frame_free(&mut self.canvas);
+      }
+      self.canvas = canvas;
+      self.end = subtitle.end;
     }
+    Ok(())
+  }
 
-    Ok((output_audio_frames, output_video_frames))
+  /// Repeat the displayed subtitle for each video frame, so the overlay never waits
+  /// for the next subtitle.
+  fn heartbeat(&mut self, video_pts: i64) -> Result<(), String> {
+    if video_pts == AV_NOPTS_VALUE {
+      return Ok(());
+    }
+    let pts = unsafe {
+      av_rescale_q(
+        video_pts,
+        self.video_time_base.clone().into(),
+        AVRational { num: 1, den: 1000 },
+      )
+    };
+
+    self.clear_ended(pts)?;
+    self.send(pts)
+  }
+
+  /// Send a transparent frame at the end of the displayed subtitle, once `pts` reaches it.
+  fn clear_ended(&mut self, pts: i64) -> Result<(), String> {
+    if !self.canvas.is_null() && pts >= self.end {
+      unsafe {
+        av_frame_free(&mut self.canvas);
+      }
+      self.send(self.end)?;
+    }
+    Ok(())
+  }
+
+  fn send(&mut self, pts: i64) -> Result<(), String> {
+    if pts <= self.last_pts {
+      return Ok(());
+    }
+    unsafe {
+      let mut frame = if self.canvas.is_null() {
+        self.new_frame()?
+      } else {
+        av_frame_clone(self.canvas)
+      };
+      (*frame).pts = pts;
+      check_result!(av_buffersrc_add_frame(self.filter.context, frame), {
+        av_frame_free(&mut frame);
+      });
+      av_frame_free(&mut frame);
+    }
+    self.last_pts = pts;
+    Ok(())
+  }
+
+  /// Allocate a fully transparent frame.
+  unsafe fn new_frame(&self) -> Result<*mut AVFrame, String> {
+    let mut frame = av_frame_alloc();
+    (*frame).width = self.width;
+    (*frame).height = self.height;
+    (*frame).format = AVPixelFormat::AV_PIX_FMT_RGBA as i32;
+    check_result!(av_frame_get_buffer(frame, 0), {
+      av_frame_free(&mut frame);
+    });
+    for y in 0..self.height {
+      write_bytes(
+        (*frame).data[0].offset((y * (*frame).linesize[0]) as isize),
+        0,
+        self.width as usize * 4,
+      );
+    }
+    Ok(frame)
+  }
+}
+
+impl Drop for SubtitleInput {
+  fn drop(&mut self) {
+    unsafe {
+      if !self.canvas.is_null() {
+        av_frame_free(&mut self.canvas);
+      }
+    }
   }
 }
 
 impl Drop for FilterGraph {
   fn drop(&mut self) {
     unsafe {
+      if !self.sink_frame.is_null() {
+        av_frame_free(&mut self.sink_frame);
+      }
       if !self.graph.is_null() {
         avfilter_graph_free(&mut self.graph);
       }
@@ -343,3 +1189,313 @@ impl fmt::Display for FilterGraph {
     Ok(())
   }
 }
+
+#[test]
+fn graph_dot_and_dump() {
+  let mut graph = FilterGraph::new().unwrap();
+  graph
+    .add_video_input(
+      "source",
+      64,
+      48,
+      "yuv420p",
+      Rational::new(1, 25),
+      Rational::new(1, 1),
+    )
+    .unwrap();
+  graph.add_video_output("output").unwrap();
+
+  let dot = graph.to_dot();
+  assert!(dot.starts_with("digraph filter_graph {"));
+  assert!(dot.contains("buffer\\nsource"));
+  assert!(dot.contains("not connected"));
+  assert!(graph.dump().is_err());
+
+  graph.parse("[source]null[output]").unwrap();
+  graph.validate().unwrap();
+  let dot = graph.to_dot();
+  assert!(!dot.contains("not connected"));
+  assert!(dot.contains("yuv420p 64x48"));
+  assert!(graph.dump().unwrap().contains("null"));
+}
+
+#[test]
+fn parse_graph_labels() {
+  let new_graph = |inputs: &[&str], outputs: &[&str]| {
+    let mut graph = FilterGraph::new().unwrap();
+    for input in inputs {
+      graph
+        .add_video_input(
+          input,
+          64,
+          48,
+          "yuv420p",
+          Rational::new(1, 25),
+          Rational::new(1, 1),
+        )
+        .unwrap();
+    }
+    for output in outputs {
+      graph.add_video_output(output).unwrap();
+    }
+    graph
+  };
+
+  // labels bind to the inputs, and to the format filters in front of the outputs
+  let mut graph = new_graph(&["left", "right"], &["output"]);
+  let mut format = HashMap::new();
+  format.insert(
+    "pix_fmts".to_string(),
+    ParameterValue::String("gray".to_string()),
+  );
+  graph
+    .set_output_format("output", "format", &format)
+    .unwrap();
+  graph.parse("[left][right]hstack[output]").unwrap();
+  graph.validate().unwrap();
+  assert!(graph.to_dot().contains("gray 128x48"));
+
+  // an output left out of the description
+  let mut graph = new_graph(&["source"], &["output", "preview"]);
+  assert_eq!(
+    graph.parse("[source]null[output]"),
+    Err("Unconnected labels in the filter graph: preview".to_string())
+  );
+
+  // a label of the description matching no output
+  let mut graph = new_graph(&["source"], &["output"]);
+  assert_eq!(
+    graph.parse("[source]split[output][thumbnail]"),
+    Err("Unconnected labels in the filter graph: thumbnail".to_string())
+  );
+
+  let mut graph = new_graph(&["source"], &["output"]);
+  assert_eq!(
+    graph.parse("[source]null\0[output]"),
+    Err("Invalid filter graph description".to_string())
+  );
+}
+
+#[test]
+fn convert_alias_frames_on_their_source() {
+  let mut graph = FilterGraph::new().unwrap();
+  graph
+    .add_video_input(
+      "source",
+      64,
+      48,
+      "yuv420p",
+      Rational::new(1, 25),
+      Rational::new(1, 1),
+    )
+    .unwrap();
+  graph.add_video_output("analysed").unwrap();
+  graph.add_output_alias("checked", "analysed");
+  graph.add_output_alias("encoded", "checked");
+
+  let mut format = HashMap::new();
+  format.insert(
+    "pix_fmts".to_string(),
+    ParameterValue::String("gray".to_string()),
+  );
+  graph
+    .set_output_format("encoded", "format", &format)
+    .unwrap();
+  assert!(graph
+    .set_output_format("checked", "format", &format)
+    .is_err());
+
+  graph.parse("[source]null[analysed]").unwrap();
+  graph.validate().unwrap();
+  assert_eq!(
+    graph.describe_output("encoded"),
+    graph.describe_output("analysed")
+  );
+  assert!(graph.describe_output("analysed").unwrap().contains("gray"));
+}
+
+#[test]
+fn queue_commands() {
+  let mut graph = FilterGraph::new().unwrap();
+  graph
+    .add_video_input(
+      "source",
+      64,
+      48,
+      "yuv420p",
+      Rational::new(1, 25),
+      Rational::new(1, 1),
+    )
+    .unwrap();
+  graph.add_video_output("output").unwrap();
+  let hue = graph
+    .add_filter(&crate::order::filter::Filter {
+      name: "hue".to_string(),
+      label: Some("color".to_string()),
+      parameters: HashMap::new(),
+      inputs: None,
+      outputs: None,
+    })
+    .unwrap();
+  graph.connect_input("source", 0, &hue, 0).unwrap();
+  graph.connect_output(&hue, 0, "output", 0).unwrap();
+  graph.validate().unwrap();
+
+  graph.queue_command("color", "s", "0", 1.0).unwrap();
+  graph.queue_command("hue", "s", "0", 1.0).unwrap();
+  graph.queue_command("all", "s", "0", 1.0).unwrap();
+  assert_eq!(
+    graph.queue_command("colour", "s", "0", 1.0),
+    Err("No filter of the graph matches the command target colour".to_string())
+  );
+  assert_eq!(
+    graph.queue_command("color", "s", "0\01", 1.0),
+    Err("Invalid command argument 0\\u{0}1".to_string())
+  );
+  assert!(graph.send_command("color", "h\0", "90").is_err());
+  assert!(graph.send_command("color", "h", "90").is_ok());
+}
+
+#[test]
+fn burn_in_bitmap_subtitles() {
+  use crate::subtitle::SubtitleBitmap;
+
+  let mut graph = FilterGraph::new().unwrap();
+  graph
+    .add_video_input(
+      "video",
+      64,
+      48,
+      "yuv420p",
+      Rational::new(1, 25),
+      Rational::new(1, 1),
+    )
+    .unwrap();
+  graph
+    .add_subtitle_input("subtitle", 64, 48, "video", Rational::new(1, 25))
+    .unwrap();
+  graph.add_video_output("output").unwrap();
+  graph
+    .parse("[video][subtitle]overlay=format=yuv420[output]")
+    .unwrap();
+  graph.validate().unwrap();
+
+  // opaque white over the whole picture
+  let cue = |start, end| Subtitle {
+    name: Some("subtitle".to_string()),
+    index: 0,
+    start,
+    end,
+    rects: vec![SubtitleRect::Bitmap(SubtitleBitmap {
+      x: 0,
+      y: 0,
+      width: 64,
+      height: 48,
+      data: vec![0; 64 * 48],
+      linesize: 64,
+      palette: vec![0xffff_ffff],
+    })],
+    packet: None,
+  };
+  // the second cue is read before the video reaches the end of the first one
+  graph.push_subtitle(&cue(40, 120)).unwrap();
+  graph.push_subtitle(&cue(280, 360)).unwrap();
+
+  let mut output_frames = vec![];
+  for pts in 0..10 {
+    let frame = unsafe {
+      let frame = av_frame_alloc();
+      (*frame).width = 64;
+      (*frame).height = 48;
+      (*frame).format = AVPixelFormat::AV_PIX_FMT_YUV420P as i32;
+      (*frame).pts = pts;
+      assert_eq!(av_frame_get_buffer(frame, 0), 0);
+      write_bytes((*frame).data[0], 16, ((*frame).linesize[0] * 48) as usize);
+      write_bytes((*frame).data[1], 128, ((*frame).linesize[1] * 24) as usize);
+      write_bytes((*frame).data[2], 128, ((*frame).linesize[2] * 24) as usize);
+      Frame {
+        name: Some("video".to_string()),
+        frame,
+        index: 0,
+      }
+    };
+    output_frames.extend(graph.process(&[], &[frame]).unwrap().1);
+  }
+  graph.close_subtitle_inputs().unwrap();
+  output_frames.extend(graph.process(&[], &[]).unwrap().1);
+
+  let is_burnt = |pts: i64| {
+    let frame = output_frames
+      .iter()
+      .find(|frame| frame.get_pts() == pts)
+      .unwrap();
+    unsafe { *(*frame.frame).data[0] > 128 }
+  };
+  assert!(is_burnt(1));
+  assert!(is_burnt(2));
+  for pts in 3..7 {
+    assert!(!is_burnt(pts), "frame {pts} still shows the first cue");
+  }
+  assert!(is_burnt(7));
+  assert!(is_burnt(8));
+}
+
+#[test]
+fn follow_video_cut() {
+  let new_graph = |description: &str| {
+    let mut graph = FilterGraph::new().unwrap();
+    graph
+      .add_video_input(
+        "source",
+        64,
+        48,
+        "yuv420p",
+        Rational::new(1, 25),
+        Rational::new(1, 1),
+      )
+      .unwrap();
+    graph.add_video_output("output").unwrap();
+    graph.parse(description).unwrap();
+    graph.validate().unwrap();
+    graph
+  };
+
+  let graph = new_graph("[source]null[output]");
+  assert_eq!(graph.get_cut_segments("output"), Ok(None));
+
+  let graph = new_graph("[source]trim=start=2:duration=3,setpts=PTS-STARTPTS[output]");
+  assert_eq!(
+    graph.get_cut_segments("output"),
+    Ok(Some(vec![Segment {
+      start: 2000,
+      end: 5000
+    }]))
+  );
+
+  let graph = new_graph(
+    "[source]split[first][second];\
+     [first]trim=end=1[head];\
+     [second]trim=start_pts=100:end_pts=150[tail];\
+     [head][tail]concat=n=2[output]",
+  );
+  assert_eq!(
+    graph.get_cut_segments("output"),
+    Ok(Some(vec![
+      Segment {
+        start: 0,
+        end: 1000
+      },
+      Segment {
+        start: 4000,
+        end: 6000
+      }
+    ]))
+  );
+
+  let graph = new_graph("[source]trim=start_frame=10[output]");
+  assert_eq!(
+    graph.get_cut_segments("output"),
+    Err("Unable to follow the cut of a trim on frame numbers".to_string())
+  );
+  assert!(graph.get_cut_segments("preview").is_err());
+}
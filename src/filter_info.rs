@@ -0,0 +1,277 @@
+use crate::{order::parameters::ParameterValue, tools};
+use ffmpeg_sys_next::AVOptionType::*;
+use ffmpeg_sys_next::*;
+use std::{collections::HashMap, ffi::CString, ptr::null_mut};
+
+/// Description of a libavfilter filter, as available in the linked FFmpeg.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FilterInfo {
+  pub name: String,
+  pub description: String,
+  pub inputs: Vec<PadInfo>,
+  pub outputs: Vec<PadInfo>,
+  /// The filter adds inputs or outputs depending on its options, like `amerge`.
+  pub dynamic_inputs: bool,
+  pub dynamic_outputs: bool,
+  pub options: Vec<OptionInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PadInfo {
+  pub name: String,
+  /// `audio`, `video`...
+  pub media_type: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OptionInfo {
+  pub name: String,
+  pub help: String,
+  /// Option type, like `int`, `double`, `string`, `pixel_fmt` or `flags`.
+  pub kind: String,
+  pub default: Option<String>,
+  pub min: f64,
+  pub max: f64,
+  pub unit: Option<String>,
+  /// Named values accepted by the option.
+  pub constants: Vec<OptionConstant>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OptionConstant {
+  pub name: String,
+  pub help: String,
+  pub value: i64,
+}
+
+impl FilterInfo {
+  pub fn list() -> Vec<FilterInfo> {
+    let mut filters = vec![];
+    unsafe {
+      let mut opaque = null_mut();
+      loop {
+        let filter = av_filter_iterate(&mut opaque);
+        if filter.is_null() {
+          break;
+        }
+        filters.push(FilterInfo::new(filter));
+      }
+    }
+    filters
+  }
+
+  pub fn find(name: &str) -> Option<FilterInfo> {
+    let name = CString::new(name).ok()?;
+    unsafe {
+      let filter = avfilter_get_by_name(name.as_ptr());
+      if filter.is_null() {
+        return None;
+      }
+      Some(FilterInfo::new(filter))
+    }
+  }
+
+  unsafe fn new(filter: *const AVFilter) -> Self {
+    let flags = (*filter).flags as u32;
+    FilterInfo {
+      name: tools::to_string((*filter).name),
+      description: tools::to_string((*filter).description),
+      inputs: get_pads(filter, false),
+      outputs: get_pads(filter, true),
+      dynamic_inputs: flags & AVFILTER_FLAG_DYNAMIC_INPUTS as u32 != 0,
+      dynamic_outputs: flags & AVFILTER_FLAG_DYNAMIC_OUTPUTS as u32 != 0,
+      options: get_options((*filter).priv_class),
+    }
+  }
+
+  pub fn get_option(&self, name: &str) -> Option<&OptionInfo> {
+    self.options.iter().find(|option| option.name == name)
+  }
+
+  /// Check the parameters are options of the filter, or of every filter (`enable`...),
+  /// and numbers are in the range of their option.
+  pub fn validate_parameters(
+    &self,
+    parameters: &HashMap<String, ParameterValue>,
+  ) -> Result<(), String> {
+    let common_options = unsafe { get_options(avfilter_get_class()) };
+    for (key, value) in parameters {
+      let option = self
+        .get_option(key)
+        .or_else(|| common_options.iter().find(|option| option.name == *key))
+        .ok_or_else(|| format!("Unknown option '{key}' for the {} filter", self.name))?;
+
+      let number = match value {
+        ParameterValue::Int64(value) => *value as f64,
+        ParameterValue::Float(value) => *value,
+        _ => continue,
+      };
+      let is_numeric = matches!(
+        option.kind.as_str(),
+        "int" | "int64" | "uint64" | "double" | "float"
+      );
+      if is_numeric && (number < option.min || number > option.max) {
+        return Err(format!(
+          "Option '{key}' of the {} filter must be between {} and {}, got {number}",
+          self.name, option.min, option.max
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+unsafe fn get_pads(filter: *const AVFilter, is_output: bool) -> Vec<PadInfo> {
+  let pads = if is_output {
+    (*filter).outputs
+  } else {
+    (*filter).inputs
+  };
+  (0..get_pad_count(filter, is_output))
+    .map(|index| PadInfo {
+      name: tools::to_string(avfilter_pad_get_name(pads, index as i32)),
+      media_type: tools::to_string(av_get_media_type_string(avfilter_pad_get_type(
+        pads,
+        index as i32,
+      ))),
+    })
+    .collect()
+}
+
+#[cfg(any(ffmpeg_5_0, ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+unsafe fn get_pad_count(filter: *const AVFilter, is_output: bool) -> u32 {
+  avfilter_filter_pad_count(filter, is_output as i32)
+}
+
+#[cfg(not(any(ffmpeg_5_0, ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+unsafe fn get_pad_count(filter: *const AVFilter, is_output: bool) -> u32 {
+  let pads = if is_output {
+    (*filter).outputs
+  } else {
+    (*filter).inputs
+  };
+  avfilter_pad_count(pads) as u32
+}
+
+unsafe fn get_options(class: *const AVClass) -> Vec<OptionInfo> {
+  if class.is_null() {
+    return vec![];
+  }
+  let mut options = vec![];
+  let mut option = (*class).option;
+  while !option.is_null() && !(*option).name.is_null() {
+    options.push(*option);
+    option = option.add(1);
+  }
+  options
+    .iter()
+    .filter(|option| option.type_ != AV_OPT_TYPE_CONST)
+    .map(|option| OptionInfo::new(option, &options))
+    .collect()
+}
+
+impl OptionInfo {
+  unsafe fn new(option: &AVOption, class_options: &[AVOption]) -> Self {
+    let unit = if option.unit.is_null() {
+      None
+    } else {
+      Some(tools::to_string(option.unit))
+    };
+    let constants = class_options
+      .iter()
+      .filter(|constant| {
+        constant.type_ == AV_OPT_TYPE_CONST
+          && !constant.unit.is_null()
+          && unit.as_deref() == Some(tools::to_string(constant.unit).as_str())
+      })
+      .map(|constant| OptionConstant {
+        name: tools::to_string(constant.name),
+        help: tools::to_string(constant.help),
+        value: constant.default_val.i64_,
+      })
+      .collect();
+
+    OptionInfo {
+      name: tools::to_string(option.name),
+      help: tools::to_string(option.help),
+      kind: format!("{:?}", option.type_)
+        .trim_start_matches("AV_OPT_TYPE_")
+        .to_lowercase(),
+      default: get_default(option),
+      min: option.min,
+      max: option.max,
+      unit,
+      constants,
+    }
+  }
+}
+
+unsafe fn get_default(option: &AVOption) -> Option<String> {
+  let default = &option.default_val;
+  match option.type_ {
+    AV_OPT_TYPE_BOOL => Some(
+      match default.i64_ {
+        0 => "false",
+        1 => "true",
+        _ => "auto",
+      }
+      .to_string(),
+    ),
+    AV_OPT_TYPE_INT | AV_OPT_TYPE_INT64 | AV_OPT_TYPE_FLAGS | AV_OPT_TYPE_DURATION => {
+      Some(default.i64_.to_string())
+    }
+    AV_OPT_TYPE_UINT64 => Some((default.i64_ as u64).to_string()),
+    AV_OPT_TYPE_DOUBLE | AV_OPT_TYPE_FLOAT | AV_OPT_TYPE_RATIONAL => Some(default.dbl.to_string()),
+    AV_OPT_TYPE_PIXEL_FMT => Some(tools::to_string(av_get_pix_fmt_name(
+      std::mem::transmute::<i32, AVPixelFormat>(default.i64_ as i32),
+    )))
+    .filter(|name| !name.is_empty()),
+    AV_OPT_TYPE_SAMPLE_FMT => Some(tools::to_string(av_get_sample_fmt_name(
+      std::mem::transmute::<i32, AVSampleFormat>(default.i64_ as i32),
+    )))
+    .filter(|name| !name.is_empty()),
+    AV_OPT_TYPE_STRING
+    | AV_OPT_TYPE_IMAGE_SIZE
+    | AV_OPT_TYPE_VIDEO_RATE
+    | AV_OPT_TYPE_COLOR
+    | AV_OPT_TYPE_DICT => {
+      if default.str_.is_null() {
+        None
+      } else {
+        Some(tools::to_string(default.str_))
+      }
+    }
+    _ => None,
+  }
+}
+
+#[test]
+fn filter_info() {
+  let volume = FilterInfo::find("volume").unwrap();
+  assert_eq!(volume.inputs.len(), 1);
+  assert_eq!(volume.inputs[0].media_type, "audio");
+  assert!(!volume.dynamic_inputs);
+  let precision = volume.get_option("precision").unwrap();
+  assert!(precision
+    .constants
+    .iter()
+    .any(|constant| constant.name == "float"));
+
+  assert!(FilterInfo::find("amerge").unwrap().dynamic_inputs);
+  assert!(FilterInfo::find("not_a_filter").is_none());
+  assert!(FilterInfo::list()
+    .iter()
+    .any(|filter| filter.name == "aformat"));
+
+  let mut parameters = HashMap::new();
+  parameters.insert(
+    "enable".to_string(),
+    ParameterValue::String("gt(t,1)".to_string()),
+  );
+  assert!(volume.validate_parameters(&parameters).is_ok());
+  parameters.insert("precision".to_string(), ParameterValue::Int64(42));
+  assert!(volume.validate_parameters(&parameters).is_err());
+  parameters.remove("precision");
+  parameters.insert("gain".to_string(), ParameterValue::Float(0.5));
+  assert!(volume.validate_parameters(&parameters).is_err());
+}
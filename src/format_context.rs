@@ -6,7 +6,8 @@ use ffmpeg_sys_next::*;
 use std::{
   collections::{BTreeMap, HashMap},
   ffi::{c_void, CString},
-  ptr::null_mut,
+  mem::size_of,
+  ptr::{null, null_mut},
 };
 
 #[derive(Debug)]
@@ -57,17 +58,21 @@ impl FormatContext {
     }
   }
 
+  /// Open the output, with the container format guessed from the filename when no
+  /// format name is given.
   pub fn open_output(
     &mut self,
+    format_name: Option<&str>,
     parameters: &HashMap<String, ParameterValue>,
   ) -> Result<(), String> {
     unsafe {
       let filename = CString::new(self.filename.to_owned()).unwrap();
+      let format_name = format_name.map(|name| CString::new(name).unwrap());
 
       if avformat_alloc_output_context2(
         &mut self.format_context,
         null_mut(),
-        null_mut(),
+        format_name.as_ref().map_or(null(), |name| name.as_ptr()),
         filename.as_ptr(),
       ) < 0
       {
@@ -89,6 +94,32 @@ impl FormatContext {
       (*av_stream).id = ((*self.format_context).nb_streams - 1) as i32;
       (*av_stream).time_base = (*encoder.codec_context).time_base;
       avcodec_parameters_from_context((*av_stream).codecpar, encoder.codec_context);
+
+      if let Some(mastering_display) = &encoder.hdr_metadata.mastering_display {
+        let metadata = new_stream_side_data(
+          av_stream,
+          AVPacketSideDataType::AV_PKT_DATA_MASTERING_DISPLAY_METADATA,
+          size_of::<AVMasteringDisplayMetadata>(),
+        ) as *mut AVMasteringDisplayMetadata;
+        if metadata.is_null() {
+          return Err("Unable to allocate mastering display metadata".to_owned());
+        }
+        *metadata = std::mem::zeroed();
+        mastering_display.write_to(metadata);
+      }
+
+      if let Some(content_light_level) = &encoder.hdr_metadata.content_light_level {
+        let metadata = new_stream_side_data(
+          av_stream,
+          AVPacketSideDataType::AV_PKT_DATA_CONTENT_LIGHT_LEVEL,
+          size_of::<AVContentLightMetadata>(),
+        ) as *mut AVContentLightMetadata;
+        if metadata.is_null() {
+          return Err("Unable to allocate content light level metadata".to_owned());
+        }
+        content_light_level.write_to(metadata);
+      }
+
       self.streams.push(av_stream);
     }
     Ok(())
@@ -131,6 +162,49 @@ impl FormatContext {
     *(*self.format_context).streams.offset(stream_index)
   }
 
+  /// Check the timecode against the frame rate of the video stream, then set it in the
+  /// container and stream metadata where muxers read it.
+  pub fn set_timecode(
+    &mut self,
+    timecode: &str,
+    frame_rate: AVRational,
+    stream_index: isize,
+  ) -> Result<(), String> {
+    unsafe {
+      let value = CString::new(timecode).unwrap();
+      let mut av_timecode: AVTimecode = std::mem::zeroed();
+      if av_timecode_init_from_string(&mut av_timecode, frame_rate, value.as_ptr(), null_mut()) < 0
+      {
+        return Err(format!(
+          "Timecode {timecode} does not match the {}/{} frame rate",
+          frame_rate.num, frame_rate.den
+        ));
+      }
+      let mut buffer = [0; AV_TIMECODE_STR_SIZE as usize];
+      av_timecode_make_string(&av_timecode, buffer.as_mut_ptr(), 0);
+
+      let key = CString::new("timecode").unwrap();
+      check_result!(av_dict_set(
+        &mut (*self.format_context).metadata,
+        key.as_ptr(),
+        buffer.as_ptr(),
+        0
+      ));
+      let stream = self.get_stream(stream_index);
+      check_result!(av_dict_set(
+        &mut (*stream).metadata,
+        key.as_ptr(),
+        buffer.as_ptr(),
+        0
+      ));
+
+      // MP4 only writes a tmcd track on request, other muxers do not have the option
+      let option = CString::new("write_tmcd").unwrap();
+      av_opt_set_int((*self.format_context).priv_data, option.as_ptr(), 1, 0);
+    }
+    Ok(())
+  }
+
   pub fn get_nb_streams(&self) -> u32 {
     if !self.frames.is_empty() {
       return 1;
@@ -275,6 +349,37 @@ impl FormatContext {
 
 unsafe impl Send for FormatContext {}
 
+#[cfg(not(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+unsafe fn new_stream_side_data(
+  stream: *mut AVStream,
+  side_data_type: AVPacketSideDataType,
+  size: usize,
+) -> *mut u8 {
+  av_stream_new_side_data(stream, side_data_type, size as _)
+}
+
+/// Stream side data moved to the codec parameters in FFmpeg 6.1.
+#[cfg(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+unsafe fn new_stream_side_data(
+  stream: *mut AVStream,
+  side_data_type: AVPacketSideDataType,
+  size: usize,
+) -> *mut u8 {
+  let codecpar = (*stream).codecpar;
+  let side_data = av_packet_side_data_new(
+    &mut (*codecpar).coded_side_data,
+    &mut (*codecpar).nb_coded_side_data,
+    side_data_type,
+    size,
+    0,
+  );
+  if side_data.is_null() {
+    null_mut()
+  } else {
+    (*side_data).data
+  }
+}
+
 impl From<*mut AVFormatContext> for FormatContext {
   fn from(format_context: *mut AVFormatContext) -> Self {
     FormatContext {
@@ -1,6 +1,12 @@
-use crate::tools;
-use ffmpeg_sys_next::{av_dict_get, av_frame_free, AVFrame};
-use std::{ffi::CString, ptr::null_mut};
+use crate::{
+  hdr::{ContentLightLevel, HdrMetadata, MasteringDisplayMetadata},
+  side_data::SideDataIterator,
+  tools,
+};
+use ffmpeg_sys_next::{
+  av_dict_get, av_frame_free, av_frame_get_side_data, AVFrame, AVFrameSideDataType,
+};
+use std::{ffi::CString, ptr::null_mut, slice};
 
 pub struct Frame {
   pub name: Option<String>,
@@ -24,6 +30,69 @@ impl Frame {
   pub fn get_pts(&self) -> i64 {
     unsafe { (*self.frame).pts }
   }
+
+  pub fn side_data(&self) -> SideDataIterator<'_> {
+    SideDataIterator::new(self)
+  }
+
+  /// Value of a `side_data.<name>` key, e.g. `side_data.afd`.
+  pub fn get_side_data_entry(&self, key: &str) -> Option<String> {
+    let name = key.strip_prefix("side_data.")?;
+    self
+      .side_data()
+      .find(|side_data| side_data.get_name() == name)
+      .map(|side_data| side_data.to_string())
+  }
+
+  pub fn get_side_data(&self, side_data_type: AVFrameSideDataType) -> Option<Vec<u8>> {
+    unsafe {
+      let side_data = av_frame_get_side_data(self.frame, side_data_type);
+      if side_data.is_null() || (*side_data).data.is_null() {
+        return None;
+      }
+      Some(slice::from_raw_parts((*side_data).data, (*side_data).size as usize).to_vec())
+    }
+  }
+
+  pub fn get_hdr_metadata(&self) -> Option<HdrMetadata> {
+    unsafe {
+      let side_data = av_frame_get_side_data(
+        self.frame,
+        AVFrameSideDataType::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA,
+      );
+      let mastering_display = if side_data.is_null() {
+        None
+      } else {
+        MasteringDisplayMetadata::from_av_mastering_display_metadata((*side_data).data as *const _)
+      };
+
+      let side_data = av_frame_get_side_data(
+        self.frame,
+        AVFrameSideDataType::AV_FRAME_DATA_CONTENT_LIGHT_LEVEL,
+      );
+      let content_light_level = if side_data.is_null() {
+        None
+      } else {
+        ContentLightLevel::from_av_content_light_metadata((*side_data).data as *const _)
+      };
+
+      let hdr_metadata = HdrMetadata {
+        mastering_display,
+        content_light_level,
+        dolby_vision: None,
+      };
+      if hdr_metadata.is_empty() {
+        None
+      } else {
+        Some(hdr_metadata)
+      }
+    }
+  }
+
+  /// CEA-708 cc_data triplets carried in the video frame.
+  pub fn get_a53_closed_captions(&self) -> Option<Vec<u8>> {
+    self.get_side_data(AVFrameSideDataType::AV_FRAME_DATA_A53_CC)
+  }
 }
 
 impl Drop for Frame {
@@ -0,0 +1,304 @@
+use crate::tools::rational::Rational;
+use ffmpeg_sys_next::*;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// CIE 1931 xy chromaticity coordinates.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Chromaticity {
+  pub x: Rational,
+  pub y: Rational,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct DisplayPrimaries {
+  pub red: Chromaticity,
+  pub green: Chromaticity,
+  pub blue: Chromaticity,
+  pub white_point: Chromaticity,
+}
+
+/// Luminance in cd/m².
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Luminance {
+  pub min: Rational,
+  pub max: Rational,
+}
+
+/// SMPTE ST 2086 mastering display color volume.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct MasteringDisplayMetadata {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub primaries: Option<DisplayPrimaries>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub luminance: Option<Luminance>,
+}
+
+/// MaxCLL and MaxFALL, in cd/m².
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct ContentLightLevel {
+  pub max_cll: u32,
+  pub max_fall: u32,
+}
+
+/// Dolby Vision decoder configuration record.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct DolbyVision {
+  pub version_major: u8,
+  pub version_minor: u8,
+  pub profile: u8,
+  pub level: u8,
+  pub rpu_present: bool,
+  pub el_present: bool,
+  pub bl_present: bool,
+  pub bl_signal_compatibility_id: u8,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct HdrMetadata {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub mastering_display: Option<MasteringDisplayMetadata>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub content_light_level: Option<ContentLightLevel>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub dolby_vision: Option<DolbyVision>,
+}
+
+impl HdrMetadata {
+  pub fn is_empty(&self) -> bool {
+    self.mastering_display.is_none()
+      && self.content_light_level.is_none()
+      && self.dolby_vision.is_none()
+  }
+}
+
+impl Chromaticity {
+  fn new(coordinates: &[AVRational; 2]) -> Self {
+    Chromaticity {
+      x: Rational::new(coordinates[0].num, coordinates[0].den),
+      y: Rational::new(coordinates[1].num, coordinates[1].den),
+    }
+  }
+}
+
+impl MasteringDisplayMetadata {
+  /// # Safety
+  /// `metadata` must be null or point to a valid mastering display metadata.
+  pub unsafe fn from_av_mastering_display_metadata(
+    metadata: *const AVMasteringDisplayMetadata,
+  ) -> Option<Self> {
+    if metadata.is_null() || ((*metadata).has_primaries == 0 && (*metadata).has_luminance == 0) {
+      return None;
+    }
+
+    let primaries = if (*metadata).has_primaries != 0 {
+      Some(DisplayPrimaries {
+        red: Chromaticity::new(&(*metadata).display_primaries[0]),
+        green: Chromaticity::new(&(*metadata).display_primaries[1]),
+        blue: Chromaticity::new(&(*metadata).display_primaries[2]),
+        white_point: Chromaticity::new(&(*metadata).white_point),
+      })
+    } else {
+      None
+    };
+
+    let luminance = if (*metadata).has_luminance != 0 {
+      Some(Luminance {
+        min: Rational::new((*metadata).min_luminance.num, (*metadata).min_luminance.den),
+        max: Rational::new((*metadata).max_luminance.num, (*metadata).max_luminance.den),
+      })
+    } else {
+      None
+    };
+
+    Some(MasteringDisplayMetadata {
+      primaries,
+      luminance,
+    })
+  }
+
+  /// # Safety
+  /// `metadata` must point to an allocated mastering display metadata.
+  pub unsafe fn write_to(&self, metadata: *mut AVMasteringDisplayMetadata) {
+    if let Some(primaries) = &self.primaries {
+      for (index, color) in [&primaries.red, &primaries.green, &primaries.blue]
+        .iter()
+        .enumerate()
+      {
+        (*metadata).display_primaries[index] = [color.x.clone().into(), color.y.clone().into()];
+      }
+      (*metadata).white_point = [
+        primaries.white_point.x.clone().into(),
+        primaries.white_point.y.clone().into(),
+      ];
+      (*metadata).has_primaries = 1;
+    }
+    if let Some(luminance) = &self.luminance {
+      (*metadata).min_luminance = luminance.min.clone().into();
+      (*metadata).max_luminance = luminance.max.clone().into();
+      (*metadata).has_luminance = 1;
+    }
+  }
+}
+
+/// Parse the x265 `master-display` syntax, e.g.
+/// `G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)`,
+/// with chromaticity in 0.00002 units and luminance in 0.0001 cd/m² units.
+impl FromStr for MasteringDisplayMetadata {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = || format!("'{s}' is not a valid value for master_display");
+    let mut values = HashMap::new();
+    for part in s.split(')').filter(|part| !part.trim().is_empty()) {
+      let (key, numbers) = part.trim().split_once('(').ok_or_else(error)?;
+      let (first, second) = numbers.split_once(',').ok_or_else(error)?;
+      let first: i32 = first.trim().parse().map_err(|_| error())?;
+      let second: i32 = second.trim().parse().map_err(|_| error())?;
+      values.insert(key.to_string(), (first, second));
+    }
+
+    let chromaticity = |key: &str| {
+      values.get(key).map(|(x, y)| Chromaticity {
+        x: Rational::new(*x, 50000),
+        y: Rational::new(*y, 50000),
+      })
+    };
+
+    let primaries = match (
+      chromaticity("R"),
+      chromaticity("G"),
+      chromaticity("B"),
+      chromaticity("WP"),
+    ) {
+      (Some(red), Some(green), Some(blue), Some(white_point)) => Some(DisplayPrimaries {
+        red,
+        green,
+        blue,
+        white_point,
+      }),
+      (None, None, None, None) => None,
+      _ => return Err(error()),
+    };
+
+    let luminance = values.get("L").map(|(max, min)| Luminance {
+      min: Rational::new(*min, 10000),
+      max: Rational::new(*max, 10000),
+    });
+
+    if primaries.is_none() && luminance.is_none() {
+      return Err(error());
+    }
+
+    Ok(MasteringDisplayMetadata {
+      primaries,
+      luminance,
+    })
+  }
+}
+
+/// Format in the x265 `master-display` syntax.
+impl fmt::Display for MasteringDisplayMetadata {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let units = |value: &Rational, unit: i64| {
+      if value.den == 0 {
+        0
+      } else {
+        i64::from(value.num) * unit / i64::from(value.den)
+      }
+    };
+
+    if let Some(primaries) = &self.primaries {
+      for (name, color) in [
+        ("G", &primaries.green),
+        ("B", &primaries.blue),
+        ("R", &primaries.red),
+        ("WP", &primaries.white_point),
+      ] {
+        write!(
+          f,
+          "{name}({},{})",
+          units(&color.x, 50000),
+          units(&color.y, 50000)
+        )?;
+      }
+    }
+    if let Some(luminance) = &self.luminance {
+      write!(
+        f,
+        "L({},{})",
+        units(&luminance.max, 10000),
+        units(&luminance.min, 10000)
+      )?;
+    }
+    Ok(())
+  }
+}
+
+impl ContentLightLevel {
+  /// # Safety
+  /// `metadata` must be null or point to a valid content light level metadata.
+  pub unsafe fn from_av_content_light_metadata(
+    metadata: *const AVContentLightMetadata,
+  ) -> Option<Self> {
+    if metadata.is_null() {
+      return None;
+    }
+    Some(ContentLightLevel {
+      max_cll: (*metadata).MaxCLL,
+      max_fall: (*metadata).MaxFALL,
+    })
+  }
+
+  /// # Safety
+  /// `metadata` must point to an allocated content light level metadata.
+  pub unsafe fn write_to(&self, metadata: *mut AVContentLightMetadata) {
+    (*metadata).MaxCLL = self.max_cll;
+    (*metadata).MaxFALL = self.max_fall;
+  }
+}
+
+#[cfg(any(
+  ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1
+))]
+impl DolbyVision {
+  /// # Safety
+  /// `record` must be null or point to a valid Dolby Vision configuration record.
+  pub unsafe fn from_av_dovi_decoder_configuration_record(
+    record: *const AVDOVIDecoderConfigurationRecord,
+  ) -> Option<Self> {
+    if record.is_null() {
+      return None;
+    }
+    Some(DolbyVision {
+      version_major: (*record).dv_version_major,
+      version_minor: (*record).dv_version_minor,
+      profile: (*record).dv_profile,
+      level: (*record).dv_level,
+      rpu_present: (*record).rpu_present_flag != 0,
+      el_present: (*record).el_present_flag != 0,
+      bl_present: (*record).bl_present_flag != 0,
+      bl_signal_compatibility_id: (*record).dv_bl_signal_compatibility_id,
+    })
+  }
+}
+
+#[test]
+fn parse_master_display() {
+  let master_display = "G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,1)";
+  let metadata: MasteringDisplayMetadata = master_display.parse().unwrap();
+  assert_eq!(metadata.to_string(), master_display);
+
+  let primaries = metadata.primaries.unwrap();
+  assert_eq!(primaries.red.x, Rational::new(34000, 50000));
+  assert_eq!(primaries.green.y, Rational::new(34500, 50000));
+  assert_eq!(primaries.white_point.x, Rational::new(15635, 50000));
+
+  let luminance = metadata.luminance.unwrap();
+  assert_eq!(luminance.max, Rational::new(10000000, 10000));
+  assert_eq!(luminance.min, Rational::new(1, 10000));
+
+  assert!("G(13250,34500)L(10000000,1)"
+    .parse::<MasteringDisplayMetadata>()
+    .is_err());
+  assert!("".parse::<MasteringDisplayMetadata>().is_err());
+}
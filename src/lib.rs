@@ -8,15 +8,24 @@ pub mod error;
 
 pub mod audio_decoder;
 pub mod audio_encoder;
+pub mod caption_extractor;
+pub mod channel_layout;
+pub mod codec;
 pub mod filter;
 pub mod filter_graph;
+pub mod filter_info;
 pub mod format_context;
 pub mod frame;
+pub mod hdr;
 pub mod order;
 pub mod packet;
 pub mod prelude;
 pub mod probe;
+pub mod resampler;
+pub mod scaler;
+pub mod side_data;
 pub mod stream;
+pub mod subtitle;
 pub mod subtitle_decoder;
 pub mod subtitle_encoder;
 pub mod tools;
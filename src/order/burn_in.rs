@@ -0,0 +1,17 @@
+use crate::order::parameters::ParameterValue;
+use std::collections::HashMap;
+
+/// Burn a subtitle input stream into a video input stream.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct BurnIn {
+  /// Label of the video input stream.
+  pub video: String,
+  /// Label of the subtitle input stream.
+  pub subtitle: String,
+  /// Label of the output stream receiving the video with subtitles.
+  pub output: String,
+  /// Parameters of the `subtitles` filter for text subtitles,
+  /// or of the `overlay` filter for bitmap subtitles.
+  #[serde(default)]
+  pub parameters: HashMap<String, ParameterValue>,
+}
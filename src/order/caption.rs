@@ -0,0 +1,8 @@
+/// Extract the closed captions of a video input stream as a subtitle stream.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Caption {
+  /// Label of the video input stream.
+  pub video: String,
+  /// Label of the subtitle stream, to be referenced by output streams.
+  pub label: String,
+}
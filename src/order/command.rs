@@ -0,0 +1,12 @@
+/// Command sent to filters of the graph, like `volume` `0.5` to a `volume` filter.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Command {
+  /// Label of the filter, name of the filters of that kind, or `all`.
+  pub target: String,
+  pub command: String,
+  #[serde(default)]
+  pub argument: String,
+  /// Time in seconds of the frames from which the command applies,
+  /// it is sent as soon as the graph is configured when not set.
+  pub time: Option<f64>,
+}
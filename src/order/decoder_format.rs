@@ -2,6 +2,7 @@ use crate::audio_decoder::AudioDecoder;
 use crate::filter_graph::FilterGraph;
 use crate::format_context::FormatContext;
 use crate::order::input::Input;
+use crate::stream::Stream;
 use crate::subtitle_decoder::SubtitleDecoder;
 use crate::tools;
 use crate::video_decoder::VideoDecoder;
@@ -16,6 +17,21 @@ pub struct DecoderFormat {
 }
 
 impl DecoderFormat {
+  /// Start timecode of the input, from the container or the stream metadata.
+  pub fn get_timecode(&self) -> Option<String> {
+    if self.context.format_context.is_null() {
+      return None;
+    }
+    if let Some(timecode) = self.context.get_metadata().remove("timecode") {
+      return Some(timecode);
+    }
+    let nb_streams = unsafe { (*self.context.format_context).nb_streams };
+    (0..nb_streams).find_map(|index| {
+      let stream = unsafe { Stream::new(self.context.get_stream(index as isize)) }.ok()?;
+      stream.get_stream_metadata().remove("timecode")
+    })
+  }
+
   pub fn new(graph: &mut FilterGraph, input: &Input) -> Result<Self, String> {
     match input {
       Input::VideoFrames {
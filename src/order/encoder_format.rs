@@ -4,11 +4,15 @@ use crate::format_context::FormatContext;
 use crate::frame::Frame;
 use crate::order::output::Output;
 use crate::order::output_kind::OutputKind;
+use crate::order::parameters::ParameterValue;
+use crate::order::subtitle_timing::SubtitleTiming;
 use crate::packet::Packet;
+use crate::subtitle::Subtitle;
 use crate::subtitle_encoder::SubtitleEncoder;
 use crate::tools;
-use crate::video_encoder::VideoEncoder;
+use crate::video_encoder::{EncodingPass, PassStats, VideoEncoder};
 use ffmpeg_sys_next::*;
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::ptr::null_mut;
 
@@ -19,18 +23,33 @@ pub struct EncoderFormat {
   pub subtitle_encoders: Vec<SubtitleEncoder>,
   pub video_encoders: Vec<VideoEncoder>,
   wrap: bool,
+  /// Nothing is written during the first pass of a two-pass encoding.
+  first_pass: bool,
 }
 
 impl Drop for EncoderFormat {
   fn drop(&mut self) {
-    unsafe {
-      av_write_trailer(self.context.format_context);
+    if !self.first_pass {
+      unsafe {
+        av_write_trailer(self.context.format_context);
+      }
     }
   }
 }
 
 impl EncoderFormat {
-  pub fn new(graph: &mut FilterGraph, output: &Output) -> Result<Self, String> {
+  /// Create the encoders of an output, starting at `timecode` when set. For a two-pass
+  /// encoding, `first_pass` is set on the first pass and `pass_stats` gives the statistics
+  /// collected by its video encoders on the second one, indexed by stream.
+  /// Subtitle streams take their style from `subtitle_headers`, indexed by label.
+  pub fn new(
+    graph: &mut FilterGraph,
+    output: &Output,
+    timecode: Option<&str>,
+    first_pass: bool,
+    pass_stats: &HashMap<isize, PassStats>,
+    subtitle_headers: &HashMap<String, String>,
+  ) -> Result<Self, String> {
     let mut audio_encoders = vec![];
     let mut subtitle_encoders = vec![];
     let mut video_encoders = vec![];
@@ -38,9 +57,29 @@ impl EncoderFormat {
       return Err("missing output filename".to_owned());
     }
 
+    if let Some(timecode) = timecode {
+      // the timecode counts frames, the video encoder time base is only set from its frame rate
+      let video_stream = output
+        .streams
+        .iter()
+        .find(|stream| {
+          tools::get_codec_type(&stream.codec) == Some(AVMediaType::AVMEDIA_TYPE_VIDEO)
+        })
+        .ok_or_else(|| format!("Unable to set timecode {timecode} without video stream"))?;
+      let has_frame_rate = matches!(
+        video_stream.parameters.get("frame_rate"),
+        Some(ParameterValue::Rational(frame_rate)) if frame_rate.num > 0 && frame_rate.den > 0
+      );
+      if !has_frame_rate {
+        return Err(format!(
+          "A frame_rate parameter is required on the first video stream to set timecode {timecode}"
+        ));
+      }
+    }
+
     let path = output.path.clone().unwrap();
     let mut format = FormatContext::new(&path)?;
-    format.open_output(&output.parameters)?;
+    format.open_output(output.format.as_deref(), &output.parameters)?;
 
     for (index, stream) in output.streams.iter().enumerate() {
       let identifier = if let Some(ref identifier) = stream.label {
@@ -49,21 +88,56 @@ impl EncoderFormat {
         tools::random_string(8)
       };
 
-      match tools::get_codec_type(&stream.codec) {
+      let codec_type = tools::get_codec_type(&stream.codec);
+      if !stream.timing.is_empty() && codec_type != Some(AVMediaType::AVMEDIA_TYPE_SUBTITLE) {
+        return Err(format!(
+          "Timing transforms only apply to subtitle streams, not to {identifier}"
+        ));
+      }
+
+      match codec_type {
         Some(AVMediaType::AVMEDIA_TYPE_VIDEO) => {
-          let video_encoder = VideoEncoder::new(identifier.clone(), index as isize, stream)?;
+          let pass = if !stream.two_pass {
+            EncodingPass::Single
+          } else if first_pass {
+            EncodingPass::First
+          } else {
+            let stats = pass_stats
+              .get(&(index as isize))
+              .ok_or_else(|| format!("Missing first pass statistics for {identifier}"))?;
+            EncodingPass::Second {
+              stats: stats.clone(),
+            }
+          };
+          let video_encoder =
+            VideoEncoder::new_with_pass(identifier.clone(), index as isize, stream, pass)?;
           format.add_video_stream(&video_encoder)?;
+          // frames of stage outputs are converted before the stages
+          if !graph.is_output_alias(&identifier) {
+            graph.add_video_output(&identifier)?;
+          }
+          graph.set_output_format(&identifier, "format", &video_encoder.get_input_format())?;
+          if let Some(size) = video_encoder.get_input_size() {
+            graph.set_output_format(&identifier, "scale", &size)?;
+          }
           video_encoders.push(video_encoder);
-          graph.add_video_output(&identifier)?;
         }
         Some(AVMediaType::AVMEDIA_TYPE_AUDIO) => {
           let audio_encoder = AudioEncoder::new(identifier.clone(), index as isize, stream)?;
           format.add_audio_stream(&audio_encoder)?;
+          if !graph.is_output_alias(&identifier) {
+            graph.add_audio_output(&identifier)?;
+          }
+          graph.set_output_format(&identifier, "aformat", &audio_encoder.get_input_format())?;
           audio_encoders.push(audio_encoder);
-          graph.add_audio_output(&identifier)?;
         }
         Some(AVMediaType::AVMEDIA_TYPE_SUBTITLE) => {
-          let subtitle_encoder = SubtitleEncoder::new(identifier.clone(), index as isize, stream)?;
+          let subtitle_encoder = SubtitleEncoder::new(
+            identifier.clone(),
+            index as isize,
+            stream,
+            subtitle_headers.get(&identifier).map(String::as_str),
+          )?;
           format.add_subtitle_stream(&subtitle_encoder)?;
           subtitle_encoders.push(subtitle_encoder);
         }
@@ -71,6 +145,28 @@ impl EncoderFormat {
       }
     }
 
+    if let Some(timecode) = timecode {
+      let video_encoder = video_encoders
+        .first()
+        .ok_or_else(|| format!("Unable to set timecode {timecode} without video stream"))?;
+      format.set_timecode(
+        timecode,
+        video_encoder.get_frame_rate(),
+        video_encoder.stream_index,
+      )?;
+    }
+
+    if first_pass {
+      return Ok(EncoderFormat {
+        context: format,
+        audio_encoders,
+        subtitle_encoders,
+        video_encoders,
+        wrap: false,
+        first_pass,
+      });
+    }
+
     unsafe {
       let p = CString::new(path).unwrap();
       av_dump_format(format.format_context, 0, p.as_ptr(), 1);
@@ -89,53 +185,118 @@ impl EncoderFormat {
       subtitle_encoders,
       video_encoders,
       wrap: output.kind == Some(OutputKind::File),
+      first_pass,
     })
   }
 
-  pub fn wrap(&mut self, packet: &Packet) -> Result<(), String> {
-    for subtitle_encoder in &self.subtitle_encoders {
-      if let Some(ref name) = packet.name {
+  /// Statistics of the first pass video encoders, indexed by stream.
+  pub fn get_pass_stats(&self) -> HashMap<isize, PassStats> {
+    self
+      .video_encoders
+      .iter()
+      .filter_map(|encoder| {
+        encoder
+          .get_stats()
+          .map(|stats| (encoder.stream_index, stats))
+      })
+      .collect()
+  }
+
+  pub fn log_negotiated_formats(&self, graph: &FilterGraph) {
+    let identifiers = self
+      .audio_encoders
+      .iter()
+      .map(|encoder| &encoder.identifier)
+      .chain(
+        self
+          .video_encoders
+          .iter()
+          .map(|encoder| &encoder.identifier),
+      );
+    for identifier in identifiers {
+      if let Some(description) = graph.describe_output(identifier) {
+        info!("{identifier} negotiated {description}");
+      }
+    }
+  }
+
+  /// Encoders take their frames in the time base of the filter graph outputs.
+  pub fn set_input_time_bases(&mut self, graph: &FilterGraph) {
+    for audio_encoder in &mut self.audio_encoders {
+      if let Some(time_base) = graph.get_output_time_base(&audio_encoder.identifier) {
+        audio_encoder.set_input_time_base(time_base.into());
+      }
+    }
+    for video_encoder in &mut self.video_encoders {
+      if let Some(time_base) = graph.get_output_time_base(&video_encoder.identifier) {
+        video_encoder.set_input_time_base(time_base.into());
+      }
+    }
+  }
+
+  /// Cut the subtitles which follow the video on the trims in front of the first video
+  /// stream of the output, or of `video_label` for outputs without video like sidecar files.
+  pub fn follow_video_cut(
+    &mut self,
+    graph: &FilterGraph,
+    video_label: Option<&str>,
+  ) -> Result<(), String> {
+    let video_label = self
+      .video_encoders
+      .first()
+      .map(|encoder| encoder.identifier.as_str())
+      .or(video_label);
+    for subtitle_encoder in &mut self.subtitle_encoders {
+      for timing in &mut subtitle_encoder.timing {
+        if let SubtitleTiming::Cut { segments: None } = timing {
+          let video_label = video_label.ok_or_else(|| {
+            format!(
+              "The cut of {} follows the video, but the order has no video output",
+              subtitle_encoder.identifier
+            )
+          })?;
+          *timing = SubtitleTiming::Cut {
+            segments: graph.get_cut_segments(video_label)?,
+          };
+        }
+      }
+    }
+    Ok(())
+  }
+
+  pub fn encode_subtitle(&mut self, subtitle: &Subtitle) -> Result<Vec<Packet>, String> {
+    let mut r_packets = vec![];
+    for subtitle_encoder in &mut self.subtitle_encoders {
+      if let Some(ref name) = subtitle.name {
         if subtitle_encoder.identifier == *name {
-          unsafe {
-            (*packet.packet).stream_index = subtitle_encoder.stream_index as i32;
-            check_result!(av_interleaved_write_frame(
-              self.context.format_context,
-              packet.packet
-            ));
-          }
+          let packets = subtitle_encoder.encode(subtitle)?;
+          r_packets.extend(write_packets(
+            &self.context,
+            self.wrap,
+            subtitle_encoder.stream_index,
+            subtitle_encoder.codec_context,
+            packets,
+          )?);
         }
       }
     }
 
-    Ok(())
+    Ok(r_packets)
   }
 
-  pub fn encode(&mut self, frame: &Frame) -> Result<Option<Packet>, String> {
-    let mut r_packet = None;
-    for audio_encoder in &self.audio_encoders {
+  pub fn encode(&mut self, frame: &Frame) -> Result<Vec<Packet>, String> {
+    let mut r_packets = vec![];
+    for audio_encoder in &mut self.audio_encoders {
       if let Some(ref name) = frame.name {
         if audio_encoder.identifier == *name {
-          unsafe {
-            let packet = av_packet_alloc();
-            av_init_packet(packet);
-            (*packet).data = null_mut();
-            (*packet).size = 0;
-            let p = Packet { name: None, packet };
-
-            let status = audio_encoder.encode(frame, &p)?;
-
-            if status {
-              if self.wrap {
-                (*packet).stream_index = audio_encoder.stream_index as i32;
-                check_result!(av_interleaved_write_frame(
-                  self.context.format_context,
-                  packet
-                ));
-              } else {
-                r_packet = Some(p);
-              }
-            }
-          }
+          let packets = audio_encoder.encode(frame)?;
+          r_packets.extend(write_packets(
+            &self.context,
+            self.wrap,
+            audio_encoder.stream_index,
+            audio_encoder.codec_context,
+            packets,
+          )?);
         }
       }
     }
@@ -151,21 +312,138 @@ impl EncoderFormat {
 
             let status = video_encoder.encode(frame, &p)?;
             if status {
-              if self.wrap {
-                (*packet).stream_index = video_encoder.stream_index as i32;
-                check_result!(av_interleaved_write_frame(
-                  self.context.format_context,
-                  packet
-                ));
-              } else {
-                r_packet = Some(p);
-              }
+              r_packets.extend(write_packets(
+                &self.context,
+                self.wrap,
+                video_encoder.stream_index,
+                video_encoder.codec_context,
+                vec![p],
+              )?);
             }
           }
         }
       }
     }
 
-    Ok(r_packet)
+    Ok(r_packets)
+  }
+
+  /// Encode the samples still buffered by the audio encoders and drain the video encoders.
+  pub fn flush(&mut self) -> Result<Vec<Packet>, String> {
+    let mut r_packets = vec![];
+    for video_encoder in &mut self.video_encoders {
+      let packets = video_encoder.flush()?;
+      r_packets.extend(write_packets(
+        &self.context,
+        self.wrap,
+        video_encoder.stream_index,
+        video_encoder.codec_context,
+        packets,
+      )?);
+    }
+    for audio_encoder in &mut self.audio_encoders {
+      let packets = audio_encoder.flush()?;
+      r_packets.extend(write_packets(
+        &self.context,
+        self.wrap,
+        audio_encoder.stream_index,
+        audio_encoder.codec_context,
+        packets,
+      )?);
+    }
+    Ok(r_packets)
+  }
+}
+
+/// Rescale packets to the stream time base, then write them when wrapping into a file or
+/// return them.
+fn write_packets(
+  context: &FormatContext,
+  wrap: bool,
+  stream_index: isize,
+  codec_context: *mut AVCodecContext,
+  packets: Vec<Packet>,
+) -> Result<Vec<Packet>, String> {
+  let mut r_packets = vec![];
+  for packet in packets {
+    unsafe {
+      let stream = context.get_stream(stream_index);
+      av_packet_rescale_ts(
+        packet.packet,
+        (*codec_context).time_base,
+        (*stream).time_base,
+      );
+
+      if wrap {
+        (*packet.packet).stream_index = stream_index as i32;
+        check_result!(av_interleaved_write_frame(
+          context.format_context,
+          packet.packet
+        ));
+      } else {
+        r_packets.push(packet);
+      }
+    }
   }
+  Ok(r_packets)
+}
+
+#[test]
+fn reject_timing_on_video_streams() {
+  let path = std::env::temp_dir().join(format!("timing_{}.mxf", tools::random_string(8)));
+  let output: Output = serde_json::from_str(&format!(
+    r#"{{
+      "kind": "file",
+      "path": "{}",
+      "streams": [
+        {{
+          "label": "video",
+          "codec": "mpeg2video",
+          "parameters": {{}},
+          "timing": [{{"kind": "offset", "milliseconds": 500}}]
+        }}
+      ]
+    }}"#,
+    path.display()
+  ))
+  .unwrap();
+
+  let mut graph = FilterGraph::new().unwrap();
+  let error = EncoderFormat::new(
+    &mut graph,
+    &output,
+    None,
+    false,
+    &HashMap::new(),
+    &HashMap::new(),
+  )
+  .unwrap_err();
+  assert!(error.contains("only apply to subtitle streams"));
+}
+
+#[test]
+fn require_frame_rate_for_timecode() {
+  let output: Output = serde_json::from_str(
+    r#"{
+      "kind": "file",
+      "path": "timecode.mxf",
+      "streams": [{"label": "video", "codec": "mpeg2video", "parameters": {}}]
+    }"#,
+  )
+  .unwrap();
+
+  let mut graph = FilterGraph::new().unwrap();
+  let error = EncoderFormat::new(
+    &mut graph,
+    &output,
+    Some("10:00:00:00"),
+    false,
+    &HashMap::new(),
+    &HashMap::new(),
+  )
+  .unwrap_err();
+  assert_eq!(
+    error,
+    "A frame_rate parameter is required on the first video stream to set timecode 10:00:00:00"
+  );
 }
@@ -0,0 +1,28 @@
+use crate::order::filter::Filter;
+
+/// Filters of an order, either as a libavfilter description like
+/// `"[audio]aformat=sample_fmts=s32[output]"` or as a list of filters.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Graph {
+  Description(String),
+  Filters(Vec<Filter>),
+}
+
+impl From<Vec<Filter>> for Graph {
+  fn from(filters: Vec<Filter>) -> Self {
+    Graph::Filters(filters)
+  }
+}
+
+impl From<&str> for Graph {
+  fn from(description: &str) -> Self {
+    Graph::Description(description.to_string())
+  }
+}
+
+impl From<String> for Graph {
+  fn from(description: String) -> Self {
+    Graph::Description(description)
+  }
+}
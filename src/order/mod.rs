@@ -1,59 +1,111 @@
 use crate::filter_graph::FilterGraph;
 use std::collections::HashMap;
 
+pub mod burn_in;
+pub mod caption;
+pub mod command;
 mod decoder_format;
 mod encoder_format;
 pub mod filter;
 pub mod filter_input;
 pub mod filter_output;
 pub mod frame;
+pub mod graph;
 pub mod input;
 pub mod input_kind;
 pub mod output;
 pub mod output_kind;
 mod output_result;
 pub mod parameters;
+pub mod preset;
+pub mod stage;
 pub mod stream;
+pub mod subtitle_timing;
 
+use crate::caption_extractor::CaptionExtractor;
+use crate::filter_graph::GraphKind;
+use crate::filter_info::FilterInfo;
 use crate::frame::Frame;
+use crate::order::burn_in::BurnIn;
+use crate::order::caption::Caption;
+pub use crate::order::command::Command;
 use crate::order::decoder_format::DecoderFormat;
 use crate::order::encoder_format::EncoderFormat;
 pub use crate::order::filter::Filter;
 use crate::order::filter_input::FilterInput;
+pub use crate::order::graph::Graph;
 use crate::order::input::Input;
 use crate::order::input_kind::InputKind;
-use crate::order::output::Output;
+use crate::order::output::{Output, Timecode};
 use crate::order::output_kind::OutputKind;
 pub use crate::order::output_result::OutputResult;
 pub use crate::order::parameters::*;
+use crate::order::stage::{RunningStage, StageDescription, StageFactory};
 
 use crate::packet::Packet;
+use crate::subtitle::Subtitle;
+use crate::video_encoder::PassStats;
+use ffmpeg_sys_next::AVMediaType;
 use std::ptr::null_mut;
 
 #[derive(Debug, Deserialize)]
 pub struct Order {
   pub inputs: Vec<Input>,
   pub outputs: Vec<Output>,
-  pub graph: Vec<Filter>,
+  pub graph: Graph,
+  #[serde(default)]
+  pub burn_in: Vec<BurnIn>,
+  #[serde(default)]
+  pub captions: Vec<Caption>,
+  /// Filter commands, sent once the graph is configured or queued at their time.
+  #[serde(default)]
+  pub commands: Vec<Command>,
+  /// Rust processing stages of graph outputs.
+  #[serde(default)]
+  pub stages: Vec<StageDescription>,
+  #[serde(skip)]
+  stage_factories: HashMap<String, StageFactory>,
+  #[serde(skip)]
+  running_stages: Vec<RunningStage>,
   #[serde(skip)]
   pub total_streams: u32,
   #[serde(skip)]
   input_formats: Vec<DecoderFormat>,
   #[serde(skip)]
+  caption_extractors: Vec<CaptionExtractor>,
+  #[serde(skip)]
   output_formats: Vec<EncoderFormat>,
   #[serde(skip)]
+  first_pass: bool,
+  /// First pass statistics of each output format, indexed by stream.
+  #[serde(skip)]
+  pass_stats: Vec<HashMap<isize, PassStats>>,
+  #[serde(skip)]
   pub filter_graph: FilterGraph,
 }
 
 impl Order {
-  pub fn new(inputs: Vec<Input>, graph: Vec<Filter>, outputs: Vec<Output>) -> Result<Self, String> {
+  pub fn new(
+    inputs: Vec<Input>,
+    graph: impl Into<Graph>,
+    outputs: Vec<Output>,
+  ) -> Result<Self, String> {
     Ok(Order {
       inputs,
       outputs,
-      graph,
+      graph: graph.into(),
+      burn_in: vec![],
+      captions: vec![],
+      commands: vec![],
+      stages: vec![],
+      stage_factories: HashMap::new(),
+      running_stages: vec![],
       total_streams: 0,
       input_formats: vec![],
+      caption_extractors: vec![],
       output_formats: vec![],
+      first_pass: false,
+      pass_stats: vec![],
       filter_graph: FilterGraph::new()?,
     })
   }
@@ -62,30 +114,143 @@ impl Order {
     serde_json::from_str(message).map_err(|e| e.to_string())
   }
 
+  /// Make a stage available to the `stages` of the order, before its setup.
+  pub fn register_stage(&mut self, name: &str, factory: StageFactory) {
+    self.stage_factories.insert(name.to_string(), factory);
+  }
+
   pub fn setup(&mut self) -> Result<(), String> {
+    if self.is_two_pass() {
+      warn!("Run first pass");
+      self.first_pass = true;
+      self.build()?;
+      self.process()?;
+      self.pass_stats = self
+        .output_formats
+        .iter()
+        .map(|output| output.get_pass_stats())
+        .collect();
+      self.reset()?;
+      self.first_pass = false;
+    }
+    self.build()
+  }
+
+  /// Graphviz DOT export of the filter graph, also available when the setup failed partway.
+  pub fn graph_to_dot(&self) -> String {
+    self.filter_graph.to_dot()
+  }
+
+  /// Dump of the filter graph by libavfilter, once all its pads are linked.
+  pub fn dump_graph(&self) -> Result<String, String> {
+    self.filter_graph.dump()
+  }
+
+  /// Check the filters of the graph exist and accept their parameters, without running it.
+  /// Graph descriptions are only checked when parsed on setup.
+  pub fn validate_graph(&self) -> Result<(), String> {
+    if let Graph::Filters(filters) = &self.graph {
+      for filter in filters {
+        let filter_info = FilterInfo::find(&filter.name)
+          .ok_or_else(|| format!("Unknown filter {}", filter.name))?;
+        filter_info.validate_parameters(&filter.parameters)?;
+      }
+    }
+    Ok(())
+  }
+
+  fn is_two_pass(&self) -> bool {
+    self
+      .outputs
+      .iter()
+      .flat_map(|output| output.streams.iter())
+      .any(|stream| stream.two_pass)
+  }
+
+  /// Release the inputs, outputs and graph, to start again from the beginning of the inputs.
+  fn reset(&mut self) -> Result<(), String> {
+    self.output_formats.clear();
+    self.running_stages.clear();
+    self.caption_extractors.clear();
+    self.input_formats.clear();
+    self.filter_graph = FilterGraph::new()?;
+    self.total_streams = 0;
+    Ok(())
+  }
+
+  fn build(&mut self) -> Result<(), String> {
     warn!("Build inputs");
     self.build_input_format()?;
+    warn!("Build stages");
+    self.build_stages()?;
     warn!("Build outputs");
     self.build_output_format()?;
+    warn!("Build burn-in");
+    self.build_burn_in()?;
     warn!("Build graph");
     self.build_graph()?;
     warn!("{}", self.filter_graph);
 
-    self.filter_graph.validate()?;
+    if let Err(msg) = self.filter_graph.validate() {
+      debug!("{}", self.filter_graph.to_dot());
+      return Err(msg);
+    }
+    let video_label = self
+      .output_formats
+      .iter()
+      .flat_map(|output| output.video_encoders.iter())
+      .map(|encoder| encoder.identifier.clone())
+      .next();
+    for output in &mut self.output_formats {
+      output.log_negotiated_formats(&self.filter_graph);
+      output.set_input_time_bases(&self.filter_graph);
+      output.follow_video_cut(&self.filter_graph, video_label.as_deref())?;
+    }
+    for command in &self.commands {
+      self.send_command(command)?;
+    }
     Ok(())
   }
 
+  /// Send a command to the graph filters, or queue it when it has a time.
+  /// Commands can be sent between calls of `filtering` to change the filters mid-stream.
+  pub fn send_command(&self, command: &Command) -> Result<(), String> {
+    match command.time {
+      Some(time) => {
+        self
+          .filter_graph
+          .queue_command(&command.target, &command.command, &command.argument, time)
+      }
+      None => {
+        let response =
+          self
+            .filter_graph
+            .send_command(&command.target, &command.command, &command.argument)?;
+        if !response.is_empty() {
+          info!("{} {}: {response}", command.target, command.command);
+        }
+        Ok(())
+      }
+    }
+    .map_err(|msg| {
+      format!(
+        "Unable to send command {} to {}: {msg}",
+        command.command, command.target
+      )
+    })
+  }
+
   pub fn process(&mut self) -> Result<Vec<OutputResult>, String> {
     let mut results: Vec<OutputResult> = vec![];
     let mut decode_end = false;
 
     while !decode_end {
-      let (in_audio_frames, in_video_frames, in_subtitle_packets, end) = self.process_input();
+      let (in_audio_frames, in_video_frames, in_subtitles, end) = self.process_input();
       if end == self.total_streams {
         decode_end = true;
       }
 
-      match self.filtering(&in_audio_frames, &in_video_frames, &in_subtitle_packets) {
+      match self.filtering(&in_audio_frames, &in_video_frames, &in_subtitles) {
         Ok(result) => {
           results.extend(result);
         }
@@ -95,19 +260,92 @@ impl Order {
       }
     }
 
+    // frames held by burn-in overlays waiting for subtitles
+    self.filter_graph.close_subtitle_inputs()?;
+    results.extend(self.filtering(&[], &[], &[])?);
+
+    results.extend(self.flush_stages()?);
+    for output in &mut self.output_formats {
+      for packet in output.flush()? {
+        results.push(OutputResult::Packet(packet));
+      }
+    }
+
+    Ok(results)
+  }
+
+  /// Pass the frames of the stage inputs through their stages, chained stages included.
+  /// Their entries are added to the results and the other frames are returned.
+  fn run_stages(
+    &mut self,
+    frames: Vec<Frame>,
+    results: &mut Vec<OutputResult>,
+  ) -> Result<Vec<Frame>, String> {
+    let mut pending = frames;
+    pending.reverse();
+    let mut output_frames = vec![];
+    while let Some(frame) = pending.pop() {
+      let running_stage = self
+        .running_stages
+        .iter_mut()
+        .find(|running_stage| frame.name.as_ref() == Some(&running_stage.input));
+      let Some(running_stage) = running_stage else {
+        output_frames.push(frame);
+        continue;
+      };
+
+      let index = frame.index;
+      let result = running_stage.stage.process(frame)?;
+      results.extend(result.entries.into_iter().map(OutputResult::Entry));
+      if let Some(output) = &running_stage.output {
+        for mut stage_frame in result.frames.into_iter().rev() {
+          stage_frame.name = Some(output.clone());
+          stage_frame.index = index;
+          pending.push(stage_frame);
+        }
+      }
+    }
+    Ok(output_frames)
+  }
+
+  /// Flush the stages in order, so frames they hold go through the next stages and get encoded.
+  fn flush_stages(&mut self) -> Result<Vec<OutputResult>, String> {
+    let mut results = vec![];
+    for stage_index in 0..self.running_stages.len() {
+      let result = self.running_stages[stage_index].stage.flush()?;
+      results.extend(result.entries.into_iter().map(OutputResult::Entry));
+      let Some(output) = self.running_stages[stage_index].output.clone() else {
+        continue;
+      };
+      let frames = result
+        .frames
+        .into_iter()
+        .map(|mut frame| {
+          frame.name = Some(output.clone());
+          frame
+        })
+        .collect();
+      for frame in self.run_stages(frames, &mut results)? {
+        for output in &mut self.output_formats {
+          for packet in output.encode(&frame)? {
+            results.push(OutputResult::Packet(packet));
+          }
+        }
+      }
+    }
     Ok(results)
   }
 
-  pub fn process_input(&mut self) -> (Vec<Frame>, Vec<Frame>, Vec<Packet>, u32) {
+  pub fn process_input(&mut self) -> (Vec<Frame>, Vec<Frame>, Vec<Subtitle>, u32) {
     let mut audio_frames = vec![];
-    let mut subtitle_packets = vec![];
+    let mut subtitles = vec![];
     let mut video_frames = vec![];
     let mut end = 0;
 
     for format in &mut self.input_formats {
       for _ in 0..format.context.get_nb_streams() {
         match format.context.next_packet() {
-          Ok(mut packet) => {
+          Ok(packet) => {
             for decoder in &format.audio_decoders {
               if decoder.stream_index == packet.get_stream_index() {
                 if let Ok(frame) = decoder.decode(&packet) {
@@ -118,14 +356,24 @@ impl Order {
             for decoder in &format.video_decoders {
               if decoder.stream_index == packet.get_stream_index() {
                 if let Ok(frame) = decoder.decode(&packet) {
+                  extract_captions(&self.caption_extractors, &frame, &mut subtitles);
                   video_frames.push(frame);
                 }
               }
             }
             for decoder in &format.subtitle_decoders {
               if decoder.stream_index == packet.get_stream_index() {
-                packet.name = Some(decoder.identifier.clone());
-                subtitle_packets.push(packet);
+                // packets are kept for stream copy even when they hold no cue
+                let mut subtitle = match decoder.decode(&packet) {
+                  Ok(Some(subtitle)) => subtitle,
+                  Ok(None) => decoder.get_packet_subtitle(&packet),
+                  Err(msg) => {
+                    warn!("Unable to decode subtitle packet: {msg}");
+                    decoder.get_packet_subtitle(&packet)
+                  }
+                };
+                subtitle.packet = Some(packet);
+                subtitles.push(subtitle);
                 break;
               }
             }
@@ -138,6 +386,7 @@ impl Order {
                 let p = Packet { name: None, packet };
 
                 if let Ok(frame) = decoder.decode(&p) {
+                  extract_captions(&self.caption_extractors, &frame, &mut subtitles);
                   video_frames.push(frame);
                 } else {
                   end += 1;
@@ -151,20 +400,28 @@ impl Order {
       }
     }
 
-    (audio_frames, video_frames, subtitle_packets, end)
+    (audio_frames, video_frames, subtitles, end)
   }
 
   pub fn filtering(
     &mut self,
     in_audio_frames: &[Frame],
     in_video_frames: &[Frame],
-    in_subtitle_packets: &Vec<Packet>,
+    in_subtitles: &[Subtitle],
   ) -> Result<Vec<OutputResult>, String> {
     let mut results = vec![];
 
+    for subtitle in in_subtitles {
+      if subtitle.is_bitmap() {
+        self.filter_graph.push_subtitle(subtitle)?;
+      }
+    }
+
     let (output_audio_frames, output_video_frames) = self
       .filter_graph
       .process(in_audio_frames, in_video_frames)?;
+    let output_audio_frames = self.run_stages(output_audio_frames, &mut results)?;
+    let output_video_frames = self.run_stages(output_video_frames, &mut results)?;
     for output_frame in output_audio_frames {
       for output in &self.outputs {
         if output.stream == output_frame.name {
@@ -187,14 +444,16 @@ impl Order {
         }
       }
       for output in &mut self.output_formats {
-        if let Some(packet) = output.encode(&output_frame)? {
+        for packet in output.encode(&output_frame)? {
           results.push(OutputResult::Packet(packet));
-        };
+        }
       }
     }
-    for output_packet in in_subtitle_packets {
+    for subtitle in in_subtitles {
       for output in &mut self.output_formats {
-        output.wrap(output_packet)?;
+        for packet in output.encode_subtitle(subtitle)? {
+          results.push(OutputResult::Packet(packet));
+        }
       }
     }
     for output_frame in output_video_frames {
@@ -208,15 +467,17 @@ impl Order {
           for key in &output.keys {
             if let Some(value) = output_frame.get_metadata(key) {
               entry.insert(key.clone(), value);
+            } else if let Some(value) = output_frame.get_side_data_entry(key) {
+              entry.insert(key.clone(), value);
             }
           }
           results.push(OutputResult::Entry(entry));
         }
       }
       for output in &mut self.output_formats {
-        if let Some(packet) = output.encode(&output_frame)? {
+        for packet in output.encode(&output_frame)? {
           results.push(OutputResult::Packet(packet));
-        };
+        }
       }
     }
 
@@ -229,24 +490,136 @@ impl Order {
       self.total_streams += decoder.context.get_nb_streams();
       self.input_formats.push(decoder);
     }
+
+    for caption in &self.captions {
+      let video_decoder = self
+        .input_formats
+        .iter()
+        .flat_map(|format| format.video_decoders.iter())
+        .find(|decoder| decoder.identifier == caption.video)
+        .ok_or_else(|| format!("Unable to find video input stream {}", caption.video))?;
+
+      let extractor = CaptionExtractor::new(caption.label.clone(), video_decoder)?;
+      self.caption_extractors.push(extractor);
+    }
+    Ok(())
+  }
+
+  /// Create the stages, with a graph output for the ones processing graph frames.
+  fn build_stages(&mut self) -> Result<(), String> {
+    for (index, description) in self.stages.iter().enumerate() {
+      let factory = self.stage_factories.get(&description.name).ok_or_else(|| {
+        format!(
+          "Unknown stage {}, it must be registered with Order::register_stage",
+          description.name
+        )
+      })?;
+
+      let source_stage = self
+        .stages
+        .iter()
+        .position(|stage| stage.output.as_ref() == Some(&description.input));
+      match source_stage {
+        Some(source_index) if source_index >= index => {
+          return Err(format!(
+            "Stage {} must come after the stage producing {}",
+            description.name, description.input
+          ));
+        }
+        Some(_) => {}
+        None => match description.kind {
+          GraphKind::Audio => self.filter_graph.add_audio_output(&description.input)?,
+          GraphKind::Video => self.filter_graph.add_video_output(&description.input)?,
+        },
+      }
+      if let Some(output) = &description.output {
+        self
+          .filter_graph
+          .add_output_alias(output, &description.input);
+      }
+
+      self.running_stages.push(RunningStage {
+        input: description.input.clone(),
+        output: description.output.clone(),
+        stage: factory(description.parameters.clone())?,
+      });
+    }
     Ok(())
   }
 
   fn build_output_format(&mut self) -> Result<(), String> {
+    let subtitle_headers: HashMap<String, String> = self
+      .input_formats
+      .iter()
+      .flat_map(|format| format.subtitle_decoders.iter())
+      .filter_map(|decoder| {
+        decoder
+          .get_subtitle_header()
+          .map(|header| (decoder.identifier.clone(), header))
+      })
+      .collect();
+
     for output in &self.outputs {
       match output.kind {
         Some(OutputKind::File) | Some(OutputKind::Packet) => {
-          let encoder = EncoderFormat::new(&mut self.filter_graph, output)?;
+          let pass_stats = self
+            .pass_stats
+            .get(self.output_formats.len())
+            .cloned()
+            .unwrap_or_default();
+          let expanded_output;
+          let mut silent_tracks = None;
+          let output = match output.preset {
+            Some(preset) => {
+              // presets are checked against the first video input stream
+              let source = self
+                .input_formats
+                .iter()
+                .flat_map(|format| format.video_decoders.iter())
+                .next()
+                .ok_or_else(|| format!("The {preset} preset requires a video input stream"))?;
+              silent_tracks = preset.get_silent_tracks(output)?;
+              expanded_output = preset.expand(output, source)?;
+              &expanded_output
+            }
+            None => output,
+          };
+          let timecode = match &output.timecode {
+            Some(Timecode::Copy) => Some(
+              self
+                .input_formats
+                .iter()
+                .find_map(|format| format.get_timecode())
+                .ok_or_else(|| "Unable to find a timecode to copy in the inputs".to_string())?,
+            ),
+            Some(Timecode::Smpte(timecode)) => Some(timecode.clone()),
+            None => None,
+          };
+          let encoder = EncoderFormat::new(
+            &mut self.filter_graph,
+            output,
+            timecode.as_deref(),
+            self.first_pass,
+            &pass_stats,
+            &subtitle_headers,
+          )?;
+          if let Some((source, labels)) = silent_tracks {
+            self.filter_graph.add_silent_outputs(&source, &labels)?;
+          }
           self.output_formats.push(encoder);
         }
         Some(OutputKind::AudioMetadata) => {
           if let Some(ref identifier) = output.stream {
-            self.filter_graph.add_audio_output(identifier)?;
+            if !self.filter_graph.is_output_alias(identifier) {
+              self.filter_graph.add_audio_output(identifier)?;
+            }
           }
         }
         Some(OutputKind::VideoMetadata) => {
           if let Some(ref identifier) = output.stream {
-            self.filter_graph.add_video_output(identifier)?;
+            if !self.filter_graph.is_output_alias(identifier) {
+              self.filter_graph.add_video_output(identifier)?;
+            }
           }
         }
         None => {}
@@ -255,10 +628,133 @@ impl Order {
     Ok(())
   }
 
+  fn build_burn_in(&mut self) -> Result<(), String> {
+    for burn_in in &self.burn_in {
+      let subtitle_input = self.input_formats.iter().find_map(|format| {
+        format
+          .subtitle_decoders
+          .iter()
+          .find(|decoder| decoder.identifier == burn_in.subtitle)
+          .map(|decoder| (format, decoder))
+      });
+      let (format, subtitle_decoder) = subtitle_input
+        .ok_or_else(|| format!("Unable to find subtitle input stream {}", burn_in.subtitle))?;
+
+      if subtitle_decoder.is_bitmap() {
+        let video_decoder = self
+          .input_formats
+          .iter()
+          .flat_map(|format| format.video_decoders.iter())
+          .find(|decoder| decoder.identifier == burn_in.video)
+          .ok_or_else(|| format!("Unable to find video input stream {}", burn_in.video))?;
+
+        self.filter_graph.add_input_from_subtitle_decoder(
+          &burn_in.subtitle,
+          subtitle_decoder,
+          &burn_in.video,
+          video_decoder,
+        )?;
+
+        let mut parameters = HashMap::new();
+        parameters.insert(
+          "eof_action".to_string(),
+          ParameterValue::String("pass".to_string()),
+        );
+        parameters.extend(burn_in.parameters.clone());
+        let overlay = self.filter_graph.add_filter(&Filter {
+          name: "overlay".to_string(),
+          label: None,
+          parameters,
+          inputs: None,
+          outputs: None,
+        })?;
+
+        self
+          .filter_graph
+          .connect_input(&burn_in.video, 0, &overlay, 0)?;
+
+        let subtitle_input = self.filter_graph.subtitle_inputs.last().unwrap();
+        if subtitle_input.width != video_decoder.get_width()
+          || subtitle_input.height != video_decoder.get_height()
+        {
+          let mut parameters = HashMap::new();
+          parameters.insert(
+            "width".to_string(),
+            ParameterValue::Int64(i64::from(video_decoder.get_width())),
+          );
+          parameters.insert(
+            "height".to_string(),
+            ParameterValue::Int64(i64::from(video_decoder.get_height())),
+          );
+          let scale = self.filter_graph.add_filter(&Filter {
+            name: "scale".to_string(),
+            label: None,
+            parameters,
+            inputs: None,
+            outputs: None,
+          })?;
+          self
+            .filter_graph
+            .connect_input(&burn_in.subtitle, 0, &scale, 0)?;
+          self.filter_graph.connect(&scale, 0, &overlay, 1)?;
+        } else {
+          self
+            .filter_graph
+            .connect_input(&burn_in.subtitle, 0, &overlay, 1)?;
+        }
+
+        self
+          .filter_graph
+          .connect_output(&overlay, 0, &burn_in.output, 0)?;
+      } else {
+        // text subtitles are rendered by libass, reading the source file again
+        let subtitle_index = (0..subtitle_decoder.stream_index)
+          .filter(|index| unsafe {
+            format.context.get_stream_type(*index) == AVMediaType::AVMEDIA_TYPE_SUBTITLE
+          })
+          .count();
+
+        let mut parameters = HashMap::new();
+        parameters.insert(
+          "filename".to_string(),
+          ParameterValue::String(format.context.filename.clone()),
+        );
+        parameters.insert(
+          "si".to_string(),
+          ParameterValue::Int64(subtitle_index as i64),
+        );
+        parameters.extend(burn_in.parameters.clone());
+        let subtitles = self.filter_graph.add_filter(&Filter {
+          name: "subtitles".to_string(),
+          label: None,
+          parameters,
+          inputs: None,
+          outputs: None,
+        })?;
+
+        self
+          .filter_graph
+          .connect_input(&burn_in.video, 0, &subtitles, 0)?;
+        self
+          .filter_graph
+          .connect_output(&subtitles, 0, &burn_in.output, 0)?;
+      }
+    }
+    Ok(())
+  }
+
   fn build_graph(&mut self) -> Result<Vec<crate::filter::Filter>, String> {
     let mut filters = vec![];
 
-    for filter_description in &self.graph {
+    let filter_descriptions = match &self.graph {
+      Graph::Description(description) => {
+        self.filter_graph.parse(description)?;
+        return Ok(filters);
+      }
+      Graph::Filters(filter_descriptions) => filter_descriptions,
+    };
+
+    for filter_description in filter_descriptions {
       let filter = self.filter_graph.add_filter(filter_description)?;
       if let Some(ref inputs) = filter_description.inputs {
         for (index, input) in inputs.iter().enumerate() {
@@ -316,6 +812,20 @@ impl Order {
   }
 }
 
+fn extract_captions(extractors: &[CaptionExtractor], frame: &Frame, subtitles: &mut Vec<Subtitle>) {
+  for extractor in extractors {
+    if frame.name.as_ref() == Some(&extractor.video_identifier) {
+      match extractor.extract(frame) {
+        Ok(Some(subtitle)) => subtitles.push(subtitle),
+        Ok(None) => {}
+        Err(msg) => {
+          warn!("Unable to extract captions: {msg}");
+        }
+      }
+    }
+  }
+}
+
 #[test]
 fn parse_sample_audio_encoding_graph() {
   use crate::order::filter_output::FilterOutput;
@@ -374,21 +884,26 @@ fn parse_sample_audio_encoding_graph() {
   assert_eq!(
     vec![Output {
       kind: Some(OutputKind::File),
+      format: None,
       keys: vec![],
       path: Some("out.wav".to_string()),
+      preset: None,
+      timecode: None,
       stream: None,
       parameters: HashMap::new(),
       streams: vec![OutputStream {
         label: Some("output1".to_string()),
         codec: "pcm_s24le".to_string(),
-        parameters: output_params
+        parameters: output_params,
+        timing: vec![],
+        two_pass: false
       }]
     }],
     order.outputs
   );
 
   assert_eq!(
-    vec![
+    Graph::Filters(vec![
       Filter {
         name: "amerge".to_string(),
         label: Some("amerge_filter".to_string()),
@@ -414,7 +929,7 @@ fn parse_sample_audio_encoding_graph() {
           stream_label: "output1".to_string()
         }])
       }
-    ],
+    ]),
     order.graph
   );
 }
@@ -513,25 +1028,34 @@ fn parse_sample_video_encoding_graph() {
   assert_eq!(
     vec![Output {
       kind: Some(OutputKind::File),
+      format: None,
       keys: vec![],
       path: Some("video_encoding.mxf".to_string()),
+      preset: None,
+      timecode: None,
       stream: None,
       parameters: HashMap::new(),
       streams: vec![
         OutputStream {
           label: Some("output1".to_string()),
           codec: "mpeg2video".to_string(),
-          parameters: output_video_params
+          parameters: output_video_params,
+          timing: vec![],
+          two_pass: false
         },
         OutputStream {
           label: Some("audio_output1".to_string()),
           codec: "pcm_s24le".to_string(),
-          parameters: output_audio1_params
+          parameters: output_audio1_params,
+          timing: vec![],
+          two_pass: false
         },
         OutputStream {
           label: Some("audio_output2".to_string()),
           codec: "pcm_s24le".to_string(),
-          parameters: output_audio2_params
+          parameters: output_audio2_params,
+          timing: vec![],
+          two_pass: false
         }
       ]
     }],
@@ -539,7 +1063,7 @@ fn parse_sample_video_encoding_graph() {
   );
 
   assert_eq!(
-    vec![
+    Graph::Filters(vec![
       Filter {
         name: "idet".to_string(),
         label: Some("idet_filter".to_string()),
@@ -583,7 +1107,115 @@ fn parse_sample_video_encoding_graph() {
           stream_label: "audio_output2".to_string()
         }])
       }
-    ],
+    ]),
     order.graph
   );
 }
+
+#[test]
+fn parse_graph_description() {
+  let order = Order::new_parse(
+    r#"{
+      "inputs": [],
+      "graph": "[audio]aformat=sample_fmts=s32,volume=0.5[output]",
+      "outputs": []
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(
+    order.graph,
+    Graph::Description("[audio]aformat=sample_fmts=s32,volume=0.5[output]".to_string())
+  );
+}
+
+#[test]
+fn parse_commands() {
+  let order = Order::new_parse(
+    r#"{
+      "inputs": [],
+      "graph": "[audio]volume@gain=1.0[output]",
+      "outputs": [],
+      "commands": [
+        { "target": "gain", "command": "volume", "argument": "0.5", "time": 12.5 },
+        { "target": "all", "command": "reinit" }
+      ]
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(
+    order.commands,
+    vec![
+      Command {
+        target: "gain".to_string(),
+        command: "volume".to_string(),
+        argument: "0.5".to_string(),
+        time: Some(12.5),
+      },
+      Command {
+        target: "all".to_string(),
+        command: "reinit".to_string(),
+        argument: "".to_string(),
+        time: None,
+      }
+    ]
+  );
+}
+
+#[test]
+fn run_stages() {
+  use crate::order::stage::{Stage, StageResult};
+  use ffmpeg_sys_next::av_frame_alloc;
+
+  #[derive(Debug)]
+  struct Counter {
+    count: usize,
+  }
+
+  impl Stage for Counter {
+    fn process(&mut self, frame: Frame) -> Result<StageResult, String> {
+      self.count += 1;
+      let mut entry = HashMap::new();
+      entry.insert("count".to_string(), self.count.to_string());
+      Ok(StageResult {
+        frames: vec![frame],
+        entries: vec![entry],
+      })
+    }
+  }
+
+  fn new_counter(_: HashMap<String, ParameterValue>) -> Result<Box<dyn Stage>, String> {
+    Ok(Box::new(Counter { count: 0 }))
+  }
+
+  let mut order = Order::new_parse(
+    r#"{
+      "inputs": [],
+      "graph": [],
+      "outputs": [],
+      "stages": [
+        { "name": "counter", "kind": "video", "input": "graph_output", "output": "counted" }
+      ]
+    }"#,
+  )
+  .unwrap();
+  order.register_stage("counter", new_counter);
+  order.build_stages().unwrap();
+  assert!(order.filter_graph.is_output_alias("counted"));
+
+  let frames = ["graph_output", "other"]
+    .iter()
+    .map(|name| Frame {
+      name: Some(name.to_string()),
+      frame: unsafe { av_frame_alloc() },
+      index: 0,
+    })
+    .collect();
+  let mut results = vec![];
+  let frames = order.run_stages(frames, &mut results).unwrap();
+  let names: Vec<Option<String>> = frames.iter().map(|frame| frame.name.clone()).collect();
+  assert_eq!(
+    names,
+    vec![Some("counted".to_string()), Some("other".to_string())]
+  );
+  assert_eq!(results.len(), 1);
+}
@@ -1,123 +1,197 @@
-use crate::order::{output_kind::OutputKind, parameters::ParameterValue};
+pub use crate::channel_layout::ChannelLayout;
+use crate::{
+  order::{
+    output_kind::OutputKind, parameters::ParameterValue, preset::Preset,
+    subtitle_timing::SubtitleTiming,
+  },
+  tools,
+};
 use ffmpeg_sys_next::*;
-use std::{collections::HashMap, convert::TryFrom, str::FromStr};
+use regex::Regex;
+use std::{
+  collections::HashMap, convert::TryFrom, ffi::CString, fmt, str::FromStr, sync::OnceLock,
+};
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
-pub enum SampleFormat {
-  #[serde(rename = "s8")]
-  Unsigned8,
-  #[serde(rename = "s8p")]
-  Unsigned8Planar,
-  #[serde(rename = "s16")]
-  Signed16,
-  #[serde(rename = "s16p")]
-  Signed16Planar,
-  #[serde(rename = "s32")]
-  Signed32,
-  #[serde(rename = "s32p")]
-  Signed32Planar,
-  #[serde(rename = "float")]
-  Float,
-  #[serde(rename = "floatp")]
-  FloatPlanar,
-  #[serde(rename = "double")]
-  Double,
-  #[serde(rename = "doublep")]
-  DoublePlanar,
+/// Audio sample format, any of the libavutil ones (`s16`, `s32p`, `fltp`...).
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct SampleFormat {
+  format: AVSampleFormat,
+}
+
+impl SampleFormat {
+  pub fn get_name(&self) -> String {
+    unsafe { tools::to_string(av_get_sample_fmt_name(self.format)) }
+  }
+
+  pub fn get_bytes_per_sample(&self) -> i32 {
+    unsafe { av_get_bytes_per_sample(self.format) }
+  }
+
+  pub fn get_bit_depth(&self) -> i32 {
+    self.get_bytes_per_sample() * 8
+  }
+
+  pub fn is_planar(&self) -> bool {
+    unsafe { av_sample_fmt_is_planar(self.format) != 0 }
+  }
+
+  /// Same format with interleaved samples.
+  pub fn get_packed(&self) -> Self {
+    SampleFormat {
+      format: unsafe { av_get_packed_sample_fmt(self.format) },
+    }
+  }
+
+  /// Same format with a plane per channel.
+  pub fn get_planar(&self) -> Self {
+    SampleFormat {
+      format: unsafe { av_get_planar_sample_fmt(self.format) },
+    }
+  }
 }
 
 impl TryFrom<i32> for SampleFormat {
   type Error = String;
   fn try_from(value: i32) -> Result<Self, Self::Error> {
-    match value {
-      0 => Ok(SampleFormat::Unsigned8),
-      1 => Ok(SampleFormat::Signed16),
-      2 => Ok(SampleFormat::Signed32),
-      3 => Ok(SampleFormat::Float),
-      4 => Ok(SampleFormat::Double),
-      5 => Ok(SampleFormat::Unsigned8Planar),
-      6 => Ok(SampleFormat::Signed16Planar),
-      7 => Ok(SampleFormat::Signed32Planar),
-      8 => Ok(SampleFormat::FloatPlanar),
-      9 => Ok(SampleFormat::DoublePlanar),
-      _ => Err(format!("'{value}' is not a valid value for SampleFormat")),
+    if value < 0 || value >= AVSampleFormat::AV_SAMPLE_FMT_NB as i32 {
+      return Err(format!("'{value}' is not a valid value for SampleFormat"));
     }
+    Ok(SampleFormat {
+      format: unsafe { std::mem::transmute::<i32, AVSampleFormat>(value) },
+    })
+  }
+}
+
+impl TryFrom<String> for SampleFormat {
+  type Error = String;
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
   }
 }
 
 impl FromStr for SampleFormat {
   type Err = String;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    match s {
-      "s8" => Ok(SampleFormat::Unsigned8),
-      "s8p" => Ok(SampleFormat::Unsigned8Planar),
-      "s16" => Ok(SampleFormat::Signed16),
-      "s16p" => Ok(SampleFormat::Signed16Planar),
-      "s32" => Ok(SampleFormat::Signed32),
-      "s32p" => Ok(SampleFormat::Signed32Planar),
-      "float" => Ok(SampleFormat::Float),
-      "floatp" => Ok(SampleFormat::FloatPlanar),
-      "double" => Ok(SampleFormat::Double),
-      "doublep" => Ok(SampleFormat::DoublePlanar),
-      _ => Err(format!("'{s}' is not a valid value for SampleFormat")),
+    let error = || format!("'{s}' is not a valid value for SampleFormat");
+    // names accepted before the formats were backed by libavutil
+    let name = match s {
+      "float" => "flt",
+      "floatp" => "fltp",
+      "double" => "dbl",
+      "doublep" => "dblp",
+      "s8" => "u8",
+      name => name,
+    };
+    let name = CString::new(name).map_err(|_| error())?;
+    let format = unsafe { av_get_sample_fmt(name.as_ptr()) };
+    if format == AVSampleFormat::AV_SAMPLE_FMT_NONE {
+      return Err(error());
     }
+    Ok(SampleFormat { format })
   }
 }
 
 impl From<SampleFormat> for AVSampleFormat {
   fn from(sample: SampleFormat) -> AVSampleFormat {
-    match sample {
-      SampleFormat::Unsigned8 => AVSampleFormat::AV_SAMPLE_FMT_U8,
-      SampleFormat::Unsigned8Planar => AVSampleFormat::AV_SAMPLE_FMT_U8P,
-      SampleFormat::Signed16 => AVSampleFormat::AV_SAMPLE_FMT_S16,
-      SampleFormat::Signed16Planar => AVSampleFormat::AV_SAMPLE_FMT_S16P,
-      SampleFormat::Signed32 => AVSampleFormat::AV_SAMPLE_FMT_S32,
-      SampleFormat::Signed32Planar => AVSampleFormat::AV_SAMPLE_FMT_S32P,
-      SampleFormat::Float => AVSampleFormat::AV_SAMPLE_FMT_FLT,
-      SampleFormat::FloatPlanar => AVSampleFormat::AV_SAMPLE_FMT_FLTP,
-      SampleFormat::Double => AVSampleFormat::AV_SAMPLE_FMT_DBL,
-      SampleFormat::DoublePlanar => AVSampleFormat::AV_SAMPLE_FMT_DBLP,
+    sample.format
+  }
+}
+
+impl fmt::Display for SampleFormat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.get_name())
+  }
+}
+
+/// Pixel format, any of the libavutil ones (`yuv422p10le`, `rgb48le`, `nv12`...).
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct PixelFormat {
+  format: AVPixelFormat,
+}
+
+impl PixelFormat {
+  fn get_descriptor(&self) -> &AVPixFmtDescriptor {
+    // formats are checked on creation, so they always have a descriptor
+    unsafe { &*av_pix_fmt_desc_get(self.format) }
+  }
+
+  pub fn get_name(&self) -> String {
+    unsafe { tools::to_string(self.get_descriptor().name) }
+  }
+
+  pub fn get_nb_components(&self) -> u8 {
+    self.get_descriptor().nb_components
+  }
+
+  /// Bits per component, of the first one for formats mixing depths.
+  pub fn get_bit_depth(&self) -> i32 {
+    self.get_descriptor().comp[0].depth
+  }
+
+  /// Log2 of the horizontal and vertical chroma subsampling, e.g. (1, 0) for 4:2:2.
+  pub fn get_chroma_subsampling(&self) -> (u8, u8) {
+    let descriptor = self.get_descriptor();
+    (descriptor.log2_chroma_w, descriptor.log2_chroma_h)
+  }
+
+  pub fn is_planar(&self) -> bool {
+    self.get_descriptor().flags & AV_PIX_FMT_FLAG_PLANAR as u64 != 0
+  }
+
+  pub fn is_rgb(&self) -> bool {
+    self.get_descriptor().flags & AV_PIX_FMT_FLAG_RGB as u64 != 0
+  }
+
+  pub fn has_alpha(&self) -> bool {
+    self.get_descriptor().flags & AV_PIX_FMT_FLAG_ALPHA as u64 != 0
+  }
+}
+
+impl TryFrom<i32> for PixelFormat {
+  type Error = String;
+  fn try_from(value: i32) -> Result<Self, Self::Error> {
+    if value < 0 || value >= AVPixelFormat::AV_PIX_FMT_NB as i32 {
+      return Err(format!("'{value}' is not a valid value for PixelFormat"));
     }
+    let format = unsafe { std::mem::transmute::<i32, AVPixelFormat>(value) };
+    if unsafe { av_pix_fmt_desc_get(format) }.is_null() {
+      return Err(format!("'{value}' is not a valid value for PixelFormat"));
+    }
+    Ok(PixelFormat { format })
   }
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
-pub enum PixelFormat {
-  #[serde(rename = "yuv420p")]
-  Yuv420p,
-  #[serde(rename = "yuv422p")]
-  Yuv422p,
-  #[serde(rename = "rgb24")]
-  Rgb24,
-  #[serde(rename = "rgb48be")]
-  Rgb48be,
-  #[serde(rename = "rgb48le")]
-  Rgb48le,
-}
-
-impl std::str::FromStr for PixelFormat {
+impl TryFrom<String> for PixelFormat {
+  type Error = String;
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+impl FromStr for PixelFormat {
   type Err = String;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    match s {
-      "yuv420p" => Ok(PixelFormat::Yuv420p),
-      "yuv422p" => Ok(PixelFormat::Yuv422p),
-      "rgb24" => Ok(PixelFormat::Rgb24),
-      "rgb48be" => Ok(PixelFormat::Rgb48be),
-      "rgb48le" => Ok(PixelFormat::Rgb48le),
-      _ => Err(format!("'{s}' is not a valid value for PixelFormat")),
+    let error = || format!("'{s}' is not a valid value for PixelFormat");
+    let name = CString::new(s).map_err(|_| error())?;
+    let format = unsafe { av_get_pix_fmt(name.as_ptr()) };
+    if format == AVPixelFormat::AV_PIX_FMT_NONE {
+      return Err(error());
     }
+    Ok(PixelFormat { format })
   }
 }
 
 impl From<PixelFormat> for AVPixelFormat {
   fn from(format: PixelFormat) -> AVPixelFormat {
-    match format {
-      PixelFormat::Yuv420p => AVPixelFormat::AV_PIX_FMT_YUV420P,
-      PixelFormat::Yuv422p => AVPixelFormat::AV_PIX_FMT_YUV422P,
-      PixelFormat::Rgb24 => AVPixelFormat::AV_PIX_FMT_RGB24,
-      PixelFormat::Rgb48be => AVPixelFormat::AV_PIX_FMT_RGB48BE,
-      PixelFormat::Rgb48le => AVPixelFormat::AV_PIX_FMT_RGB48LE,
-    }
+    format.format
+  }
+}
+
+impl fmt::Display for PixelFormat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.get_name())
   }
 }
 
@@ -201,38 +275,289 @@ impl From<ColorRange> for AVColorRange {
   }
 }
 
+/// Color primaries, named as in libavutil.
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
-pub enum ChannelLayout {
-  #[serde(rename = "mono")]
-  Mono,
-  #[serde(rename = "stereo")]
-  Stereo,
-  #[serde(rename = "5.1")]
-  Multi5_1,
-  #[serde(rename = "7.1")]
-  Multi7_1,
-}
-
-impl std::str::FromStr for ChannelLayout {
+pub enum ColorPrimaries {
+  #[serde(rename = "bt709")]
+  Bt709,
+  #[serde(rename = "bt470m")]
+  Bt470m,
+  #[serde(rename = "bt470bg")]
+  Bt470bg,
+  #[serde(rename = "smpte170m")]
+  Smpte170m,
+  #[serde(rename = "smpte240m")]
+  Smpte240m,
+  #[serde(rename = "film")]
+  Film,
+  #[serde(rename = "bt2020")]
+  Bt2020,
+  #[serde(rename = "smpte428")]
+  Smpte428,
+  #[serde(rename = "smpte431")]
+  Smpte431,
+  #[serde(rename = "smpte432")]
+  Smpte432,
+}
+
+impl std::str::FromStr for ColorPrimaries {
   type Err = String;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
-      "mono" => Ok(ChannelLayout::Mono),
-      "stereo" => Ok(ChannelLayout::Stereo),
-      "5.1" => Ok(ChannelLayout::Multi5_1),
-      "7.1" => Ok(ChannelLayout::Multi7_1),
-      _ => Err(format!("'{s}' is not a valid value for ChannelLayout")),
+      "bt709" => Ok(ColorPrimaries::Bt709),
+      "bt470m" => Ok(ColorPrimaries::Bt470m),
+      "bt470bg" => Ok(ColorPrimaries::Bt470bg),
+      "smpte170m" => Ok(ColorPrimaries::Smpte170m),
+      "smpte240m" => Ok(ColorPrimaries::Smpte240m),
+      "film" => Ok(ColorPrimaries::Film),
+      "bt2020" => Ok(ColorPrimaries::Bt2020),
+      "smpte428" => Ok(ColorPrimaries::Smpte428),
+      "smpte431" => Ok(ColorPrimaries::Smpte431),
+      "smpte432" => Ok(ColorPrimaries::Smpte432),
+      _ => Err(format!("'{s}' is not a valid value for ColorPrimaries")),
     }
   }
 }
 
-impl From<ChannelLayout> for u64 {
-  fn from(layout: ChannelLayout) -> u64 {
-    match layout {
-      ChannelLayout::Mono => AV_CH_LAYOUT_MONO,
-      ChannelLayout::Stereo => AV_CH_LAYOUT_STEREO,
-      ChannelLayout::Multi5_1 => AV_CH_LAYOUT_5POINT1,
-      ChannelLayout::Multi7_1 => AV_CH_LAYOUT_7POINT1,
+impl From<ColorPrimaries> for AVColorPrimaries {
+  fn from(primaries: ColorPrimaries) -> AVColorPrimaries {
+    match primaries {
+      ColorPrimaries::Bt709 => AVColorPrimaries::AVCOL_PRI_BT709,
+      ColorPrimaries::Bt470m => AVColorPrimaries::AVCOL_PRI_BT470M,
+      ColorPrimaries::Bt470bg => AVColorPrimaries::AVCOL_PRI_BT470BG,
+      ColorPrimaries::Smpte170m => AVColorPrimaries::AVCOL_PRI_SMPTE170M,
+      ColorPrimaries::Smpte240m => AVColorPrimaries::AVCOL_PRI_SMPTE240M,
+      ColorPrimaries::Film => AVColorPrimaries::AVCOL_PRI_FILM,
+      ColorPrimaries::Bt2020 => AVColorPrimaries::AVCOL_PRI_BT2020,
+      ColorPrimaries::Smpte428 => AVColorPrimaries::AVCOL_PRI_SMPTE428,
+      ColorPrimaries::Smpte431 => AVColorPrimaries::AVCOL_PRI_SMPTE431,
+      ColorPrimaries::Smpte432 => AVColorPrimaries::AVCOL_PRI_SMPTE432,
+    }
+  }
+}
+
+/// Transfer characteristics, named as in libavutil.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub enum ColorTransfer {
+  #[serde(rename = "bt709")]
+  Bt709,
+  #[serde(rename = "gamma22")]
+  Gamma22,
+  #[serde(rename = "gamma28")]
+  Gamma28,
+  #[serde(rename = "smpte170m")]
+  Smpte170m,
+  #[serde(rename = "smpte240m")]
+  Smpte240m,
+  #[serde(rename = "linear")]
+  Linear,
+  #[serde(rename = "iec61966-2-1")]
+  Iec61966_2_1,
+  #[serde(rename = "bt2020-10")]
+  Bt2020_10,
+  #[serde(rename = "bt2020-12")]
+  Bt2020_12,
+  #[serde(rename = "smpte2084")]
+  Smpte2084,
+  #[serde(rename = "smpte428")]
+  Smpte428,
+  #[serde(rename = "arib-std-b67")]
+  AribStdB67,
+}
+
+impl std::str::FromStr for ColorTransfer {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "bt709" => Ok(ColorTransfer::Bt709),
+      "gamma22" => Ok(ColorTransfer::Gamma22),
+      "gamma28" => Ok(ColorTransfer::Gamma28),
+      "smpte170m" => Ok(ColorTransfer::Smpte170m),
+      "smpte240m" => Ok(ColorTransfer::Smpte240m),
+      "linear" => Ok(ColorTransfer::Linear),
+      "iec61966-2-1" => Ok(ColorTransfer::Iec61966_2_1),
+      "bt2020-10" => Ok(ColorTransfer::Bt2020_10),
+      "bt2020-12" => Ok(ColorTransfer::Bt2020_12),
+      "smpte2084" => Ok(ColorTransfer::Smpte2084),
+      "smpte428" => Ok(ColorTransfer::Smpte428),
+      "arib-std-b67" => Ok(ColorTransfer::AribStdB67),
+      _ => Err(format!("'{s}' is not a valid value for ColorTransfer")),
+    }
+  }
+}
+
+impl From<ColorTransfer> for AVColorTransferCharacteristic {
+  fn from(transfer: ColorTransfer) -> AVColorTransferCharacteristic {
+    match transfer {
+      ColorTransfer::Bt709 => AVColorTransferCharacteristic::AVCOL_TRC_BT709,
+      ColorTransfer::Gamma22 => AVColorTransferCharacteristic::AVCOL_TRC_GAMMA22,
+      ColorTransfer::Gamma28 => AVColorTransferCharacteristic::AVCOL_TRC_GAMMA28,
+      ColorTransfer::Smpte170m => AVColorTransferCharacteristic::AVCOL_TRC_SMPTE170M,
+      ColorTransfer::Smpte240m => AVColorTransferCharacteristic::AVCOL_TRC_SMPTE240M,
+      ColorTransfer::Linear => AVColorTransferCharacteristic::AVCOL_TRC_LINEAR,
+      ColorTransfer::Iec61966_2_1 => AVColorTransferCharacteristic::AVCOL_TRC_IEC61966_2_1,
+      ColorTransfer::Bt2020_10 => AVColorTransferCharacteristic::AVCOL_TRC_BT2020_10,
+      ColorTransfer::Bt2020_12 => AVColorTransferCharacteristic::AVCOL_TRC_BT2020_12,
+      ColorTransfer::Smpte2084 => AVColorTransferCharacteristic::AVCOL_TRC_SMPTE2084,
+      ColorTransfer::Smpte428 => AVColorTransferCharacteristic::AVCOL_TRC_SMPTE428,
+      ColorTransfer::AribStdB67 => AVColorTransferCharacteristic::AVCOL_TRC_ARIB_STD_B67,
+    }
+  }
+}
+
+/// Location of the chroma samples, named as in libavutil.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub enum ChromaLocation {
+  #[serde(rename = "left")]
+  Left,
+  #[serde(rename = "center")]
+  Center,
+  #[serde(rename = "topleft")]
+  TopLeft,
+  #[serde(rename = "top")]
+  Top,
+  #[serde(rename = "bottomleft")]
+  BottomLeft,
+  #[serde(rename = "bottom")]
+  Bottom,
+}
+
+impl std::str::FromStr for ChromaLocation {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "left" => Ok(ChromaLocation::Left),
+      "center" => Ok(ChromaLocation::Center),
+      "topleft" => Ok(ChromaLocation::TopLeft),
+      "top" => Ok(ChromaLocation::Top),
+      "bottomleft" => Ok(ChromaLocation::BottomLeft),
+      "bottom" => Ok(ChromaLocation::Bottom),
+      _ => Err(format!("'{s}' is not a valid value for ChromaLocation")),
+    }
+  }
+}
+
+impl From<ChromaLocation> for AVChromaLocation {
+  fn from(location: ChromaLocation) -> AVChromaLocation {
+    match location {
+      ChromaLocation::Left => AVChromaLocation::AVCHROMA_LOC_LEFT,
+      ChromaLocation::Center => AVChromaLocation::AVCHROMA_LOC_CENTER,
+      ChromaLocation::TopLeft => AVChromaLocation::AVCHROMA_LOC_TOPLEFT,
+      ChromaLocation::Top => AVChromaLocation::AVCHROMA_LOC_TOP,
+      ChromaLocation::BottomLeft => AVChromaLocation::AVCHROMA_LOC_BOTTOMLEFT,
+      ChromaLocation::Bottom => AVChromaLocation::AVCHROMA_LOC_BOTTOM,
+    }
+  }
+}
+
+/// Field order of the encoded video, `tt` and `tb` being top field first.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum FieldOrder {
+  #[default]
+  #[serde(rename = "progressive")]
+  Progressive,
+  #[serde(rename = "tt", alias = "tff")]
+  TopFirst,
+  #[serde(rename = "bb", alias = "bff")]
+  BottomFirst,
+  /// Top field coded first, bottom field displayed first.
+  #[serde(rename = "tb")]
+  TopCodedFirst,
+  /// Bottom field coded first, top field displayed first.
+  #[serde(rename = "bt")]
+  BottomCodedFirst,
+}
+
+impl FieldOrder {
+  pub fn is_interlaced(&self) -> bool {
+    *self != FieldOrder::Progressive
+  }
+
+  pub fn is_top_field_first(&self) -> bool {
+    matches!(self, FieldOrder::TopFirst | FieldOrder::TopCodedFirst)
+  }
+}
+
+impl std::str::FromStr for FieldOrder {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "progressive" => Ok(FieldOrder::Progressive),
+      "tt" | "tff" => Ok(FieldOrder::TopFirst),
+      "bb" | "bff" => Ok(FieldOrder::BottomFirst),
+      "tb" => Ok(FieldOrder::TopCodedFirst),
+      "bt" => Ok(FieldOrder::BottomCodedFirst),
+      _ => Err(format!("'{s}' is not a valid value for FieldOrder")),
+    }
+  }
+}
+
+impl From<FieldOrder> for AVFieldOrder {
+  fn from(field_order: FieldOrder) -> AVFieldOrder {
+    match field_order {
+      FieldOrder::Progressive => AVFieldOrder::AV_FIELD_PROGRESSIVE,
+      FieldOrder::TopFirst => AVFieldOrder::AV_FIELD_TT,
+      FieldOrder::BottomFirst => AVFieldOrder::AV_FIELD_BB,
+      FieldOrder::TopCodedFirst => AVFieldOrder::AV_FIELD_TB,
+      FieldOrder::BottomCodedFirst => AVFieldOrder::AV_FIELD_BT,
+    }
+  }
+}
+
+/// Start timecode of an output.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum Timecode {
+  /// Start timecode of the inputs.
+  Copy,
+  /// SMPTE timecode, `hh:mm:ss:ff` or `hh:mm:ss;ff` for drop frame.
+  Smpte(String),
+}
+
+impl TryFrom<String> for Timecode {
+  type Error = String;
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+/// `hh:mm:ss:ff`, or `hh:mm:ss;ff` for drop frame timecodes.
+static TIMECODE_REGEX: OnceLock<Regex> = OnceLock::new();
+
+impl FromStr for Timecode {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s == "copy" {
+      return Ok(Timecode::Copy);
+    }
+    let regex =
+      TIMECODE_REGEX.get_or_init(|| Regex::new(r"^\d{2}:\d{2}:\d{2}[:;]\d{2,3}$").unwrap());
+    if !regex.is_match(s) {
+      return Err(format!("'{s}' is not a valid value for Timecode"));
+    }
+    Ok(Timecode::Smpte(s.to_string()))
+  }
+}
+
+/// How video encoders timestamp frames.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub enum TimestampMode {
+  /// Keep the filter output timestamps, rescaled to the encoder time base (variable frame rate).
+  #[default]
+  #[serde(rename = "passthrough")]
+  Passthrough,
+  /// Number frames one after another from the first timestamp (constant frame rate).
+  #[serde(rename = "cfr")]
+  Cfr,
+}
+
+impl std::str::FromStr for TimestampMode {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "passthrough" | "vfr" => Ok(TimestampMode::Passthrough),
+      "cfr" => Ok(TimestampMode::Cfr),
+      _ => Err(format!("'{s}' is not a valid value for TimestampMode")),
     }
   }
 }
@@ -240,19 +565,81 @@ impl From<ChannelLayout> for u64 {
 #[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct OutputStream {
   pub label: Option<String>,
+  /// Encoder name, optional when set by the output preset.
+  #[serde(default)]
   pub codec: String,
   pub parameters: HashMap<String, ParameterValue>,
+  /// Timing transforms applied to subtitle streams before encoding.
+  #[serde(default)]
+  pub timing: Vec<SubtitleTiming>,
+  /// Encode video in two passes, the first one only collects the rate control statistics.
+  #[serde(default)]
+  pub two_pass: bool,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct Output {
   pub kind: Option<OutputKind>,
+  /// Container format name, guessed from the path by default.
+  #[serde(default)]
+  pub format: Option<String>,
   #[serde(default)]
   pub keys: Vec<String>,
   #[serde(default)]
   pub parameters: HashMap<String, ParameterValue>,
   pub path: Option<String>,
+  /// Delivery preset setting the codecs, parameters and container of the streams.
+  #[serde(default)]
+  pub preset: Option<Preset>,
   pub stream: Option<String>,
   #[serde(default)]
   pub streams: Vec<OutputStream>,
+  /// Start timecode written in the container, and as a timecode track where supported.
+  #[serde(default)]
+  pub timecode: Option<Timecode>,
+}
+
+#[test]
+fn pixel_and_sample_formats() {
+  let format: PixelFormat = "yuv422p10le".parse().unwrap();
+  assert_eq!(format.to_string(), "yuv422p10le");
+  assert_eq!(format.get_bit_depth(), 10);
+  assert_eq!(format.get_chroma_subsampling(), (1, 0));
+  assert!(format.is_planar());
+  assert!(!format.has_alpha());
+  assert!("rgba".parse::<PixelFormat>().unwrap().has_alpha());
+  assert!("v210".parse::<PixelFormat>().is_err());
+
+  let format: SampleFormat = "s32p".parse().unwrap();
+  assert_eq!(format.get_bit_depth(), 32);
+  assert!(format.is_planar());
+  assert_eq!(format.get_packed().to_string(), "s32");
+  assert_eq!(SampleFormat::try_from(3).unwrap().to_string(), "flt");
+  assert!(SampleFormat::try_from(-1).is_err());
+  assert!("s24".parse::<SampleFormat>().is_err());
+  assert_eq!("float".parse::<SampleFormat>().unwrap().to_string(), "flt");
+  assert_eq!(
+    "doublep".parse::<SampleFormat>().unwrap().to_string(),
+    "dblp"
+  );
+  assert_eq!("s8".parse::<SampleFormat>().unwrap().to_string(), "u8");
+}
+
+#[test]
+fn parse_timecode() {
+  assert_eq!("copy".parse(), Ok(Timecode::Copy));
+  assert_eq!(
+    "10:00:00;00".parse(),
+    Ok(Timecode::Smpte("10:00:00;00".to_string()))
+  );
+  assert!("10:00:00".parse::<Timecode>().is_err());
+  assert!("10:00:00.00".parse::<Timecode>().is_err());
+
+  let output: Output =
+    serde_json::from_str(r#"{"kind": "file", "path": "out.mov", "timecode": "01:00:00:00"}"#)
+      .unwrap();
+  assert_eq!(
+    output.timecode,
+    Some(Timecode::Smpte("01:00:00:00".to_string()))
+  );
 }
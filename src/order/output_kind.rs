@@ -1,4 +1,4 @@
-#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub enum OutputKind {
   #[serde(rename = "file")]
   File,
@@ -1,6 +1,6 @@
-use crate::{tools, tools::rational::Rational};
+use crate::{channel_layout::ChannelLayout, tools, tools::rational::Rational};
 use ffmpeg_sys_next::*;
-use libc::{c_char, c_void};
+use libc::c_void;
 use std::{collections::HashMap, ffi::CString, hash::BuildHasher};
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -24,6 +24,27 @@ pub fn set_parameters<S: BuildHasher>(
   Ok(())
 }
 
+/// Set the parameters which are not handled by the encoder itself as options of the codec
+/// context or of its private data (preset, crf, profile, x264-params...), before it is opened.
+pub fn set_codec_options<S: BuildHasher>(
+  codec_context: *mut AVCodecContext,
+  parameters: &HashMap<String, ParameterValue, S>,
+  handled_keys: &[&str],
+) -> Result<(), String> {
+  let mut keys: Vec<&String> = parameters
+    .keys()
+    .filter(|key| !handled_keys.contains(&key.as_str()))
+    .collect();
+  keys.sort();
+
+  for key in keys {
+    parameters[key]
+      .set(key, codec_context as *mut c_void)
+      .map_err(|error| format!("Unable to set codec option '{key}': {error}"))?;
+  }
+  Ok(())
+}
+
 impl ParameterValue {
   pub fn set(&self, key: &str, context: *mut c_void) -> Result<(), String> {
     match self {
@@ -35,33 +56,12 @@ impl ParameterValue {
       }
       ParameterValue::String(data) => self.set_str_parameter(context, key, data),
       ParameterValue::ChannelLayout(data) => {
-        let mut ch_layout = [0; 64];
-        unsafe {
-          av_get_channel_layout_string(ch_layout.as_mut_ptr(), 64, 0, *data);
-        }
-        self.set_parameter(context, key, ch_layout.as_ptr())
+        let channel_layout = ChannelLayout::from_mask(*data)?;
+        self.set_str_parameter(context, key, &channel_layout.to_string())
       }
     }
   }
 
-  fn set_parameter(
-    &self,
-    context: *mut c_void,
-    key: &str,
-    value: *const c_char,
-  ) -> Result<(), String> {
-    let key_str = CString::new(key).unwrap();
-    unsafe {
-      check_result!(av_opt_set(
-        context,
-        key_str.as_ptr(),
-        value,
-        AV_OPT_SEARCH_CHILDREN
-      ));
-    }
-    Ok(())
-  }
-
   fn set_str_parameter(&self, context: *mut c_void, key: &str, value: &str) -> Result<(), String> {
     let key_str = CString::new(key).unwrap();
     let value_str = CString::new(value).unwrap();
@@ -123,3 +123,41 @@ impl ParameterValue {
     Ok(())
   }
 }
+
+#[test]
+fn set_private_codec_options() {
+  unsafe {
+    let codec = avcodec_find_encoder(AVCodecID::AV_CODEC_ID_MPEG2VIDEO);
+    let mut codec_context = avcodec_alloc_context3(codec);
+
+    let mut parameters = HashMap::new();
+    parameters.insert("intra_vlc".to_string(), ParameterValue::Bool(true));
+    // handled keys are left to the encoder, whatever their value
+    parameters.insert(
+      "width".to_string(),
+      ParameterValue::String("wide".to_string()),
+    );
+    assert_eq!(
+      set_codec_options(codec_context, &parameters, &["width"]),
+      Ok(())
+    );
+    let key = CString::new("intra_vlc").unwrap();
+    let mut value = 0;
+    assert_eq!(
+      av_opt_get_int(
+        codec_context as *mut c_void,
+        key.as_ptr(),
+        AV_OPT_SEARCH_CHILDREN,
+        &mut value
+      ),
+      0
+    );
+    assert_eq!(value, 1);
+
+    parameters.insert("not_an_option".to_string(), ParameterValue::Int64(1));
+    let error = set_codec_options(codec_context, &parameters, &["width"]).unwrap_err();
+    assert!(error.starts_with("Unable to set codec option 'not_an_option'"));
+
+    avcodec_free_context(&mut codec_context);
+  }
+}
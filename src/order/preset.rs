@@ -0,0 +1,516 @@
+use crate::{
+  order::{
+    output::{Output, OutputStream},
+    parameters::ParameterValue,
+  },
+  tools::rational::Rational,
+  video_decoder::VideoDecoder,
+};
+use std::{collections::HashMap, fmt};
+
+/// Quantization matrices of XDCAM HD422 streams, the MPEG-2 default ones written in the
+/// sequence headers.
+const XDCAM_INTRA_MATRIX: &str = "8,16,19,22,26,27,29,34,16,16,22,24,27,29,34,37,\
+  19,22,26,27,29,34,34,38,22,22,26,27,29,34,37,40,22,26,27,29,32,35,40,48,\
+  26,27,29,32,35,40,48,58,26,27,29,34,38,46,56,69,27,29,35,38,46,56,69,83";
+const XDCAM_INTER_MATRIX: &str = "16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,\
+  16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,\
+  16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16,16";
+
+/// Delivery preset of an output: the first stream is encoded as the video,
+/// the following ones as the audio tracks.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub enum Preset {
+  #[serde(rename = "xdcam_hd422")]
+  XdcamHd422,
+  #[serde(rename = "avc_intra_50")]
+  AvcIntra50,
+  #[serde(rename = "avc_intra_100")]
+  AvcIntra100,
+  #[serde(rename = "dnxhd_hq")]
+  DnxhdHq,
+  #[serde(rename = "dnxhr_hq")]
+  DnxhrHq,
+  #[serde(rename = "prores_422")]
+  ProRes422,
+  #[serde(rename = "prores_422_hq")]
+  ProRes422Hq,
+  #[serde(rename = "prores_4444")]
+  ProRes4444,
+}
+
+/// Video format accepted by a preset.
+struct VideoFormat {
+  width: i32,
+  height: i32,
+  frame_rate: Rational,
+  interlaced: bool,
+}
+
+impl VideoFormat {
+  fn new(width: i32, height: i32, num: i32, den: i32, interlaced: bool) -> Self {
+    VideoFormat {
+      width,
+      height,
+      frame_rate: Rational::new(num, den),
+      interlaced,
+    }
+  }
+}
+
+impl Preset {
+  pub fn get_container(&self) -> &'static str {
+    match self {
+      Preset::XdcamHd422 | Preset::AvcIntra50 | Preset::AvcIntra100 | Preset::DnxhdHq => "mxf",
+      Preset::DnxhrHq | Preset::ProRes422 | Preset::ProRes422Hq | Preset::ProRes4444 => "mov",
+    }
+  }
+
+  pub fn get_video_codec(&self) -> &'static str {
+    match self {
+      Preset::XdcamHd422 => "mpeg2video",
+      Preset::AvcIntra50 | Preset::AvcIntra100 => "libx264",
+      Preset::DnxhdHq | Preset::DnxhrHq => "dnxhd",
+      Preset::ProRes422 | Preset::ProRes422Hq | Preset::ProRes4444 => "prores_ks",
+    }
+  }
+
+  pub fn get_audio_codec(&self) -> &'static str {
+    "pcm_s24le"
+  }
+
+  pub fn get_max_audio_tracks(&self) -> usize {
+    match self.get_container() {
+      "mxf" => 8,
+      _ => 16,
+    }
+  }
+
+  /// Number of audio tracks of the deliveries with a fixed track layout.
+  fn get_audio_tracks(&self) -> Option<usize> {
+    match self {
+      Preset::XdcamHd422 => Some(8),
+      _ => None,
+    }
+  }
+
+  /// Labels of the silent tracks completing the audio tracks of the output, with the label of
+  /// the audio stream whose frames drive them. Outputs without audio stream are left as is.
+  pub fn get_silent_tracks(
+    &self,
+    output: &Output,
+  ) -> Result<Option<(String, Vec<String>)>, String> {
+    let (Some(audio_tracks), Some(audio_stream)) = (self.get_audio_tracks(), output.streams.get(1))
+    else {
+      return Ok(None);
+    };
+    if output.streams.len() > audio_tracks {
+      return Ok(None);
+    }
+    let label = audio_stream.label.clone().ok_or_else(|| {
+      format!(
+        "The {self} preset requires a labelled first audio stream to complete {audio_tracks} tracks"
+      )
+    })?;
+    let labels = (output.streams.len()..=audio_tracks)
+      .map(|index| format!("{label}_silence_{index}"))
+      .collect();
+    Ok(Some((label, labels)))
+  }
+
+  /// Formats of the broadcast presets, resolution independent presets have none.
+  fn get_video_formats(&self) -> Vec<VideoFormat> {
+    match self {
+      Preset::XdcamHd422 | Preset::AvcIntra50 | Preset::AvcIntra100 => vec![
+        VideoFormat::new(1920, 1080, 25, 1, true),
+        VideoFormat::new(1920, 1080, 30000, 1001, true),
+        VideoFormat::new(1280, 720, 50, 1, false),
+        VideoFormat::new(1280, 720, 60000, 1001, false),
+      ],
+      Preset::DnxhdHq => vec![
+        VideoFormat::new(1920, 1080, 24000, 1001, false),
+        VideoFormat::new(1920, 1080, 25, 1, true),
+        VideoFormat::new(1920, 1080, 30000, 1001, true),
+        VideoFormat::new(1280, 720, 50, 1, false),
+        VideoFormat::new(1280, 720, 60000, 1001, false),
+      ],
+      _ => vec![],
+    }
+  }
+
+  /// Video stream parameters for the source format.
+  fn get_video_parameters(&self, format: Option<&VideoFormat>) -> HashMap<String, ParameterValue> {
+    let mut parameters = HashMap::new();
+    let mut insert = |key: &str, value: ParameterValue| {
+      parameters.insert(key.to_string(), value);
+    };
+    let string = |value: &str| ParameterValue::String(value.to_string());
+    let interlaced = format.is_some_and(|format| format.interlaced);
+
+    match self {
+      Preset::XdcamHd422 => {
+        let bitrate = 50_000_000;
+        let buffer_size = 17_825_792;
+        let gop_size = match format {
+          Some(format) if format.frame_rate == Rational::new(25, 1) => 12,
+          Some(format) if format.frame_rate == Rational::new(50, 1) => 12,
+          _ => 15,
+        };
+        insert("pixel_format", string("yuv422p"));
+        insert("bitrate", ParameterValue::Int64(bitrate));
+        insert("minrate", ParameterValue::Int64(bitrate));
+        insert("maxrate", ParameterValue::Int64(bitrate));
+        insert("bufsize", ParameterValue::Int64(buffer_size));
+        insert("rc_init_occupancy", ParameterValue::Int64(buffer_size));
+        insert("gop_size", ParameterValue::Int64(gop_size));
+        insert("max_b_frames", ParameterValue::Int64(2));
+        insert("profile", ParameterValue::Int64(0));
+        insert("level", ParameterValue::Int64(2));
+        insert("dc", ParameterValue::Int64(2));
+        insert("intra_vlc", ParameterValue::Bool(true));
+        insert("non_linear_quant", ParameterValue::Bool(true));
+        insert("qmin", ParameterValue::Int64(1));
+        insert("qmax", ParameterValue::Int64(12));
+        insert("intra_matrix", string(XDCAM_INTRA_MATRIX));
+        insert("inter_matrix", string(XDCAM_INTER_MATRIX));
+        insert("color_range", string("head"));
+        if interlaced {
+          insert("field_order", string("tt"));
+          insert("alternate_scan", ParameterValue::Bool(true));
+        }
+      }
+      Preset::AvcIntra50 | Preset::AvcIntra100 => {
+        let (class, pixel_format) = match self {
+          Preset::AvcIntra50 => (50, "yuv420p10le"),
+          _ => (100, "yuv422p10le"),
+        };
+        insert("pixel_format", string(pixel_format));
+        insert("avcintra-class", ParameterValue::Int64(class));
+        // AVC-Intra 50 is horizontally subsampled, the graph scales the frames
+        if let (Preset::AvcIntra50, Some(format)) = (self, format) {
+          let width = if format.height == 1080 { 1440 } else { 960 };
+          insert("width", ParameterValue::Int64(width));
+          insert("height", ParameterValue::Int64(i64::from(format.height)));
+          insert(
+            "sample_aspect_ratio",
+            ParameterValue::Rational(Rational::new(4, 3)),
+          );
+        }
+        insert("color_range", string("head"));
+        if interlaced {
+          insert("field_order", string("tt"));
+          insert("interlaced_me", ParameterValue::Bool(false));
+        }
+      }
+      Preset::DnxhdHq => {
+        let bitrate = match format {
+          Some(format) if format.height == 720 => {
+            if format.frame_rate == Rational::new(50, 1) {
+              180_000_000
+            } else {
+              220_000_000
+            }
+          }
+          Some(format) if format.frame_rate == Rational::new(24000, 1001) => 175_000_000,
+          Some(format) if format.frame_rate == Rational::new(25, 1) => 185_000_000,
+          _ => 220_000_000,
+        };
+        insert("pixel_format", string("yuv422p"));
+        insert("bitrate", ParameterValue::Int64(bitrate));
+        insert("color_range", string("head"));
+        if interlaced {
+          insert("field_order", string("tt"));
+          insert("interlaced_me", ParameterValue::Bool(false));
+        }
+      }
+      Preset::DnxhrHq => {
+        insert("pixel_format", string("yuv422p"));
+        insert("profile", string("dnxhr_hq"));
+      }
+      Preset::ProRes422 | Preset::ProRes422Hq | Preset::ProRes4444 => {
+        let (profile, codec_tag, pixel_format) = match self {
+          Preset::ProRes422 => (2, "apcn", "yuv422p10le"),
+          Preset::ProRes422Hq => (3, "apch", "yuv422p10le"),
+          _ => (4, "ap4h", "yuva444p10le"),
+        };
+        insert("pixel_format", string(pixel_format));
+        insert("profile", ParameterValue::Int64(profile));
+        insert("codec_tag", string(codec_tag));
+        insert("vendor", string("apl0"));
+      }
+    }
+    parameters
+  }
+
+  fn get_audio_parameters(&self) -> HashMap<String, ParameterValue> {
+    let mut parameters = HashMap::new();
+    parameters.insert(
+      "sample_rate".to_string(),
+      ParameterValue::Rational(Rational::new(48000, 1)),
+    );
+    parameters.insert(
+      "sample_fmt".to_string(),
+      ParameterValue::String("s32".to_string()),
+    );
+    if self.get_container() == "mxf" {
+      parameters.insert(
+        "channel_layout".to_string(),
+        ParameterValue::String("mono".to_string()),
+      );
+    }
+    parameters
+  }
+
+  /// Check the source against the formats of the preset.
+  fn find_video_format(&self, source: &VideoDecoder) -> Result<Option<VideoFormat>, String> {
+    let formats = self.get_video_formats();
+    if formats.is_empty() {
+      return Ok(None);
+    }
+
+    let width = source.get_width();
+    let height = source.get_height();
+    let frame_rate = source.get_frame_rate();
+    if frame_rate.num <= 0 || frame_rate.den <= 0 {
+      return Err(format!(
+        "Unable to use the {self} preset, unknown frame rate of {}",
+        source.identifier
+      ));
+    }
+    let frame_rate = frame_rate.reduce();
+
+    formats
+      .into_iter()
+      .find(|format| {
+        format.width == width && format.height == height && format.frame_rate == frame_rate
+      })
+      .map(Some)
+      .ok_or_else(|| {
+        format!(
+          "The {self} preset does not support {width}x{height} at {}/{} fps",
+          frame_rate.num, frame_rate.den
+        )
+      })
+  }
+
+  /// Expand the preset into the streams and container of the output, the parameters
+  /// set on the output taking precedence.
+  pub fn expand(&self, output: &Output, source: &VideoDecoder) -> Result<Output, String> {
+    if output.streams.is_empty() {
+      return Err(format!("The {self} preset requires a video output stream"));
+    }
+    if output.streams.len() - 1 > self.get_max_audio_tracks() {
+      return Err(format!(
+        "The {self} preset supports up to {} audio tracks",
+        self.get_max_audio_tracks()
+      ));
+    }
+
+    let video_format = self.find_video_format(source)?;
+    let mut video_parameters = self.get_video_parameters(video_format.as_ref());
+    video_parameters
+      .entry("width".to_string())
+      .or_insert_with(|| ParameterValue::Int64(i64::from(source.get_width())));
+    video_parameters
+      .entry("height".to_string())
+      .or_insert_with(|| ParameterValue::Int64(i64::from(source.get_height())));
+    let frame_rate = source.get_frame_rate();
+    if frame_rate.num > 0 && frame_rate.den > 0 {
+      video_parameters.insert(
+        "frame_rate".to_string(),
+        ParameterValue::Rational(frame_rate),
+      );
+    }
+
+    let mut streams = output
+      .streams
+      .iter()
+      .enumerate()
+      .map(|(index, stream)| {
+        let (codec, mut parameters) = if index == 0 {
+          (self.get_video_codec(), video_parameters.clone())
+        } else {
+          (self.get_audio_codec(), self.get_audio_parameters())
+        };
+        if !stream.codec.is_empty() && stream.codec != codec {
+          return Err(format!(
+            "The {self} preset encodes stream {index} with {codec}, not {}",
+            stream.codec
+          ));
+        }
+        parameters.extend(stream.parameters.clone());
+        Ok(OutputStream {
+          codec: codec.to_string(),
+          parameters,
+          ..stream.clone()
+        })
+      })
+      .collect::<Result<Vec<OutputStream>, String>>()?;
+
+    if let Some((_, labels)) = self.get_silent_tracks(output)? {
+      streams.extend(labels.into_iter().map(|label| OutputStream {
+        label: Some(label),
+        codec: self.get_audio_codec().to_string(),
+        parameters: self.get_audio_parameters(),
+        timing: vec![],
+        two_pass: false,
+      }));
+    }
+
+    Ok(Output {
+      format: output
+        .format
+        .clone()
+        .or_else(|| Some(self.get_container().to_string())),
+      preset: None,
+      streams,
+      ..output.clone()
+    })
+  }
+}
+
+impl fmt::Display for Preset {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Preset::XdcamHd422 => "XDCAM HD422",
+      Preset::AvcIntra50 => "AVC-Intra 50",
+      Preset::AvcIntra100 => "AVC-Intra 100",
+      Preset::DnxhdHq => "DNxHD HQ",
+      Preset::DnxhrHq => "DNxHR HQ",
+      Preset::ProRes422 => "ProRes 422",
+      Preset::ProRes422Hq => "ProRes 422 HQ",
+      Preset::ProRes4444 => "ProRes 4444",
+    };
+    write!(f, "{name}")
+  }
+}
+
+#[test]
+fn parse_preset_output() {
+  use crate::tools;
+
+  let output: Output = serde_json::from_str(
+    r#"{
+      "kind": "file",
+      "path": "xdcam.mxf",
+      "preset": "xdcam_hd422",
+      "streams": [
+        { "label": "video", "parameters": {} },
+        { "label": "audio", "parameters": {} }
+      ]
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(output.preset, Some(Preset::XdcamHd422));
+  assert_eq!(output.format, None);
+  assert_eq!(Preset::XdcamHd422.to_string(), "XDCAM HD422");
+  assert_eq!(Preset::ProRes422Hq.get_container(), "mov");
+
+  let parameters = Preset::XdcamHd422.get_video_parameters(None);
+  assert_eq!(
+    parameters.get("bitrate"),
+    Some(&ParameterValue::Int64(50_000_000))
+  );
+  assert_eq!(
+    tools::codec_tag_from_fourcc("apch"),
+    Ok(u32::from_le_bytes(*b"apch"))
+  );
+  assert!(tools::codec_tag_from_fourcc("apc").is_err());
+}
+
+#[test]
+fn expand_preset_output() {
+  use ffmpeg_sys_next::{avcodec_alloc_context3, AVRational};
+
+  let source = |width: i32, height: i32, num: i32, den: i32| unsafe {
+    let codec_context = avcodec_alloc_context3(std::ptr::null());
+    (*codec_context).width = width;
+    (*codec_context).height = height;
+    (*codec_context).framerate = AVRational { num, den };
+    VideoDecoder {
+      identifier: "source".to_string(),
+      stream_index: 0,
+      codec_context,
+      bar_data: Default::default(),
+    }
+  };
+  let output: Output = serde_json::from_str(
+    r#"{
+      "kind": "file",
+      "path": "delivery.mxf",
+      "streams": [
+        { "label": "video", "parameters": {} },
+        { "label": "audio_1", "parameters": {} },
+        { "label": "audio_2", "parameters": {"sample_fmt": "s16"} }
+      ]
+    }"#,
+  )
+  .unwrap();
+
+  let expanded = Preset::XdcamHd422
+    .expand(&output, &source(1920, 1080, 25, 1))
+    .unwrap();
+  assert_eq!(expanded.format, Some("mxf".to_string()));
+  assert_eq!(expanded.streams.len(), 9);
+  let video = &expanded.streams[0].parameters;
+  assert_eq!(video.get("gop_size"), Some(&ParameterValue::Int64(12)));
+  assert_eq!(
+    video.get("field_order"),
+    Some(&ParameterValue::String("tt".to_string()))
+  );
+  assert!(video.contains_key("intra_matrix"));
+  assert!(video.contains_key("inter_matrix"));
+  assert_eq!(
+    expanded.streams[2].parameters.get("sample_fmt"),
+    Some(&ParameterValue::String("s16".to_string()))
+  );
+  let labels: Vec<Option<String>> = expanded.streams[3..]
+    .iter()
+    .map(|stream| stream.label.clone())
+    .collect();
+  assert_eq!(labels[0], Some("audio_1_silence_3".to_string()));
+  assert_eq!(labels[5], Some("audio_1_silence_8".to_string()));
+  for stream in &expanded.streams[1..] {
+    assert_eq!(stream.codec, "pcm_s24le");
+    assert_eq!(
+      stream.parameters.get("channel_layout"),
+      Some(&ParameterValue::String("mono".to_string()))
+    );
+  }
+  assert_eq!(
+    Preset::XdcamHd422.get_silent_tracks(&output),
+    Ok(Some((
+      "audio_1".to_string(),
+      (3..=8)
+        .map(|index| format!("audio_1_silence_{index}"))
+        .collect()
+    )))
+  );
+
+  let expanded = Preset::AvcIntra50
+    .expand(&output, &source(1920, 1080, 25, 1))
+    .unwrap();
+  assert_eq!(expanded.streams.len(), 3);
+  let video = &expanded.streams[0].parameters;
+  assert_eq!(video.get("width"), Some(&ParameterValue::Int64(1440)));
+  assert_eq!(video.get("height"), Some(&ParameterValue::Int64(1080)));
+  let expanded = Preset::AvcIntra50
+    .expand(&output, &source(1280, 720, 50, 1))
+    .unwrap();
+  assert_eq!(
+    expanded.streams[0].parameters.get("width"),
+    Some(&ParameterValue::Int64(960))
+  );
+
+  let expanded = Preset::DnxhdHq
+    .expand(&output, &source(1920, 1080, 24000, 1001))
+    .unwrap();
+  assert_eq!(
+    expanded.streams[0].parameters.get("bitrate"),
+    Some(&ParameterValue::Int64(175_000_000))
+  );
+
+  assert!(Preset::XdcamHd422
+    .expand(&output, &source(1920, 1080, 24, 1))
+    .is_err());
+}
@@ -0,0 +1,45 @@
+use crate::{filter_graph::GraphKind, frame::Frame, order::parameters::ParameterValue};
+use std::{collections::HashMap, fmt};
+
+/// Rust processing of the frames of a graph output, for analyses which are not filters.
+pub trait Stage: fmt::Debug {
+  /// Process a frame, returning the frames passed on (possibly the same one) and entries.
+  fn process(&mut self, frame: Frame) -> Result<StageResult, String>;
+
+  /// Return what remains once all frames were processed.
+  fn flush(&mut self) -> Result<StageResult, String> {
+    Ok(StageResult::default())
+  }
+}
+
+#[derive(Default)]
+pub struct StageResult {
+  pub frames: Vec<Frame>,
+  /// Metadata entries, returned as `OutputResult::Entry`.
+  pub entries: Vec<HashMap<String, String>>,
+}
+
+/// Create a stage from its parameters, registered with `Order::register_stage`.
+pub type StageFactory = fn(HashMap<String, ParameterValue>) -> Result<Box<dyn Stage>, String>;
+
+/// Stage of an order, between a graph output and the outputs or other stages.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct StageDescription {
+  /// Name of the registered stage.
+  pub name: String,
+  pub kind: GraphKind,
+  /// Label of the graph output, or of another stage output, providing the frames.
+  pub input: String,
+  /// Label of the frames returned by the stage, to be referenced by outputs or stages.
+  /// They keep the format and time base of the input.
+  pub output: Option<String>,
+  #[serde(default)]
+  pub parameters: HashMap<String, ParameterValue>,
+}
+
+#[derive(Debug)]
+pub(crate) struct RunningStage {
+  pub input: String,
+  pub output: Option<String>,
+  pub stage: Box<dyn Stage>,
+}
@@ -0,0 +1,122 @@
+use crate::tools::rational::Rational;
+
+/// Source interval in milliseconds, the end is excluded.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Segment {
+  pub start: i64,
+  pub end: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum SubtitleTiming {
+  /// Shift subtitles by a constant offset in milliseconds.
+  #[serde(rename = "offset")]
+  Offset { milliseconds: i64 },
+  /// Retime subtitles from a source frame rate to a target frame rate,
+  /// e.g. from 25 to 24000/1001 when conforming a master.
+  #[serde(rename = "scale")]
+  Scale { from: Rational, to: Rational },
+  /// Keep only the segments, spliced one after another. Without segments, the subtitles
+  /// follow the in and out points of the `trim` filters cutting the video of the order.
+  #[serde(rename = "cut")]
+  Cut { segments: Option<Vec<Segment>> },
+}
+
+impl SubtitleTiming {
+  /// Returns the new start and end times, or None when the subtitle is cut out.
+  pub fn apply(&self, start: i64, end: i64) -> Option<(i64, i64)> {
+    match self {
+      SubtitleTiming::Offset { milliseconds } => {
+        let end = end + milliseconds;
+        if end <= 0 {
+          return None;
+        }
+        Some(((start + milliseconds).max(0), end))
+      }
+      SubtitleTiming::Scale { from, to } => {
+        let num = i128::from(from.num) * i128::from(to.den);
+        let den = i128::from(from.den) * i128::from(to.num);
+        if num <= 0 || den <= 0 {
+          return None;
+        }
+        Some((rescale(start, num, den), rescale(end, num, den)))
+      }
+      SubtitleTiming::Cut { segments } => {
+        let Some(segments) = segments else {
+          return Some((start, end));
+        };
+        let mut offset = 0;
+        let mut retimed: Option<(i64, i64)> = None;
+        for segment in segments {
+          if start < segment.end && end > segment.start {
+            let segment_start = start.max(segment.start) - segment.start + offset;
+            let segment_end = end.min(segment.end) - segment.start + offset;
+            retimed = match retimed {
+              Some((first_start, _)) => Some((first_start, segment_end)),
+              None => Some((segment_start, segment_end)),
+            };
+          }
+          offset += segment.end - segment.start;
+        }
+        retimed
+      }
+    }
+  }
+}
+
+/// Apply the timings in order, returns None when the subtitle is cut out.
+pub fn retime(timings: &[SubtitleTiming], start: i64, end: i64) -> Option<(i64, i64)> {
+  timings
+    .iter()
+    .try_fold((start, end), |(start, end), timing| {
+      timing.apply(start, end)
+    })
+}
+
+fn rescale(value: i64, num: i128, den: i128) -> i64 {
+  ((i128::from(value) * num + den / 2) / den) as i64
+}
+
+#[test]
+fn subtitle_timing() {
+  let timings: Vec<SubtitleTiming> = serde_json::from_str(
+    r#"[
+      {"kind": "cut", "segments": [{"start": 1000, "end": 5000}, {"start": 10000, "end": 12000}]},
+      {"kind": "scale", "from": {"num": 25, "den": 1}, "to": {"num": 24000, "den": 1001}},
+      {"kind": "offset", "milliseconds": 500}
+    ]"#,
+  )
+  .unwrap();
+
+  assert_eq!(
+    timings[0],
+    SubtitleTiming::Cut {
+      segments: Some(vec![
+        Segment {
+          start: 1000,
+          end: 5000
+        },
+        Segment {
+          start: 10000,
+          end: 12000
+        }
+      ])
+    }
+  );
+
+  assert_eq!(timings[1].apply(24000, 48000), Some((25025, 50050)));
+  assert_eq!(timings[2].apply(-1000, 200), Some((0, 700)));
+  assert_eq!(timings[2].apply(-1000, -600), None);
+
+  assert_eq!(timings[0].apply(0, 500), None);
+  assert_eq!(timings[0].apply(4500, 10500), Some((3500, 4500)));
+  assert_eq!(timings[0].apply(11000, 13000), Some((5000, 6000)));
+
+  let video_cut: SubtitleTiming = serde_json::from_str(r#"{"kind": "cut"}"#).unwrap();
+  assert_eq!(video_cut, SubtitleTiming::Cut { segments: None });
+  assert_eq!(video_cut.apply(6000, 7000), Some((6000, 7000)));
+
+  assert_eq!(retime(&timings, 6000, 7000), None);
+  assert_eq!(retime(&timings, 2000, 3000), Some((1543, 2585)));
+}
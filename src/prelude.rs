@@ -15,6 +15,7 @@
 pub use crate::{
   audio_decoder::AudioDecoder,
   audio_encoder::AudioEncoder,
+  channel_layout::ChannelLayout,
   check_result,
   filter_graph::FilterGraph,
   format_context::FormatContext,
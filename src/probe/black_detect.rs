@@ -79,12 +79,15 @@ pub fn create_graph(
     });
     outputs.push(Output {
       kind: Some(OutputKind::VideoMetadata),
+      format: None,
       keys: vec![
         "lavfi.black_start".to_string(),
         "lavfi.black_end".to_string(),
       ],
       stream: Some(output_identifier),
       path: None,
+      preset: None,
+      timecode: None,
       streams: vec![],
       parameters: HashMap::new(),
     });
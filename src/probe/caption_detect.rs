@@ -0,0 +1,129 @@
+use crate::{
+  order::caption::Caption,
+  probe::deep::{
+    CaptionGapResult, CaptionResult, CheckParameterValue, StreamProbeResult, VideoDetails,
+  },
+  subtitle::Subtitle,
+};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Debug, Default)]
+pub struct CaptionTrack {
+  present: bool,
+  cues: Vec<(i64, i64)>,
+}
+
+pub fn captions_init(video_indexes: Vec<u32>) -> Vec<Caption> {
+  video_indexes
+    .iter()
+    .map(|index| Caption {
+      video: format!("video_input_{index}"),
+      label: format!("caption_output_{index}"),
+    })
+    .collect()
+}
+
+pub fn collect_captions(tracks: &mut BTreeMap<usize, CaptionTrack>, subtitles: &[Subtitle]) {
+  for subtitle in subtitles {
+    if let Some(name) = &subtitle.name {
+      if !name.starts_with("caption_output_") {
+        continue;
+      }
+      let track = tracks.entry(subtitle.index).or_default();
+      track.present = true;
+      if !subtitle.is_empty() {
+        track.cues.push((subtitle.start, subtitle.end));
+      }
+    }
+  }
+}
+
+pub fn detect_captions(
+  tracks: &BTreeMap<usize, CaptionTrack>,
+  streams: &mut [StreamProbeResult],
+  video_indexes: Vec<u32>,
+  params: HashMap<String, CheckParameterValue>,
+  video_details: VideoDetails,
+) {
+  let min_duration = params
+    .get("duration")
+    .and_then(|duration| duration.min)
+    .unwrap_or(0) as i64;
+  // cues are timed like the frames, from the start of the stream
+  let stream_start = video_details
+    .stream_start_time
+    .map_or(0, |start_time| (start_time * 1000.0) as i64);
+  let stream_end = video_details
+    .stream_duration
+    .map(|duration| stream_start + (duration * 1000.0) as i64);
+
+  for index in video_indexes {
+    let track = tracks.get(&(index as usize)).cloned().unwrap_or_default();
+    let mut cues = track.cues.clone();
+    cues.sort_unstable();
+
+    let mut gaps = vec![];
+    let mut position = stream_start;
+    for (start, end) in cues {
+      if start - position >= min_duration && start > position {
+        gaps.push(CaptionGapResult {
+          start: position,
+          end: start,
+        });
+      }
+      position = position.max(end);
+    }
+    if let Some(stream_end) = stream_end {
+      if stream_end - position >= min_duration && stream_end > position {
+        gaps.push(CaptionGapResult {
+          start: position,
+          end: stream_end,
+        });
+      }
+    }
+
+    streams[index as usize].detected_captions = Some(CaptionResult {
+      present: track.present,
+      gaps,
+    });
+  }
+}
+
+#[test]
+fn caption_gaps_from_stream_start() {
+  let mut tracks = BTreeMap::new();
+  tracks.insert(
+    0,
+    CaptionTrack {
+      present: true,
+      cues: vec![(10_000, 12_000), (20_000, 25_000)],
+    },
+  );
+  let mut streams = vec![StreamProbeResult::new()];
+  let mut params = HashMap::new();
+  params.insert(
+    "duration".to_string(),
+    CheckParameterValue {
+      min: Some(1000),
+      max: None,
+      num: None,
+      den: None,
+      th: None,
+      pairs: None,
+    },
+  );
+  let mut video_details = VideoDetails::new();
+  video_details.stream_start_time = Some(10.0);
+  video_details.stream_duration = Some(20.0);
+
+  detect_captions(&tracks, &mut streams, vec![0], params, video_details);
+
+  let gap = |start, end| CaptionGapResult { start, end };
+  assert_eq!(
+    streams[0].detected_captions,
+    Some(CaptionResult {
+      present: true,
+      gaps: vec![gap(12_000, 20_000), gap(25_000, 30_000)],
+    })
+  );
+}
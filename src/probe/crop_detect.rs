@@ -87,6 +87,7 @@ pub fn create_graph(
     });
     outputs.push(Output {
       kind: Some(OutputKind::VideoMetadata),
+      format: None,
       keys: vec![
         "lavfi.cropdetect.w".to_string(),
         "lavfi.cropdetect.h".to_string(),
@@ -97,6 +98,8 @@ pub fn create_graph(
       ],
       stream: Some(output_identifier),
       path: None,
+      preset: None,
+      timecode: None,
       streams: vec![],
       parameters: HashMap::new(),
     });
@@ -1,9 +1,13 @@
+use crate::hdr::HdrMetadata;
 use crate::order::input::Input;
 use crate::order::stream::Stream as StreamOrder;
 use crate::order::OutputResult;
 use crate::probe::black_and_silence::detect_black_and_silence;
 use crate::probe::black_detect::{blackframes_init, detect_black_frames};
 use crate::probe::blackfade_detect::detect_blackfade;
+use crate::probe::caption_detect::{
+  captions_init, collect_captions, detect_captions, CaptionTrack,
+};
 use crate::probe::crop_detect::{black_borders_init, detect_black_borders};
 use crate::probe::dualmono_detect::{detect_dualmono, dualmono_init};
 use crate::probe::freeze_detect::{detect_freeze, freeze_init};
@@ -119,6 +123,18 @@ pub struct FreezeResult {
   pub end: i64,
 }
 
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct CaptionGapResult {
+  pub start: i64,
+  pub end: i64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct CaptionResult {
+  pub present: bool,
+  pub gaps: Vec<CaptionGapResult>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct StreamProbeResult {
   stream_index: usize,
@@ -131,6 +147,10 @@ pub struct StreamProbeResult {
   pub color_primaries: Option<String>,
   pub color_trc: Option<String>,
   pub color_matrix: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hdr: Option<HdrMetadata>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub first_frame_hdr: Option<HdrMetadata>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub detected_silence: Option<Vec<SilenceResult>>,
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -157,6 +177,8 @@ pub struct StreamProbeResult {
   pub detected_sine: Option<Vec<SineResult>>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub detected_freeze: Option<Vec<FreezeResult>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub detected_captions: Option<CaptionResult>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
@@ -200,6 +222,7 @@ pub struct DeepProbeCheck {
   pub dualmono_detect: Option<HashMap<String, CheckParameterValue>>,
   pub sine_detect: Option<HashMap<String, CheckParameterValue>>,
   pub freeze_detect: Option<HashMap<String, CheckParameterValue>>,
+  pub caption_detect: Option<HashMap<String, CheckParameterValue>>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -208,6 +231,8 @@ pub struct VideoDetails {
   pub time_base: f32,
   pub frame_duration: f32,
   pub stream_duration: Option<f32>,
+  /// Start time of the stream in seconds, frame timestamps do not start at 0 in TS or MXF.
+  pub stream_start_time: Option<f32>,
   pub stream_frames: Option<i64>,
   pub bits_raw_sample: Option<i32>,
   pub metadata_width: i32,
@@ -233,6 +258,7 @@ pub struct DeepOrder {
   audio_details: Vec<AudioDetails>,
   audio_indexes: Vec<u32>,
   video_indexes: Vec<u32>,
+  captions: BTreeMap<usize, CaptionTrack>,
 }
 
 #[derive(Eq, Hash, PartialEq, PartialOrd, Ord)]
@@ -283,6 +309,12 @@ impl fmt::Display for DeepProbeResult {
         "{:30} : {:?}",
         "Matrix coefficients", stream.color_matrix
       )?;
+      writeln!(f, "{:30} : {:?}", "HDR metadata", stream.hdr)?;
+      writeln!(
+        f,
+        "{:30} : {:?}",
+        "First frame HDR metadata", stream.first_frame_hdr
+      )?;
       writeln!(
         f,
         "{:30} : {:?}",
@@ -322,6 +354,11 @@ impl fmt::Display for DeepProbeResult {
         "DualMono detection", stream.detected_dualmono,
       )?;
       writeln!(f, "{:30} : {:?}", "1000Hz detection", stream.detected_sine)?;
+      writeln!(
+        f,
+        "{:30} : {:?}",
+        "Captions detection", stream.detected_captions
+      )?;
       writeln!(
         f,
         "{:30} : {:?}",
@@ -343,6 +380,8 @@ impl StreamProbeResult {
       color_primaries: None,
       color_trc: None,
       color_matrix: None,
+      hdr: None,
+      first_frame_hdr: None,
       min_packet_size: i32::MAX,
       max_packet_size: i32::MIN,
       detected_silence: None,
@@ -358,6 +397,7 @@ impl StreamProbeResult {
       detected_sine: None,
       detected_bitrate: None,
       detected_freeze: None,
+      detected_captions: None,
     }
   }
 }
@@ -396,6 +436,7 @@ impl VideoDetails {
       time_base: 1.0,
       frame_duration: 0.0,
       stream_duration: None,
+      stream_start_time: None,
       stream_frames: None,
       bits_raw_sample: None,
       metadata_width: 0,
@@ -416,6 +457,7 @@ impl DeepOrder {
       audio_details: vec![],
       audio_indexes: vec![],
       video_indexes: vec![],
+      captions: BTreeMap::new(),
     }
   }
 }
@@ -471,10 +513,12 @@ impl DeepProbe {
               stream.get_color_primaries();
             deep_orders.streams[stream_index as usize].color_trc = stream.get_color_trc();
             deep_orders.streams[stream_index as usize].color_matrix = stream.get_color_matrix();
+            deep_orders.streams[stream_index as usize].hdr = stream.get_hdr_metadata();
             deep_orders.video_details.frame_duration = stream.get_frame_rate().invert().to_float();
             deep_orders.video_details.frame_rate = stream.get_frame_rate().to_float();
             deep_orders.video_details.time_base = stream.get_time_base().to_float();
             deep_orders.video_details.stream_duration = stream.get_duration();
+            deep_orders.video_details.stream_start_time = stream.get_start_time();
             deep_orders.video_details.stream_frames = stream.get_nb_frames();
             deep_orders.video_details.bits_raw_sample = stream.get_bits_per_raw_sample();
             deep_orders.video_details.metadata_width = stream.get_width();
@@ -743,6 +787,15 @@ impl DeepProbe {
         );
       }
     }
+    if let Some(params) = deep_orders.check.caption_detect.clone() {
+      detect_captions(
+        &deep_orders.captions,
+        &mut deep_orders.streams,
+        deep_orders.video_indexes.clone(),
+        params,
+        deep_orders.video_details.clone(),
+      );
+    }
     for index in 0..context.get_nb_streams() {
       unsafe {
         if let Ok(stream) = Stream::new(context.get_stream(index as isize)) {
@@ -781,19 +834,32 @@ impl DeepProbe {
     }
 
     let mut order_src = Order::new(src_inputs, vec![], vec![]).unwrap();
+    if deep_orders.check.caption_detect.is_some() {
+      order_src.captions = captions_init(deep_orders.video_indexes.clone());
+    }
     order_src.build_input_format()?;
     let mut decode_end = false;
+    let mut first_frame_indexes = vec![];
 
     while !decode_end {
-      let (in_audio_frames, in_video_frames, in_subtitle_packets, end) = order_src.process_input();
+      let (in_audio_frames, in_video_frames, in_subtitles, end) = order_src.process_input();
       if end == order_src.total_streams {
         decode_end = true;
       }
+      collect_captions(&mut deep_orders.captions, &in_subtitles);
+      for frame in &in_video_frames {
+        if !first_frame_indexes.contains(&frame.index) {
+          first_frame_indexes.push(frame.index);
+          if let Some(stream) = deep_orders.streams.get_mut(frame.index) {
+            stream.first_frame_hdr = frame.get_hdr_metadata();
+          }
+        }
+      }
 
       for order in &mut deep_orders.orders {
         match order
           .1
-          .filtering(&in_audio_frames, &in_video_frames, &in_subtitle_packets)
+          .filtering(&in_audio_frames, &in_video_frames, &in_subtitles)
         {
           Ok(results) => {
             let res = deep_orders.output_results.get_mut(order.0).unwrap();
@@ -1023,6 +1089,7 @@ fn deep_probe() {
     dualmono_detect: Some(dualmono_params),
     sine_detect: Some(sine_params),
     freeze_detect: Some(freeze_params),
+    caption_detect: None,
   };
   let id = Uuid::parse_str("ef7e3ad9-a08f-4cd0-9fec-3ac465bbdd85").unwrap();
   let mut probe = DeepProbe::new("tests/test_file.mxf", id);
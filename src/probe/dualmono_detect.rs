@@ -108,6 +108,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
               });
               outputs.push(Output {
                 kind: Some(OutputKind::AudioMetadata),
+                format: None,
                 keys: vec![
                   "lavfi.aphasemeter.mono_start".to_string(),
                   "lavfi.aphasemeter.mono_end".to_string(),
@@ -115,6 +116,8 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
                 ],
                 stream: Some(output_label),
                 path: None,
+                preset: None,
+                timecode: None,
                 streams: vec![],
                 parameters: HashMap::new(),
               });
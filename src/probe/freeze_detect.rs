@@ -77,12 +77,15 @@ pub fn create_graph(
     });
     outputs.push(Output {
       kind: Some(OutputKind::VideoMetadata),
+      format: None,
       keys: vec![
         "lavfi.freezedetect.freeze_start".to_string(),
         "lavfi.freezedetect.freeze_end".to_string(),
       ],
       stream: Some(output_identifier),
       path: None,
+      preset: None,
+      timecode: None,
       streams: vec![],
       parameters: HashMap::new(),
     });
@@ -82,9 +82,12 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
           });
           outputs.push(Output {
             kind: Some(OutputKind::AudioMetadata),
+            format: None,
             keys: lavfi_keys,
             stream: Some(output_label.clone()),
             path: None,
+            preset: None,
+            timecode: None,
             streams: vec![],
             parameters: HashMap::new(),
           });
@@ -72,6 +72,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
     });
     outputs.push(Output {
       kind: Some(OutputKind::VideoMetadata),
+      format: None,
       keys: vec![
         "lavfi.ocr.text".to_string(),
         "lavfi.ocr.confidence".to_string(),
@@ -79,6 +80,8 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
       ],
       stream: Some(output_identifier),
       path: None,
+      preset: None,
+      timecode: None,
       streams: vec![],
       parameters: HashMap::new(),
     });
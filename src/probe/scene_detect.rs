@@ -63,9 +63,12 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
     });
     outputs.push(Output {
       kind: Some(OutputKind::VideoMetadata),
+      format: None,
       keys: vec!["lavfi.scd.time".to_string(), "lavfi.scd.score".to_string()],
       stream: Some(output_identifier),
       path: None,
+      preset: None,
+      timecode: None,
       streams: vec![],
       parameters: HashMap::new(),
     });
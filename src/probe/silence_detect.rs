@@ -77,6 +77,7 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
     });
     outputs.push(Output {
       kind: Some(OutputKind::AudioMetadata),
+      format: None,
       keys: vec![
         "lavfi.silence_start".to_string(),
         "lavfi.silence_end".to_string(),
@@ -84,6 +85,8 @@ pub fn create_graph<S: ::std::hash::BuildHasher>(
       ],
       stream: Some(output_identifier),
       path: None,
+      preset: None,
+      timecode: None,
       streams: vec![],
       parameters: HashMap::new(),
     });
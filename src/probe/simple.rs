@@ -1,4 +1,5 @@
 use crate::format_context::FormatContext;
+use crate::hdr::HdrMetadata;
 use crate::stream::Stream;
 use crate::tools::rational::Rational;
 use ffmpeg_sys_next::*;
@@ -58,6 +59,8 @@ pub struct VideoProperties {
   timecode: Option<String>,
   pix_fmt: Option<String>,
   nb_frames: Option<i64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  hdr: Option<HdrMetadata>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq, Serialize)]
@@ -113,6 +116,19 @@ impl fmt::Display for Format {
         writeln!(f, "{:30} : {:?}", "Timecode", vp.timecode)?;
         writeln!(f, "{:30} : {:?}", "Pixel format", vp.pix_fmt)?;
         writeln!(f, "{:30} : {:?}", "Number of frames", vp.nb_frames)?;
+        if let Some(ref hdr) = vp.hdr {
+          writeln!(
+            f,
+            "{:30} : {:?}",
+            "Mastering display", hdr.mastering_display
+          )?;
+          writeln!(
+            f,
+            "{:30} : {:?}",
+            "Content light level", hdr.content_light_level
+          )?;
+          writeln!(f, "{:30} : {:?}", "Dolby Vision", hdr.dolby_vision)?;
+        }
       }
       if let Some(ref ap) = stream.audio_properties {
         writeln!(f, "{:30} : {}", "Channels", ap.channels)?;
@@ -201,6 +217,7 @@ impl Probe {
               let timecode = stream.get_timecode();
               let pix_fmt = stream.get_pix_fmt_name();
               let nb_frames = stream.get_nb_frames();
+              let hdr = stream.get_hdr_metadata();
 
               vp = Some(VideoProperties {
                 width,
@@ -214,6 +231,7 @@ impl Probe {
                 timecode,
                 pix_fmt,
                 nb_frames,
+                hdr,
               });
             }
             AVMediaType::AVMEDIA_TYPE_AUDIO => {
@@ -87,9 +87,12 @@ pub fn create_graph(
           });
           outputs.push(Output {
             kind: Some(OutputKind::AudioMetadata),
+            format: None,
             keys: lavfi_keys,
             stream: Some(output_identifier),
             path: None,
+            preset: None,
+            timecode: None,
             streams: vec![],
             parameters: HashMap::new(),
           });
@@ -0,0 +1,374 @@
+use crate::{channel_layout::ChannelLayout, frame::Frame, order::output::SampleFormat};
+use ffmpeg_sys_next::*;
+use libc::c_void;
+use std::{ffi::CString, ptr::null_mut};
+
+/// Trade-off between the speed and the quality of the resampling filter.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResamplingQuality {
+  Fast,
+  #[default]
+  Standard,
+  High,
+}
+
+impl ResamplingQuality {
+  /// Length of the filter and log2 of the number of its phases.
+  fn get_filter(&self) -> (i64, i64) {
+    match self {
+      ResamplingQuality::Fast => (16, 8),
+      ResamplingQuality::Standard => (32, 10),
+      ResamplingQuality::High => (64, 14),
+    }
+  }
+}
+
+/// Convert the layout, sample rate and sample format of audio frames with swresample,
+/// without a filter graph.
+pub struct Resampler {
+  context: *mut SwrContext,
+  channel_layout: ChannelLayout,
+  sample_rate: i32,
+  sample_format: SampleFormat,
+  quality: ResamplingQuality,
+  channel_mapping: Option<Vec<i32>>,
+  /// Time base of the incoming frames, by default 1/sample rate of the source.
+  input_time_base: Option<AVRational>,
+  /// Layout, sample rate and sample format of the frames the context is set up for.
+  source: Option<(ChannelLayout, i32, i32)>,
+  /// Frame to take the name, index and properties of the flushed samples from.
+  last_frame: Option<Frame>,
+  /// Samples drained from the context before its setup changed, output with the next ones.
+  pending: Option<Frame>,
+}
+
+impl Resampler {
+  pub fn new(
+    channel_layout: ChannelLayout,
+    sample_rate: i32,
+    sample_format: SampleFormat,
+  ) -> Result<Self, String> {
+    if sample_rate <= 0 {
+      return Err(format!("Invalid resampler sample rate {sample_rate}"));
+    }
+    Ok(Resampler {
+      context: null_mut(),
+      channel_layout,
+      sample_rate,
+      sample_format,
+      quality: ResamplingQuality::default(),
+      channel_mapping: None,
+      input_time_base: None,
+      source: None,
+      last_frame: None,
+      pending: None,
+    })
+  }
+
+  pub fn set_quality(&mut self, quality: ResamplingQuality) {
+    self.quality = quality;
+    self.source = None;
+  }
+
+  /// Reorder the source channels before converting the layout: the source channel used
+  /// for each of them, -1 muting it, e.g. `[1, 0]` to swap the channels of a stereo source.
+  pub fn set_channel_mapping(&mut self, channel_mapping: Vec<i32>) {
+    self.channel_mapping = Some(channel_mapping);
+    self.source = None;
+  }
+
+  /// Set the time base of the incoming frames, by default 1/sample rate of the source.
+  pub fn set_input_time_base(&mut self, time_base: AVRational) {
+    if time_base.num > 0 && time_base.den > 0 {
+      self.input_time_base = Some(time_base);
+    }
+  }
+
+  /// Resample a frame into a new one, keeping its properties, timestamped in 1/sample rate
+  /// of the resampler. Samples may be buffered until the next frame or the flush.
+  pub fn resample(&mut self, frame: &Frame) -> Result<Frame, String> {
+    unsafe {
+      self.setup(frame.frame)?;
+      let pts = self.get_next_pts((*frame.frame).pts);
+      let output = self.convert(
+        frame,
+        (*frame.frame).extended_data as *const *const u8,
+        (*frame.frame).nb_samples,
+        pts,
+      )?;
+      self.last_frame = Some(Frame {
+        name: frame.name.clone(),
+        frame: av_frame_clone(frame.frame),
+        index: frame.index,
+      });
+      match self.pending.take() {
+        Some(pending) => self.concat(&pending, &output),
+        None => Ok(output),
+      }
+    }
+  }
+
+  /// Samples still buffered in the resampler, when there are any.
+  pub fn flush(&mut self) -> Result<Option<Frame>, String> {
+    let Some(last_frame) = self.last_frame.take() else {
+      return Ok(None);
+    };
+    unsafe { self.drain(&last_frame) }
+  }
+
+  /// Samples buffered in the context, following the last converted ones.
+  unsafe fn drain(&mut self, frame: &Frame) -> Result<Option<Frame>, String> {
+    let pts = self.get_next_pts(AV_NOPTS_VALUE);
+    let output = self.convert(frame, std::ptr::null(), 0, pts)?;
+    if (*output.frame).nb_samples == 0 {
+      return Ok(None);
+    }
+    Ok(Some(output))
+  }
+
+  /// Timestamp of the samples converted next, in 1/sample rate of the resampler, following
+  /// the previous samples when the input timestamp is unknown.
+  unsafe fn get_next_pts(&self, pts: i64) -> i64 {
+    let source_sample_rate = self
+      .source
+      .as_ref()
+      .map_or(self.sample_rate, |source| source.1);
+    let time_base = self.input_time_base.unwrap_or(AVRational {
+      num: 1,
+      den: source_sample_rate,
+    });
+    // swresample counts in 1/(input sample rate * output sample rate)
+    let input_pts = if pts == AV_NOPTS_VALUE {
+      i64::MIN
+    } else {
+      av_rescale(
+        pts,
+        i64::from(time_base.num) * i64::from(self.sample_rate) * i64::from(source_sample_rate),
+        i64::from(time_base.den),
+      )
+    };
+    let output_pts = swr_next_pts(self.context, input_pts);
+    let rate = i64::from(source_sample_rate);
+    if output_pts >= 0 {
+      (output_pts + rate / 2) / rate
+    } else {
+      (output_pts - rate / 2) / rate
+    }
+  }
+
+  unsafe fn convert(
+    &mut self,
+    frame: &Frame,
+    data: *const *const u8,
+    nb_samples: i32,
+    pts: i64,
+  ) -> Result<Frame, String> {
+    let output_frame =
+      self.alloc_frame(frame, swr_get_out_samples(self.context, nb_samples).max(1))?;
+    let output = output_frame.frame;
+    (*output).pts = pts;
+
+    let converted = swr_convert(
+      self.context,
+      (*output).extended_data as _,
+      (*output).nb_samples,
+      data as _,
+      nb_samples,
+    );
+    check_result!(converted);
+    (*output).nb_samples = converted;
+    Ok(output_frame)
+  }
+
+  /// Samples of two converted frames, one after the other.
+  unsafe fn concat(&self, first: &Frame, second: &Frame) -> Result<Frame, String> {
+    let first_samples = (*first.frame).nb_samples;
+    let second_samples = (*second.frame).nb_samples;
+    let output = self.alloc_frame(first, first_samples + second_samples)?;
+    let nb_channels = self.channel_layout.get_nb_channels();
+    let sample_format = AVSampleFormat::from(self.sample_format);
+    check_result!(av_samples_copy(
+      (*output.frame).extended_data as _,
+      (*first.frame).extended_data as _,
+      0,
+      0,
+      first_samples,
+      nb_channels,
+      sample_format
+    ));
+    check_result!(av_samples_copy(
+      (*output.frame).extended_data as _,
+      (*second.frame).extended_data as _,
+      first_samples,
+      0,
+      second_samples,
+      nb_channels,
+      sample_format
+    ));
+    Ok(output)
+  }
+
+  /// Frame in the output format, with the properties of `frame`.
+  unsafe fn alloc_frame(&self, frame: &Frame, nb_samples: i32) -> Result<Frame, String> {
+    let output = av_frame_alloc();
+    if output.is_null() {
+      return Err("Unable to allocate frame".to_string());
+    }
+    let output_frame = Frame {
+      name: frame.name.clone(),
+      frame: output,
+      index: frame.index,
+    };
+    check_result!(av_frame_copy_props(output, frame.frame));
+    self.channel_layout.set_to_frame(output)?;
+    (*output).sample_rate = self.sample_rate;
+    (*output).format = AVSampleFormat::from(self.sample_format) as i32;
+    (*output).nb_samples = nb_samples;
+    check_result!(av_frame_get_buffer(output, 0));
+    Ok(output_frame)
+  }
+
+  unsafe fn setup(&mut self, frame: *const AVFrame) -> Result<(), String> {
+    let channel_layout = ChannelLayout::from_frame(frame);
+    let source = (channel_layout, (*frame).sample_rate, (*frame).format);
+    if self.source.as_ref() == Some(&source) {
+      return Ok(());
+    }
+    let sample_format = SampleFormat::try_from(source.2)?;
+
+    // samples buffered with the previous setup are output before the next ones
+    if let Some(last_frame) = self.last_frame.take() {
+      if !self.context.is_null() {
+        self.pending = self.drain(&last_frame)?;
+      }
+    }
+    self.free();
+    self.source = None;
+    self.context = swr_alloc();
+    if self.context.is_null() {
+      return Err("Unable to allocate the resampler".to_string());
+    }
+    source.0.set_to_resampler(self.context, "in")?;
+    self.channel_layout.set_to_resampler(self.context, "out")?;
+    set_sample_format(self.context, "in_sample_fmt", sample_format.into())?;
+    set_sample_format(self.context, "out_sample_fmt", self.sample_format.into())?;
+    set_int(self.context, "in_sample_rate", i64::from(source.1))?;
+    set_int(self.context, "out_sample_rate", i64::from(self.sample_rate))?;
+
+    let (filter_size, phase_shift) = self.quality.get_filter();
+    set_int(self.context, "filter_size", filter_size)?;
+    set_int(self.context, "phase_shift", phase_shift)?;
+
+    if let Some(channel_mapping) = &self.channel_mapping {
+      if channel_mapping.len() != source.0.get_nb_channels() as usize {
+        return Err(format!(
+          "The channel mapping has {} channels, the source has {}",
+          channel_mapping.len(),
+          source.0.get_nb_channels()
+        ));
+      }
+      check_result!(swr_set_channel_mapping(
+        self.context,
+        channel_mapping.as_ptr()
+      ));
+    }
+    check_result!(swr_init(self.context));
+
+    self.source = Some(source);
+    Ok(())
+  }
+
+  fn free(&mut self) {
+    if !self.context.is_null() {
+      unsafe {
+        swr_free(&mut self.context);
+      }
+    }
+  }
+}
+
+impl Drop for Resampler {
+  fn drop(&mut self) {
+    self.free();
+  }
+}
+
+unsafe fn set_int(context: *mut SwrContext, key: &str, value: i64) -> Result<(), String> {
+  let key = CString::new(key).unwrap();
+  check_result!(av_opt_set_int(
+    context as *mut c_void,
+    key.as_ptr(),
+    value,
+    0
+  ));
+  Ok(())
+}
+
+unsafe fn set_sample_format(
+  context: *mut SwrContext,
+  key: &str,
+  value: AVSampleFormat,
+) -> Result<(), String> {
+  let key = CString::new(key).unwrap();
+  check_result!(av_opt_set_sample_fmt(
+    context as *mut c_void,
+    key.as_ptr(),
+    value,
+    0
+  ));
+  Ok(())
+}
+
+#[test]
+fn resample_frame() {
+  let frame = unsafe {
+    let frame = av_frame_alloc();
+    ChannelLayout::default_for(1).set_to_frame(frame).unwrap();
+    (*frame).sample_rate = 48_000;
+    (*frame).format = AVSampleFormat::AV_SAMPLE_FMT_S16 as i32;
+    (*frame).nb_samples = 1024;
+    (*frame).pts = 48_000;
+    assert_eq!(av_frame_get_buffer(frame, 0), 0);
+    Frame {
+      name: Some("audio".to_string()),
+      frame,
+      index: 0,
+    }
+  };
+
+  let stereo: ChannelLayout = "stereo".parse().unwrap();
+  assert!(Resampler::new(stereo.clone(), 0, "flt".parse().unwrap()).is_err());
+
+  let mut resampler = Resampler::new(stereo.clone(), 44_100, "flt".parse().unwrap()).unwrap();
+  resampler.set_quality(ResamplingQuality::High);
+  let resampled = resampler.resample(&frame).unwrap();
+  unsafe {
+    assert_eq!(ChannelLayout::from_frame(resampled.frame), stereo);
+    assert_eq!((*resampled.frame).sample_rate, 44_100);
+    assert_eq!(
+      (*resampled.frame).format,
+      AVSampleFormat::AV_SAMPLE_FMT_FLT as i32
+    );
+    assert!((*resampled.frame).nb_samples > 0);
+  }
+  // one second, in 1/44100 minus the filter delay
+  assert!((resampled.get_pts() - 44_100).abs() < 64);
+  assert!(resampler.flush().unwrap().is_some());
+  assert!(resampler.flush().unwrap().is_none());
+
+  let mut resampler = Resampler::new(stereo.clone(), 44_100, "flt".parse().unwrap()).unwrap();
+  resampler.set_quality(ResamplingQuality::Fast);
+  let nb_samples = unsafe { (*resampler.resample(&frame).unwrap().frame).nb_samples };
+
+  // the samples buffered before a reconfiguration are output with the next ones
+  let mut resampler = Resampler::new(stereo.clone(), 44_100, "flt".parse().unwrap()).unwrap();
+  resampler.resample(&frame).unwrap();
+  resampler.set_quality(ResamplingQuality::Fast);
+  let resampled = resampler.resample(&frame).unwrap();
+  assert!(unsafe { (*resampled.frame).nb_samples } > nb_samples);
+
+  let mut resampler = Resampler::new(stereo, 48_000, "s16".parse().unwrap()).unwrap();
+  resampler.set_channel_mapping(vec![0, 1]);
+  assert!(resampler.resample(&frame).is_err());
+}
@@ -0,0 +1,222 @@
+use crate::{
+  frame::Frame,
+  order::output::{ColorRange, Colorspace, PixelFormat},
+};
+use ffmpeg_sys_next::*;
+use std::ptr::null_mut;
+
+/// Scaling algorithm of the scaler, bicubic by default.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalingAlgorithm {
+  FastBilinear,
+  Bilinear,
+  #[default]
+  Bicubic,
+  Point,
+  Area,
+  Lanczos,
+  Spline,
+}
+
+impl ScalingAlgorithm {
+  fn get_flags(&self) -> i32 {
+    (match self {
+      ScalingAlgorithm::FastBilinear => SWS_FAST_BILINEAR,
+      ScalingAlgorithm::Bilinear => SWS_BILINEAR,
+      ScalingAlgorithm::Bicubic => SWS_BICUBIC,
+      ScalingAlgorithm::Point => SWS_POINT,
+      ScalingAlgorithm::Area => SWS_AREA,
+      ScalingAlgorithm::Lanczos => SWS_LANCZOS,
+      ScalingAlgorithm::Spline => SWS_SPLINE,
+    }) as i32
+  }
+}
+
+/// Convert the size and pixel format of video frames with swscale, without a filter graph.
+#[derive(Debug)]
+pub struct Scaler {
+  context: *mut SwsContext,
+  width: i32,
+  height: i32,
+  pixel_format: AVPixelFormat,
+  algorithm: ScalingAlgorithm,
+  colorspace: Option<AVColorSpace>,
+  color_range: Option<AVColorRange>,
+  /// Width, height, pixel format, colorspace and range of the frames the context is set up for.
+  source: Option<(i32, i32, i32, AVColorSpace, AVColorRange)>,
+}
+
+impl Scaler {
+  pub fn new(width: i32, height: i32, pixel_format: PixelFormat) -> Result<Self, String> {
+    if width <= 0 || height <= 0 {
+      return Err(format!("Invalid scaler size {width}x{height}"));
+    }
+    Ok(Scaler {
+      context: null_mut(),
+      width,
+      height,
+      pixel_format: pixel_format.into(),
+      algorithm: ScalingAlgorithm::default(),
+      colorspace: None,
+      color_range: None,
+      source: None,
+    })
+  }
+
+  pub fn set_algorithm(&mut self, algorithm: ScalingAlgorithm) {
+    self.algorithm = algorithm;
+    self.source = None;
+  }
+
+  /// Colorspace of the scaled frames, the one of the source frames by default.
+  pub fn set_colorspace(&mut self, colorspace: Colorspace) {
+    self.colorspace = Some(colorspace.into());
+    self.source = None;
+  }
+
+  /// Range of the scaled frames, the one of the source frames by default.
+  pub fn set_color_range(&mut self, color_range: ColorRange) {
+    self.color_range = Some(color_range.into());
+    self.source = None;
+  }
+
+  /// Scale a frame into a new one, keeping its timestamps and properties.
+  pub fn scale(&mut self, frame: &Frame) -> Result<Frame, String> {
+    unsafe {
+      self.setup(frame.frame)?;
+
+      let output = av_frame_alloc();
+      if output.is_null() {
+        return Err("Unable to allocate frame".to_string());
+      }
+      let output_frame = Frame {
+        name: frame.name.clone(),
+        frame: output,
+        index: frame.index,
+      };
+      check_result!(av_frame_copy_props(output, frame.frame));
+      (*output).width = self.width;
+      (*output).height = self.height;
+      (*output).format = self.pixel_format as i32;
+      (*output).colorspace = self.colorspace.unwrap_or((*frame.frame).colorspace);
+      (*output).color_range = self.color_range.unwrap_or((*frame.frame).color_range);
+      check_result!(av_frame_get_buffer(output, 0));
+
+      check_result!(sws_scale(
+        self.context,
+        (*frame.frame).data.as_ptr() as *const *const u8,
+        (*frame.frame).linesize.as_ptr(),
+        0,
+        (*frame.frame).height,
+        (*output).data.as_ptr(),
+        (*output).linesize.as_ptr(),
+      ));
+      Ok(output_frame)
+    }
+  }
+
+  unsafe fn setup(&mut self, frame: *const AVFrame) -> Result<(), String> {
+    let source = (
+      (*frame).width,
+      (*frame).height,
+      (*frame).format,
+      (*frame).colorspace,
+      (*frame).color_range,
+    );
+    if self.source == Some(source) {
+      return Ok(());
+    }
+    if (*frame).format < 0 || (*frame).format >= AVPixelFormat::AV_PIX_FMT_NB as i32 {
+      return Err(format!("Invalid pixel format {} to scale", (*frame).format));
+    }
+    let (width, height, format, colorspace, color_range) = source;
+
+    self.free();
+    self.source = None;
+    self.context = sws_getContext(
+      width,
+      height,
+      std::mem::transmute::<i32, AVPixelFormat>(format),
+      self.width,
+      self.height,
+      self.pixel_format,
+      self.algorithm.get_flags(),
+      null_mut(),
+      null_mut(),
+      null_mut(),
+    );
+    if self.context.is_null() {
+      return Err(format!(
+        "Unable to scale {width}x{height} frames to {}x{}",
+        self.width, self.height
+      ));
+    }
+
+    let output_colorspace = self.colorspace.unwrap_or(colorspace);
+    let output_range = self.color_range.unwrap_or(color_range);
+    // unsupported or unspecified colorspaces use the default coefficients
+    sws_setColorspaceDetails(
+      self.context,
+      sws_getCoefficients(colorspace as i32),
+      (color_range == AVColorRange::AVCOL_RANGE_JPEG) as i32,
+      sws_getCoefficients(output_colorspace as i32),
+      (output_range == AVColorRange::AVCOL_RANGE_JPEG) as i32,
+      0,
+      1 << 16,
+      1 << 16,
+    );
+
+    self.source = Some(source);
+    Ok(())
+  }
+
+  fn free(&mut self) {
+    if !self.context.is_null() {
+      unsafe {
+        sws_freeContext(self.context);
+      }
+      self.context = null_mut();
+    }
+  }
+}
+
+impl Drop for Scaler {
+  fn drop(&mut self) {
+    self.free();
+  }
+}
+
+#[test]
+fn scale_frame() {
+  let frame = unsafe {
+    let frame = av_frame_alloc();
+    (*frame).width = 64;
+    (*frame).height = 48;
+    (*frame).format = AVPixelFormat::AV_PIX_FMT_YUV420P as i32;
+    (*frame).pts = 42;
+    assert_eq!(av_frame_get_buffer(frame, 0), 0);
+    Frame {
+      name: Some("video".to_string()),
+      frame,
+      index: 0,
+    }
+  };
+
+  assert!(Scaler::new(0, 24, "rgb24".parse().unwrap()).is_err());
+  let mut scaler = Scaler::new(32, 24, "rgb24".parse().unwrap()).unwrap();
+  scaler.set_algorithm(ScalingAlgorithm::Lanczos);
+  scaler.set_color_range(ColorRange::Full);
+  let scaled = scaler.scale(&frame).unwrap();
+  unsafe {
+    assert_eq!((*scaled.frame).width, 32);
+    assert_eq!((*scaled.frame).height, 24);
+    assert_eq!(
+      (*scaled.frame).format,
+      AVPixelFormat::AV_PIX_FMT_RGB24 as i32
+    );
+    assert_eq!((*scaled.frame).color_range, AVColorRange::AVCOL_RANGE_JPEG);
+  }
+  assert_eq!(scaled.get_pts(), 42);
+  assert_eq!(scaled.name.as_deref(), Some("video"));
+}
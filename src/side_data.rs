@@ -0,0 +1,339 @@
+use crate::{
+  frame::Frame,
+  hdr::{ContentLightLevel, MasteringDisplayMetadata},
+  tools,
+};
+use ffmpeg_sys_next::*;
+use std::{fmt, slice, str::FromStr};
+
+/// Frame metadata key of the bar data found in the coded picture of a frame.
+pub const BAR_DATA_METADATA_KEY: &str = "atsc.bar_data";
+
+/// Letterbox and pillarbox bars, as defined by ATSC A/53 `bar_data()`,
+/// in lines for top and bottom bars and in pixels for left and right bars.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BarData {
+  pub top: Option<u16>,
+  pub bottom: Option<u16>,
+  pub left: Option<u16>,
+  pub right: Option<u16>,
+}
+
+impl BarData {
+  /// Parse a `bar_data()` structure.
+  pub fn parse(data: &[u8]) -> Option<Self> {
+    let flags = *data.first()?;
+    let mut values = data[1..]
+      .chunks_exact(2)
+      .map(|value| (u16::from(value[0] & 0x3f) << 8) | u16::from(value[1]));
+
+    let mut read = |flag: u8| {
+      if flags & flag != 0 {
+        values.next().map(Some)
+      } else {
+        Some(None)
+      }
+    };
+
+    Some(BarData {
+      top: read(0x80)?,
+      bottom: read(0x40)?,
+      left: read(0x20)?,
+      right: read(0x10)?,
+    })
+  }
+
+  /// Find the bar data of a coded picture, in the ATSC user data of MPEG-2 pictures or of
+  /// H.264 and HEVC registered user data SEI messages.
+  /// FFmpeg decoders do not export it, so it is read from the packets.
+  pub fn find(data: &[u8]) -> Option<Self> {
+    // ATSC identifier, followed by the bar data user data type code. The markers of the
+    // bar values keep them from holding emulation prevention bytes.
+    let position = data.windows(5).position(|window| window == b"GA94\x06")?;
+    BarData::parse(&data[position + 5..])
+  }
+}
+
+impl fmt::Display for BarData {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let bars = [
+      ("top", self.top),
+      ("bottom", self.bottom),
+      ("left", self.left),
+      ("right", self.right),
+    ];
+    let values: Vec<String> = bars
+      .iter()
+      .filter_map(|(name, value)| value.map(|value| format!("{name}={value}")))
+      .collect();
+    write!(f, "{}", values.join(","))
+  }
+}
+
+impl FromStr for BarData {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut bar_data = BarData::default();
+    for value in s.split(',').filter(|value| !value.is_empty()) {
+      let (name, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("'{s}' is not a valid value for BarData"))?;
+      let value = value
+        .parse()
+        .map_err(|_| format!("'{s}' is not a valid value for BarData"))?;
+      match name {
+        "top" => bar_data.top = Some(value),
+        "bottom" => bar_data.bottom = Some(value),
+        "left" => bar_data.left = Some(value),
+        "right" => bar_data.right = Some(value),
+        _ => return Err(format!("'{s}' is not a valid value for BarData")),
+      }
+    }
+    Ok(bar_data)
+  }
+}
+
+/// Side data of a decoded frame. Bar data is not frame side data in FFmpeg, it comes from
+/// the frame metadata set by `VideoDecoder`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SideData {
+  /// Active Format Description code.
+  Afd(u8),
+  BarData(BarData),
+  /// SMPTE 12M timecodes, as `hh:mm:ss:ff` strings.
+  S12mTimecode(Vec<String>),
+  MasteringDisplay(MasteringDisplayMetadata),
+  ContentLightLevel(ContentLightLevel),
+  /// CEA-708 cc_data triplets.
+  A53ClosedCaptions(Vec<u8>),
+  Raw {
+    side_data_type: AVFrameSideDataType,
+    data: Vec<u8>,
+  },
+}
+
+impl SideData {
+  /// # Safety
+  /// `side_data` must point to a valid frame side data.
+  pub unsafe fn from_av_frame_side_data(side_data: *const AVFrameSideData) -> Self {
+    let data = if (*side_data).data.is_null() {
+      vec![]
+    } else {
+      slice::from_raw_parts((*side_data).data, (*side_data).size as usize).to_vec()
+    };
+
+    match (*side_data).type_ {
+      AVFrameSideDataType::AV_FRAME_DATA_AFD if !data.is_empty() => SideData::Afd(data[0]),
+      AVFrameSideDataType::AV_FRAME_DATA_S12M_TIMECODE if data.len() >= 4 => {
+        let timecodes = slice::from_raw_parts((*side_data).data as *const u32, data.len() / 4);
+        let count = (timecodes[0] as usize).min(timecodes.len() - 1);
+        SideData::S12mTimecode(
+          timecodes[1..=count]
+            .iter()
+            .map(|timecode| {
+              let mut buffer = [0; AV_TIMECODE_STR_SIZE as usize];
+              av_timecode_make_smpte_tc_string(buffer.as_mut_ptr(), *timecode, 0);
+              tools::to_string(buffer.as_ptr())
+            })
+            .collect(),
+        )
+      }
+      AVFrameSideDataType::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA => {
+        match MasteringDisplayMetadata::from_av_mastering_display_metadata(
+          (*side_data).data as *const _,
+        ) {
+          Some(metadata) => SideData::MasteringDisplay(metadata),
+          None => SideData::Raw {
+            side_data_type: (*side_data).type_,
+            data,
+          },
+        }
+      }
+      AVFrameSideDataType::AV_FRAME_DATA_CONTENT_LIGHT_LEVEL => {
+        match ContentLightLevel::from_av_content_light_metadata((*side_data).data as *const _) {
+          Some(metadata) => SideData::ContentLightLevel(metadata),
+          None => SideData::Raw {
+            side_data_type: (*side_data).type_,
+            data,
+          },
+        }
+      }
+      AVFrameSideDataType::AV_FRAME_DATA_A53_CC => SideData::A53ClosedCaptions(data),
+      side_data_type => SideData::Raw {
+        side_data_type,
+        data,
+      },
+    }
+  }
+
+  /// Name used for `side_data.<name>` keys of video metadata outputs.
+  pub fn get_name(&self) -> String {
+    match self {
+      SideData::Afd(_) => "afd".to_string(),
+      SideData::BarData(_) => "bar_data".to_string(),
+      SideData::S12mTimecode(_) => "s12m_timecode".to_string(),
+      SideData::MasteringDisplay(_) => "mastering_display".to_string(),
+      SideData::ContentLightLevel(_) => "content_light_level".to_string(),
+      SideData::A53ClosedCaptions(_) => "a53_cc".to_string(),
+      SideData::Raw { side_data_type, .. } => unsafe {
+        tools::to_string(av_frame_side_data_name(*side_data_type))
+          .to_lowercase()
+          .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+      },
+    }
+  }
+}
+
+impl fmt::Display for SideData {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SideData::Afd(afd) => write!(f, "{afd}"),
+      SideData::BarData(bar_data) => write!(f, "{bar_data}"),
+      SideData::S12mTimecode(timecodes) => write!(f, "{}", timecodes.join(",")),
+      SideData::MasteringDisplay(metadata) => write!(f, "{metadata}"),
+      SideData::ContentLightLevel(metadata) => {
+        write!(f, "{},{}", metadata.max_cll, metadata.max_fall)
+      }
+      SideData::A53ClosedCaptions(data) | SideData::Raw { data, .. } => {
+        for byte in data {
+          write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+pub struct SideDataIterator<'a> {
+  frame: &'a Frame,
+  index: usize,
+  bar_data: Option<BarData>,
+}
+
+impl<'a> SideDataIterator<'a> {
+  pub fn new(frame: &'a Frame) -> Self {
+    let bar_data = frame
+      .get_metadata(BAR_DATA_METADATA_KEY)
+      .and_then(|bar_data| bar_data.parse().ok());
+    SideDataIterator {
+      frame,
+      index: 0,
+      bar_data,
+    }
+  }
+}
+
+impl Iterator for SideDataIterator<'_> {
+  type Item = SideData;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    unsafe {
+      let frame = self.frame.frame;
+      if frame.is_null() || self.index >= (*frame).nb_side_data as usize {
+        return self.bar_data.take().map(SideData::BarData);
+      }
+      let side_data = *(*frame).side_data.add(self.index);
+      self.index += 1;
+      Some(SideData::from_av_frame_side_data(side_data))
+    }
+  }
+}
+
+#[test]
+fn read_side_data() {
+  use std::ffi::CString;
+
+  let frame = Frame {
+    name: None,
+    frame: unsafe { av_frame_alloc() },
+    index: 0,
+  };
+  unsafe {
+    let side_data = [
+      (AVFrameSideDataType::AV_FRAME_DATA_AFD, vec![10]),
+      (
+        AVFrameSideDataType::AV_FRAME_DATA_S12M_TIMECODE,
+        [1u32, 0x1312_1110]
+          .iter()
+          .flat_map(|value| value.to_ne_bytes())
+          .collect(),
+      ),
+      (
+        AVFrameSideDataType::AV_FRAME_DATA_MOTION_VECTORS,
+        vec![1, 2, 3],
+      ),
+    ];
+    for (side_data_type, data) in side_data {
+      let side_data = av_frame_new_side_data(frame.frame, side_data_type, data.len() as _);
+      assert!(!side_data.is_null());
+      (*side_data)
+        .data
+        .copy_from_nonoverlapping(data.as_ptr(), data.len());
+    }
+    let key = CString::new(BAR_DATA_METADATA_KEY).unwrap();
+    let value = CString::new("top=140,bottom=1040").unwrap();
+    assert_eq!(
+      av_dict_set(
+        &mut (*frame.frame).metadata,
+        key.as_ptr(),
+        value.as_ptr(),
+        0
+      ),
+      0
+    );
+  }
+
+  let bar_data = BarData {
+    top: Some(140),
+    bottom: Some(1040),
+    left: None,
+    right: None,
+  };
+  assert_eq!(
+    frame.side_data().collect::<Vec<_>>(),
+    vec![
+      SideData::Afd(10),
+      SideData::S12mTimecode(vec!["10:11:12:13".to_string()]),
+      SideData::Raw {
+        side_data_type: AVFrameSideDataType::AV_FRAME_DATA_MOTION_VECTORS,
+        data: vec![1, 2, 3],
+      },
+      SideData::BarData(bar_data.clone()),
+    ]
+  );
+  assert_eq!(
+    frame.get_side_data_entry("side_data.afd"),
+    Some("10".to_string())
+  );
+  assert_eq!(
+    frame.get_side_data_entry("side_data.s12m_timecode"),
+    Some("10:11:12:13".to_string())
+  );
+  assert_eq!(
+    frame.get_side_data_entry("side_data.motion_vectors"),
+    Some("010203".to_string())
+  );
+  assert_eq!(
+    frame.get_side_data_entry("side_data.bar_data"),
+    Some("top=140,bottom=1040".to_string())
+  );
+  assert_eq!(frame.get_side_data_entry("side_data.a53_cc"), None);
+  assert_eq!(frame.get_side_data_entry("afd"), None);
+
+  // bar data of an MPEG-2 picture user data, and of an H.264 registered user data SEI
+  assert_eq!(
+    BarData::find(b"\x00\x00\x01\xb2GA94\x06\xcf\xc0\x8c\xc4\x10"),
+    Some(bar_data)
+  );
+  assert_eq!(
+    BarData::find(b"\x06\x04\x0b\xb5\x00\x31GA94\x06\x4f\xc4\x10\x80"),
+    Some(BarData {
+      bottom: Some(1040),
+      ..Default::default()
+    })
+  );
+  assert_eq!(BarData::find(b"GA94\x03\xcf"), None);
+  assert_eq!(BarData::parse(&[0x3f, 0xc0, 0x00]), None);
+  assert!("top=a".parse::<BarData>().is_err());
+}
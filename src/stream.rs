@@ -1,4 +1,9 @@
-use crate::{tools, tools::rational::Rational};
+use crate::{
+  channel_layout::ChannelLayout,
+  hdr::{ContentLightLevel, DolbyVision, HdrMetadata, MasteringDisplayMetadata},
+  tools,
+  tools::rational::Rational,
+};
 use ffmpeg_sys_next::*;
 use regex::Regex;
 use std::{char, collections::HashMap, ffi::CString, ptr::null_mut};
@@ -292,17 +297,19 @@ impl Stream {
   }
 
   pub fn get_channels(&self) -> i32 {
-    unsafe { (*(*self.stream).codecpar).channels }
+    self.get_channel_layout().get_nb_channels()
   }
 
-  #[cfg(any(ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1))]
+  pub fn get_channel_layout(&self) -> ChannelLayout {
+    unsafe { ChannelLayout::from_codec_parameters((*self.stream).codecpar) }
+  }
+
+  #[cfg(any(
+    ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1
+  ))]
   pub fn get_timecode(&self) -> Option<String> {
     unsafe {
-      let timecode_side_data = av_stream_get_side_data(
-        self.stream,
-        AVPacketSideDataType::AV_PKT_DATA_S12M_TIMECODE,
-        null_mut(),
-      );
+      let timecode_side_data = self.get_side_data(AVPacketSideDataType::AV_PKT_DATA_S12M_TIMECODE);
 
       let timecode = &mut 0;
       if timecode_side_data.is_null() {
@@ -327,6 +334,69 @@ impl Stream {
     }
   }
 
+  #[cfg(not(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+  pub fn get_side_data(&self, side_data_type: AVPacketSideDataType) -> *const u8 {
+    unsafe { av_stream_get_side_data(self.stream, side_data_type, null_mut()) }
+  }
+
+  /// Stream side data moved to the codec parameters in FFmpeg 6.1.
+  #[cfg(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+  pub fn get_side_data(&self, side_data_type: AVPacketSideDataType) -> *const u8 {
+    unsafe {
+      let codecpar = (*self.stream).codecpar;
+      let side_data = av_packet_side_data_get(
+        (*codecpar).coded_side_data,
+        (*codecpar).nb_coded_side_data,
+        side_data_type,
+      );
+      if side_data.is_null() {
+        null_mut()
+      } else {
+        (*side_data).data
+      }
+    }
+  }
+
+  pub fn get_hdr_metadata(&self) -> Option<HdrMetadata> {
+    unsafe {
+      let mastering_display = MasteringDisplayMetadata::from_av_mastering_display_metadata(
+        self.get_side_data(AVPacketSideDataType::AV_PKT_DATA_MASTERING_DISPLAY_METADATA)
+          as *const _,
+      );
+      let content_light_level = ContentLightLevel::from_av_content_light_metadata(
+        self.get_side_data(AVPacketSideDataType::AV_PKT_DATA_CONTENT_LIGHT_LEVEL) as *const _,
+      );
+      let hdr_metadata = HdrMetadata {
+        mastering_display,
+        content_light_level,
+        dolby_vision: self.get_dolby_vision(),
+      };
+      if hdr_metadata.is_empty() {
+        None
+      } else {
+        Some(hdr_metadata)
+      }
+    }
+  }
+
+  #[cfg(any(
+    ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1
+  ))]
+  fn get_dolby_vision(&self) -> Option<DolbyVision> {
+    unsafe {
+      DolbyVision::from_av_dovi_decoder_configuration_record(
+        self.get_side_data(AVPacketSideDataType::AV_PKT_DATA_DOVI_CONF) as *const _,
+      )
+    }
+  }
+
+  #[cfg(not(any(
+    ffmpeg_4_4, ffmpeg_5_0, ffmpeg_5_1, ffmpeg_6_0, ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1
+  )))]
+  fn get_dolby_vision(&self) -> Option<DolbyVision> {
+    None
+  }
+
   pub fn get_bits_per_raw_sample(&self) -> Option<i32> {
     unsafe {
       if (*(*self.stream).codecpar).bits_per_raw_sample == 0 {
@@ -0,0 +1,155 @@
+use crate::{packet::Packet, tools};
+use ffmpeg_sys_next::*;
+use std::slice;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubtitleBitmap {
+  pub x: i32,
+  pub y: i32,
+  pub width: i32,
+  pub height: i32,
+  /// Palette indexes, `linesize` bytes per line.
+  pub data: Vec<u8>,
+  pub linesize: i32,
+  /// ARGB palette entries (0xAARRGGBB).
+  pub palette: Vec<u32>,
+}
+
+impl SubtitleBitmap {
+  /// ARGB color of the pixel at the position relative to the bitmap.
+  pub fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+    if x < 0 || y < 0 || x >= self.width || y >= self.height {
+      return None;
+    }
+    let index = self.data.get((y * self.linesize + x) as usize)?;
+    self.palette.get(*index as usize).cloned()
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubtitleRect {
+  Text(String),
+  Ass(String),
+  Bitmap(SubtitleBitmap),
+}
+
+pub struct Subtitle {
+  pub name: Option<String>,
+  pub index: usize,
+  /// Start time in milliseconds.
+  pub start: i64,
+  /// End time in milliseconds.
+  pub end: i64,
+  pub rects: Vec<SubtitleRect>,
+  /// Source packet, kept to allow stream copy.
+  pub packet: Option<Packet>,
+}
+
+impl Subtitle {
+  /// # Safety
+  /// `subtitle` must point to a subtitle filled by `avcodec_decode_subtitle2`.
+  pub unsafe fn from_av_subtitle(
+    subtitle: *const AVSubtitle,
+    name: Option<String>,
+    index: usize,
+    fallback_pts: i64,
+  ) -> Self {
+    let pts = if (*subtitle).pts == AV_NOPTS_VALUE {
+      fallback_pts
+    } else {
+      (*subtitle).pts / 1000
+    };
+
+    let rects = tools::from_buf_raw((*subtitle).rects, (*subtitle).num_rects as usize)
+      .iter()
+      .filter_map(|rect| SubtitleRect::from_av_subtitle_rect(*rect))
+      .collect();
+
+    Subtitle {
+      name,
+      index,
+      start: pts + i64::from((*subtitle).start_display_time),
+      end: pts + i64::from((*subtitle).end_display_time),
+      rects,
+      packet: None,
+    }
+  }
+
+  pub fn get_duration(&self) -> i64 {
+    self.end - self.start
+  }
+
+  /// True when nothing is displayed, e.g. for caption data without text.
+  pub fn is_empty(&self) -> bool {
+    self.rects.iter().all(|rect| match rect {
+      SubtitleRect::Bitmap(bitmap) => bitmap.width == 0 || bitmap.height == 0,
+      rect => rect.get_text().map_or(true, |text| text.trim().is_empty()),
+    })
+  }
+
+  pub fn is_bitmap(&self) -> bool {
+    self
+      .rects
+      .iter()
+      .any(|rect| matches!(rect, SubtitleRect::Bitmap(_)))
+  }
+}
+
+impl SubtitleRect {
+  /// Format the rect as an ASS dialog event line, as expected by text subtitle encoders.
+  pub fn to_ass_dialog(&self, read_order: i32) -> Option<String> {
+    match self {
+      SubtitleRect::Text(text) => Some(format!(
+        "{read_order},0,Default,,0,0,0,,{}",
+        text.trim_end().replace("\r\n", "\\N").replace('\n', "\\N")
+      )),
+      SubtitleRect::Ass(ass) => Some(ass.clone()),
+      SubtitleRect::Bitmap(_) => None,
+    }
+  }
+
+  /// Displayed text, without the ASS event fields.
+  pub fn get_text(&self) -> Option<&str> {
+    match self {
+      SubtitleRect::Text(text) => Some(text),
+      SubtitleRect::Ass(ass) => ass.splitn(9, ',').nth(8),
+      SubtitleRect::Bitmap(_) => None,
+    }
+  }
+
+  unsafe fn from_av_subtitle_rect(rect: *const AVSubtitleRect) -> Option<Self> {
+    if rect.is_null() {
+      return None;
+    }
+
+    match (*rect).type_ {
+      AVSubtitleType::SUBTITLE_TEXT => Some(SubtitleRect::Text(tools::to_string((*rect).text))),
+      AVSubtitleType::SUBTITLE_ASS => Some(SubtitleRect::Ass(tools::to_string((*rect).ass))),
+      AVSubtitleType::SUBTITLE_BITMAP => {
+        let linesize = (*rect).linesize[0];
+        let size = (linesize * (*rect).h) as usize;
+        let data = if (*rect).data[0].is_null() {
+          vec![]
+        } else {
+          slice::from_raw_parts((*rect).data[0], size).to_vec()
+        };
+        let palette = if (*rect).data[1].is_null() {
+          vec![]
+        } else {
+          slice::from_raw_parts((*rect).data[1] as *const u32, (*rect).nb_colors as usize).to_vec()
+        };
+
+        Some(SubtitleRect::Bitmap(SubtitleBitmap {
+          x: (*rect).x,
+          y: (*rect).y,
+          width: (*rect).w,
+          height: (*rect).h,
+          data,
+          linesize,
+          palette,
+        }))
+      }
+      _ => None,
+    }
+  }
+}
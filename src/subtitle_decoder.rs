@@ -1,5 +1,6 @@
-use crate::{format_context::FormatContext, tools};
+use crate::{format_context::FormatContext, packet::Packet, subtitle::Subtitle, tools};
 use ffmpeg_sys_next::*;
+use std::{mem, ptr::null_mut};
 
 #[derive(Debug)]
 pub struct SubtitleDecoder {
@@ -16,6 +17,11 @@ impl SubtitleDecoder {
   ) -> Result<Self, String> {
     unsafe {
       let codec = avcodec_find_decoder(format.get_codec_id(stream_index));
+      if codec.is_null() {
+        return Err(format!(
+          "Unable to find a subtitle decoder for stream {stream_index}"
+        ));
+      }
       let mut codec_context = avcodec_alloc_context3(codec);
 
       check_result!(
@@ -28,6 +34,13 @@ impl SubtitleDecoder {
         }
       );
 
+      (*codec_context).pkt_timebase =
+        (**(*format.format_context).streams.offset(stream_index)).time_base;
+
+      check_result!(avcodec_open2(codec_context, codec, null_mut()), {
+        avcodec_free_context(&mut codec_context);
+      });
+
       Ok(SubtitleDecoder {
         identifier,
         stream_index,
@@ -35,6 +48,97 @@ impl SubtitleDecoder {
       })
     }
   }
+
+  pub fn get_width(&self) -> i32 {
+    unsafe { (*self.codec_context).width }
+  }
+
+  pub fn get_height(&self) -> i32 {
+    unsafe { (*self.codec_context).height }
+  }
+
+  pub fn is_bitmap(&self) -> bool {
+    unsafe {
+      let descriptor = avcodec_descriptor_get((*self.codec_context).codec_id);
+      !descriptor.is_null() && (*descriptor).props & AV_CODEC_PROP_BITMAP_SUB != 0
+    }
+  }
+
+  pub fn get_subtitle_header(&self) -> Option<String> {
+    unsafe {
+      if (*self.codec_context).subtitle_header.is_null() {
+        None
+      } else {
+        Some(tools::to_string(
+          (*self.codec_context).subtitle_header as *const _,
+        ))
+      }
+    }
+  }
+
+  pub fn decode(&self, packet: &Packet) -> Result<Option<Subtitle>, String> {
+    if packet.get_stream_index() != self.stream_index {
+      return Err("bad stream".to_string());
+    }
+    unsafe {
+      let mut av_subtitle: AVSubtitle = mem::zeroed();
+      let mut got_subtitle = 0;
+
+      check_result!(avcodec_decode_subtitle2(
+        self.codec_context,
+        &mut av_subtitle,
+        &mut got_subtitle,
+        packet.packet
+      ));
+
+      if got_subtitle == 0 {
+        return Ok(None);
+      }
+
+      let (packet_start, packet_end) = self.get_packet_times(packet);
+      let mut subtitle = Subtitle::from_av_subtitle(
+        &av_subtitle,
+        Some(self.identifier.clone()),
+        self.stream_index as usize,
+        packet_start,
+      );
+      if av_subtitle.end_display_time == 0 && packet_end > packet_start {
+        subtitle.end = subtitle.start + packet_end - packet_start;
+      }
+
+      avsubtitle_free(&mut av_subtitle);
+      Ok(Some(subtitle))
+    }
+  }
+}
+
+impl SubtitleDecoder {
+  /// Subtitle without any cue spanning the packet, so packets which do not decode
+  /// into a cue are still copied.
+  pub fn get_packet_subtitle(&self, packet: &Packet) -> Subtitle {
+    let (start, end) = unsafe { self.get_packet_times(packet) };
+    Subtitle {
+      name: Some(self.identifier.clone()),
+      index: self.stream_index as usize,
+      start,
+      end,
+      rects: vec![],
+      packet: None,
+    }
+  }
+
+  /// Start and end of a packet in milliseconds.
+  unsafe fn get_packet_times(&self, packet: &Packet) -> (i64, i64) {
+    let time_base = (*self.codec_context).pkt_timebase;
+    let milliseconds = AVRational { num: 1, den: 1000 };
+    let start = if (*packet.packet).pts == AV_NOPTS_VALUE {
+      0
+    } else {
+      av_rescale_q((*packet.packet).pts, time_base, milliseconds)
+    };
+    let duration = av_rescale_q((*packet.packet).duration.max(0), time_base, milliseconds);
+    (start, start + duration)
+  }
 }
 
 impl Drop for SubtitleDecoder {
@@ -47,3 +151,35 @@ impl Drop for SubtitleDecoder {
     }
   }
 }
+
+#[test]
+fn decode_srt_sample() {
+  use crate::subtitle::SubtitleRect;
+
+  let mut format = FormatContext::new("tests/sample.srt").unwrap();
+  format.open_input().unwrap();
+  let decoder = SubtitleDecoder::new("subtitle".to_string(), &format, 0).unwrap();
+
+  let mut subtitles = vec![];
+  let mut packet_subtitles = vec![];
+  while let Ok(packet) = format.next_packet() {
+    packet_subtitles.push(decoder.get_packet_subtitle(&packet));
+    if let Some(subtitle) = decoder.decode(&packet).unwrap() {
+      subtitles.push(subtitle);
+    }
+  }
+
+  assert_eq!(subtitles.len(), 3);
+  assert_eq!(subtitles[1].start, 1500);
+  assert_eq!(subtitles[1].end, 2500);
+  assert!(!subtitles[1].is_bitmap());
+  match &subtitles[1].rects[0] {
+    SubtitleRect::Ass(ass) => assert!(ass.ends_with("{\\i1}Tonight's the night.{\\i0}")),
+    rect => panic!("unexpected subtitle rect {rect:?}"),
+  }
+
+  assert_eq!(packet_subtitles.len(), 3);
+  assert!(packet_subtitles[1].rects.is_empty());
+  assert_eq!(packet_subtitles[1].start, 1500);
+  assert_eq!(packet_subtitles[1].end, 2500);
+}
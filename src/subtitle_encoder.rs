@@ -1,40 +1,280 @@
 use crate::{
-  order::{output::OutputStream, parameters::ParameterValue},
+  order::{
+    output::OutputStream,
+    parameters::ParameterValue,
+    subtitle_timing::{retime, SubtitleTiming},
+  },
+  packet::Packet,
+  subtitle::{Subtitle, SubtitleBitmap, SubtitleRect},
   tools,
 };
 use ffmpeg_sys_next::*;
+use std::{
+  ffi::CString,
+  mem,
+  ptr::{copy_nonoverlapping, null_mut},
+};
+
+const SUBTITLE_BUFFER_SIZE: usize = 1024 * 1024;
+
+const DEFAULT_ASS_HEADER: &str = "[Script Info]\r
+; Script generated by stainless-ffmpeg\r
+ScriptType: v4.00+\r
+PlayResX: 384\r
+PlayResY: 288\r
+ScaledBorderAndShadow: yes\r
+\r
+[V4+ Styles]\r
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\r
+Style: Default,Arial,16,&Hffffff,&Hffffff,&H0,&H0,0,0,0,0,100,100,0,0,1,1,0,2,10,10,10,1\r
+\r
+[Events]\r
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r
+";
 
 #[derive(Debug)]
 pub struct SubtitleEncoder {
   pub identifier: String,
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
+  /// Null when the codec has no encoder and packets are copied from the source.
+  pub codec: *const AVCodec,
+  pub timing: Vec<SubtitleTiming>,
+  read_order: i32,
 }
 
 impl SubtitleEncoder {
+  /// Encoder of an output stream, styled by the ASS `header` of its source, or by a
+  /// default style when the source has none.
   pub fn new(
     identifier: String,
     stream_index: isize,
     output_stream: &OutputStream,
+    header: Option<&str>,
   ) -> Result<Self, String> {
     unsafe {
       let codec = tools::get_codec(&output_stream.codec);
       if codec.is_null() {
-        return Err(format!("Unable to found codec {}", output_stream.codec));
+        return SubtitleEncoder::new_passthrough(identifier, stream_index, output_stream);
       }
-      let codec_context = avcodec_alloc_context3(codec);
+      let header = CString::new(header.unwrap_or(DEFAULT_ASS_HEADER))
+        .map_err(|_| format!("Invalid subtitle header for {identifier}"))?;
+      let mut codec_context = avcodec_alloc_context3(codec);
 
+      (*codec_context).time_base = AVRational { num: 1, den: 1000 };
       if let Some(ParameterValue::Rational(data)) = output_stream.parameters.get("frame_rate") {
         (*codec_context).time_base = data.clone().invert().into();
       }
 
+      (*codec_context).subtitle_header = av_strdup(header.as_ptr()) as *mut u8;
+      (*codec_context).subtitle_header_size = header.as_bytes().len() as i32;
+
+      check_result!(avcodec_open2(codec_context, codec, null_mut()), {
+        avcodec_free_context(&mut codec_context);
+      });
+
       Ok(SubtitleEncoder {
         identifier,
         stream_index,
         codec_context,
+        codec,
+        timing: output_stream.timing.clone(),
+        read_order: 0,
       })
     }
   }
+
+  unsafe fn new_passthrough(
+    identifier: String,
+    stream_index: isize,
+    output_stream: &OutputStream,
+  ) -> Result<Self, String> {
+    let codec_name = CString::new(output_stream.codec.as_str()).unwrap();
+    let descriptor = avcodec_descriptor_get_by_name(codec_name.as_ptr());
+    if descriptor.is_null() || (*descriptor).type_ != AVMediaType::AVMEDIA_TYPE_SUBTITLE {
+      return Err(format!("Unable to found codec {}", output_stream.codec));
+    }
+
+    let codec_context = avcodec_alloc_context3(null_mut());
+    (*codec_context).codec_type = AVMediaType::AVMEDIA_TYPE_SUBTITLE;
+    (*codec_context).codec_id = (*descriptor).id;
+    (*codec_context).time_base = AVRational { num: 1, den: 1000 };
+
+    Ok(SubtitleEncoder {
+      identifier,
+      stream_index,
+      codec_context,
+      codec: null_mut(),
+      timing: output_stream.timing.clone(),
+      read_order: 0,
+    })
+  }
+
+  pub fn get_codec_name(&self) -> String {
+    unsafe { tools::to_string(avcodec_get_name((*self.codec_context).codec_id)) }
+  }
+
+  pub fn is_bitmap(&self) -> bool {
+    unsafe {
+      let descriptor = avcodec_descriptor_get((*self.codec_context).codec_id);
+      !descriptor.is_null() && (*descriptor).props & AV_CODEC_PROP_BITMAP_SUB != 0
+    }
+  }
+
+  /// Encode the subtitle after applying the timing transforms, returns no packet when it
+  /// is cut out. Empty cues clear the display of bitmap codecs and are dropped otherwise.
+  pub fn encode(&mut self, subtitle: &Subtitle) -> Result<Vec<Packet>, String> {
+    // copied packets are not converted, and may hold no decoded cue
+    if !self.codec.is_null() {
+      if subtitle.is_empty() {
+        if !self.is_bitmap() {
+          return Ok(vec![]);
+        }
+      } else if subtitle.is_bitmap() && !self.is_bitmap() {
+        return Err(format!(
+          "Unable to convert bitmap subtitles to text codec {}",
+          self.get_codec_name()
+        ));
+      } else if !subtitle.is_bitmap() && self.is_bitmap() {
+        return Err(format!(
+          "Unable to convert text subtitles to bitmap codec {}",
+          self.get_codec_name()
+        ));
+      }
+    }
+
+    let (start, end) = match retime(&self.timing, subtitle.start, subtitle.end) {
+      Some(times) => times,
+      None => return Ok(vec![]),
+    };
+
+    let packet = if self.codec.is_null() {
+      self.copy(subtitle)?
+    } else {
+      unsafe { self.encode_subtitle(subtitle, start, end)? }
+    };
+
+    unsafe {
+      let milliseconds = AVRational { num: 1, den: 1000 };
+      let time_base = (*self.codec_context).time_base;
+      (*packet.packet).pts = av_rescale_q(start, milliseconds, time_base);
+      (*packet.packet).dts = (*packet.packet).pts;
+      (*packet.packet).duration = av_rescale_q(end - start, milliseconds, time_base);
+    }
+    Ok(vec![packet])
+  }
+
+  fn copy(&self, subtitle: &Subtitle) -> Result<Packet, String> {
+    match subtitle.packet {
+      Some(ref source) => unsafe {
+        let packet = av_packet_clone(source.packet);
+        if packet.is_null() {
+          return Err("Unable to copy subtitle packet".to_string());
+        }
+        Ok(Packet { name: None, packet })
+      },
+      None => Err(format!(
+        "No source packet to copy into {} subtitle stream",
+        self.get_codec_name()
+      )),
+    }
+  }
+
+  unsafe fn encode_subtitle(
+    &mut self,
+    subtitle: &Subtitle,
+    start: i64,
+    end: i64,
+  ) -> Result<Packet, String> {
+    let mut av_subtitle: AVSubtitle = mem::zeroed();
+    av_subtitle.format = if self.is_bitmap() { 0 } else { 1 };
+    av_subtitle.pts = start * 1000;
+    av_subtitle.start_display_time = 0;
+    av_subtitle.end_display_time = (end - start) as u32;
+
+    // an empty cue is a clear event, encoded without rects, which the DVD subtitle
+    // encoder rejects: it gets a transparent pixel instead
+    let rects: Vec<*mut AVSubtitleRect> = if !subtitle.is_empty() {
+      subtitle
+        .rects
+        .iter()
+        .map(|rect| {
+          self.read_order += 1;
+          to_av_subtitle_rect(rect, self.read_order)
+        })
+        .collect()
+    } else if (*self.codec_context).codec_id == AVCodecID::AV_CODEC_ID_DVD_SUBTITLE {
+      let transparent = SubtitleRect::Bitmap(SubtitleBitmap {
+        x: 0,
+        y: 0,
+        width: 1,
+        height: 1,
+        data: vec![0],
+        linesize: 1,
+        palette: vec![0],
+      });
+      vec![to_av_subtitle_rect(&transparent, 0)]
+    } else {
+      vec![]
+    };
+
+    av_subtitle.num_rects = rects.len() as u32;
+    av_subtitle.rects =
+      av_mallocz(rects.len() * mem::size_of::<*mut AVSubtitleRect>()) as *mut *mut AVSubtitleRect;
+    copy_nonoverlapping(rects.as_ptr(), av_subtitle.rects, rects.len());
+
+    let mut buffer = vec![0u8; SUBTITLE_BUFFER_SIZE];
+    let size = avcodec_encode_subtitle(
+      self.codec_context,
+      buffer.as_mut_ptr(),
+      SUBTITLE_BUFFER_SIZE as i32,
+      &av_subtitle,
+    );
+    avsubtitle_free(&mut av_subtitle);
+    check_result!(size);
+
+    let mut packet = av_packet_alloc();
+    check_result!(av_new_packet(packet, size), {
+      av_packet_free(&mut packet);
+    });
+    copy_nonoverlapping(buffer.as_ptr(), (*packet).data, size as usize);
+
+    trace!("received encoded subtitle packet with {} bytes", size);
+    Ok(Packet { name: None, packet })
+  }
+}
+
+unsafe fn to_av_subtitle_rect(rect: &SubtitleRect, read_order: i32) -> *mut AVSubtitleRect {
+  let av_rect = av_mallocz(mem::size_of::<AVSubtitleRect>()) as *mut AVSubtitleRect;
+
+  match rect {
+    SubtitleRect::Text(_) | SubtitleRect::Ass(_) => {
+      let dialog = CString::new(rect.to_ass_dialog(read_order).unwrap_or_default()).unwrap();
+      (*av_rect).type_ = AVSubtitleType::SUBTITLE_ASS;
+      (*av_rect).ass = av_strdup(dialog.as_ptr());
+    }
+    SubtitleRect::Bitmap(bitmap) => {
+      (*av_rect).type_ = AVSubtitleType::SUBTITLE_BITMAP;
+      (*av_rect).x = bitmap.x;
+      (*av_rect).y = bitmap.y;
+      (*av_rect).w = bitmap.width;
+      (*av_rect).h = bitmap.height;
+      (*av_rect).nb_colors = bitmap.palette.len() as i32;
+      (*av_rect).linesize[0] = bitmap.linesize;
+
+      (*av_rect).data[0] = av_malloc(bitmap.data.len()) as *mut u8;
+      copy_nonoverlapping(bitmap.data.as_ptr(), (*av_rect).data[0], bitmap.data.len());
+
+      (*av_rect).data[1] = av_mallocz(AVPALETTE_SIZE as usize) as *mut u8;
+      copy_nonoverlapping(
+        bitmap.palette.as_ptr(),
+        (*av_rect).data[1] as *mut u32,
+        bitmap.palette.len().min(AVPALETTE_COUNT as usize),
+      );
+    }
+  }
+
+  av_rect
 }
 
 impl Drop for SubtitleEncoder {
@@ -47,3 +287,128 @@ impl Drop for SubtitleEncoder {
     }
   }
 }
+
+#[cfg(test)]
+fn convert_subtitles(input: &str, output: &std::path::Path, codec: &str) -> Vec<u8> {
+  use crate::order::Order;
+
+  let mut order = Order::new_parse(&format!(
+    r#"{{
+      "inputs": [
+        {{
+          "id": 1,
+          "path": "{input}",
+          "streams": [{{"kind": "subtitle", "label": "subtitle_track", "index": 0}}]
+        }}
+      ],
+      "graph": [],
+      "outputs": [
+        {{
+          "kind": "file",
+          "path": "{}",
+          "streams": [{{"label": "subtitle_track", "codec": "{codec}", "parameters": {{}}}}]
+        }}
+      ]
+    }}"#,
+    output.display()
+  ))
+  .unwrap();
+  order.setup().unwrap();
+  order.process().unwrap();
+  drop(order);
+  std::fs::read(output).unwrap()
+}
+
+#[test]
+fn convert_srt_sample() {
+  let directory = std::env::temp_dir().join(format!("subtitles_{}", tools::random_string(8)));
+  std::fs::create_dir_all(&directory).unwrap();
+
+  for (codec, extension) in [
+    ("webvtt", "vtt"),
+    ("ass", "ass"),
+    ("mov_text", "mp4"),
+    ("ttml", "ttml"),
+  ] {
+    let output = directory.join(format!("sample.{extension}"));
+    let content = convert_subtitles("tests/sample.srt", &output, codec);
+    assert!(!content.is_empty(), "empty {codec} output");
+    if codec != "mov_text" {
+      assert!(
+        String::from_utf8_lossy(&content).contains("the night"),
+        "missing cue in {codec} output"
+      );
+    }
+  }
+
+  std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn keep_ass_styles() {
+  let directory = std::env::temp_dir().join(format!("subtitles_{}", tools::random_string(8)));
+  std::fs::create_dir_all(&directory).unwrap();
+
+  let input = directory.join("styled.ass");
+  std::fs::write(
+    &input,
+    "[Script Info]\r
+ScriptType: v4.00+\r
+PlayResX: 1920\r
+PlayResY: 1080\r
+\r
+[V4+ Styles]\r
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\r
+Style: Default,Arial,48,&Hffffff,&Hffffff,&H0,&H0,0,0,0,0,100,100,0,0,1,1,0,2,10,10,10,1\r
+Style: Sign,Arial,64,&H00ffff,&Hffffff,&H0,&H0,1,0,0,0,100,100,0,0,1,1,0,8,10,10,10,1\r
+\r
+[Events]\r
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r
+Dialogue: 0,0:00:01.00,0:00:02.00,Sign,,0,0,0,,Exit\r
+",
+  )
+  .unwrap();
+
+  let output = directory.join("styled_copy.ass");
+  let content = convert_subtitles(input.to_str().unwrap(), &output, "ass");
+  let content = String::from_utf8_lossy(&content);
+  assert!(content.contains("PlayResX: 1920"));
+  assert!(content.contains("Style: Sign,Arial,64"));
+  assert!(content.contains(",Sign,"));
+
+  std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn encode_empty_cues() {
+  let new_subtitle = |end: i64, rects: Vec<SubtitleRect>| Subtitle {
+    name: Some("subtitle".to_string()),
+    index: 0,
+    start: 1000,
+    end,
+    rects,
+    packet: None,
+  };
+  let clear = new_subtitle(1000, vec![]);
+  let text = new_subtitle(2000, vec![SubtitleRect::Text("Hello".to_string())]);
+
+  for (codec, name, packets) in [
+    ("dvbsub", "dvb_subtitle", 1),
+    ("dvdsub", "dvd_subtitle", 1),
+    ("webvtt", "webvtt", 0),
+  ] {
+    let output_stream: OutputStream = serde_json::from_str(&format!(
+      r#"{{"label": "subtitle", "codec": "{codec}", "parameters": {{}}}}"#
+    ))
+    .unwrap();
+    let mut encoder =
+      SubtitleEncoder::new("subtitle".to_string(), 0, &output_stream, None).unwrap();
+    assert_eq!(encoder.encode(&clear).unwrap().len(), packets, "{codec}");
+    if packets == 1 {
+      assert_eq!(
+        encoder.encode(&text).unwrap_err(),
+        format!("Unable to convert text subtitles to bitmap codec {name}")
+      );
+    }
+  }
+}
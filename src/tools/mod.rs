@@ -1,4 +1,6 @@
-use ffmpeg_sys_next::{avcodec_find_encoder_by_name, AVCodec, AVMediaType};
+use ffmpeg_sys_next::{
+  avcodec_descriptor_get_by_name, avcodec_find_encoder_by_name, AVCodec, AVMediaType,
+};
 use libc::c_char;
 use rand::{prelude::SliceRandom, thread_rng};
 use std::{
@@ -50,8 +52,27 @@ pub fn get_codec_type(codec_name: &str) -> Option<AVMediaType> {
     let cs_codec_name = CString::new(codec_name).unwrap();
     let codec = avcodec_find_encoder_by_name(cs_codec_name.as_ptr());
     if codec.is_null() {
+      // subtitle codecs without encoder can still be copied from their source packets
+      let descriptor = avcodec_descriptor_get_by_name(cs_codec_name.as_ptr());
+      if !descriptor.is_null() && (*descriptor).type_ == AVMediaType::AVMEDIA_TYPE_SUBTITLE {
+        return Some(AVMediaType::AVMEDIA_TYPE_SUBTITLE);
+      }
       return None;
     }
     Some((*codec).type_)
   }
 }
+
+/// Codec tag of a four character code, like `apch` or `avc1`.
+pub fn codec_tag_from_fourcc(fourcc: &str) -> Result<u32, String> {
+  let bytes = fourcc.as_bytes();
+  if bytes.len() != 4 {
+    return Err(format!("'{fourcc}' is not a valid value for codec_tag"));
+  }
+  Ok(
+    bytes
+      .iter()
+      .rev()
+      .fold(0, |tag, byte| (tag << 8) | u32::from(*byte)),
+  )
+}
@@ -2,16 +2,22 @@ use crate::{
   format_context::FormatContext,
   frame::Frame,
   packet::Packet,
+  side_data::{BarData, BAR_DATA_METADATA_KEY},
   tools::{self, rational::Rational},
 };
 use ffmpeg_sys_next::*;
-use std::{ffi::CString, ptr::null_mut};
+use std::{cell::RefCell, ffi::CString, ptr::null_mut, slice};
+
+/// Bar data kept for pictures not output yet, when the decoder reorders them.
+const MAX_PENDING_BAR_DATA: usize = 32;
 
 #[derive(Debug)]
 pub struct VideoDecoder {
   pub identifier: String,
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
+  /// Bar data of the decoded packets by timestamp, set on the frames of the same timestamp.
+  pub(crate) bar_data: RefCell<Vec<(i64, BarData)>>,
 }
 
 impl VideoDecoder {
@@ -44,6 +50,7 @@ impl VideoDecoder {
         identifier,
         stream_index,
         codec_context,
+        bar_data: RefCell::default(),
       })
     }
   }
@@ -70,6 +77,7 @@ impl VideoDecoder {
         identifier,
         stream_index,
         codec_context,
+        bar_data: RefCell::default(),
       })
     }
   }
@@ -121,18 +129,54 @@ impl VideoDecoder {
       return Err("bad stream".to_string());
     }
     unsafe {
+      self.keep_bar_data(packet);
       check_result!(avcodec_send_packet(self.codec_context, packet.packet));
 
       let frame = av_frame_alloc();
 
       check_result!(avcodec_receive_frame(self.codec_context, frame));
 
-      Ok(Frame {
+      let frame = Frame {
         frame,
         name: Some(self.identifier.clone()),
         index: self.stream_index as usize,
-      })
+      };
+      self.set_bar_data(frame.frame)?;
+      Ok(frame)
+    }
+  }
+
+  /// FFmpeg decoders do not export bar data, it is read from the packets.
+  unsafe fn keep_bar_data(&self, packet: &Packet) {
+    let packet = packet.packet;
+    if packet.is_null() || (*packet).data.is_null() {
+      return;
     }
+    let data = slice::from_raw_parts((*packet).data, (*packet).size as usize);
+    if let Some(bar_data) = BarData::find(data) {
+      let mut pending = self.bar_data.borrow_mut();
+      if pending.len() == MAX_PENDING_BAR_DATA {
+        pending.remove(0);
+      }
+      pending.push(((*packet).pts, bar_data));
+    }
+  }
+
+  unsafe fn set_bar_data(&self, frame: *mut AVFrame) -> Result<(), String> {
+    let mut pending = self.bar_data.borrow_mut();
+    let Some(position) = pending.iter().position(|(pts, _)| *pts == (*frame).pts) else {
+      return Ok(());
+    };
+    let (_, bar_data) = pending.remove(position);
+    let key = CString::new(BAR_DATA_METADATA_KEY).unwrap();
+    let value = CString::new(bar_data.to_string()).unwrap();
+    check_result!(av_dict_set(
+      &mut (*frame).metadata,
+      key.as_ptr(),
+      value.as_ptr(),
+      0
+    ));
+    Ok(())
   }
 }
 
@@ -1,14 +1,175 @@
 use crate::{
+  codec::{join, Codec},
   frame::Frame,
+  hdr::{ContentLightLevel, HdrMetadata, MasteringDisplayMetadata},
   order::{
-    output::{ColorRange, Colorspace, OutputStream, PixelFormat},
-    parameters::ParameterValue,
+    output::{
+      ChromaLocation, ColorPrimaries, ColorRange, ColorTransfer, Colorspace, FieldOrder,
+      OutputStream, PixelFormat, TimestampMode,
+    },
+    parameters::{set_codec_options, ParameterValue},
   },
   packet::Packet,
   tools,
 };
 use ffmpeg_sys_next::*;
-use std::ptr::null_mut;
+use libc::c_void;
+use std::{
+  collections::HashMap,
+  convert::TryFrom,
+  ffi::CString,
+  mem::size_of,
+  path::{Path, PathBuf},
+  ptr::{null, null_mut},
+};
+
+/// Parameters mapped to codec context fields, the other ones are set as codec options.
+const VIDEO_ENCODER_PARAMETERS: [&str; 31] = [
+  "frame_rate",
+  "sample_aspect_ratio",
+  "pixel_format",
+  "width",
+  "height",
+  "bitrate",
+  "minrate",
+  "maxrate",
+  "bufsize",
+  "rc_init_occupancy",
+  "qmin",
+  "qmax",
+  "gop_size",
+  "max_b_frames",
+  "refs",
+  "keyint_min",
+  "colorspace",
+  "color_range",
+  "color_primaries",
+  "color_trc",
+  "chroma_location",
+  "master_display",
+  "max_cll",
+  "max_fall",
+  "timestamp_mode",
+  "codec_tag",
+  "field_order",
+  "interlaced_dct",
+  "interlaced_me",
+  "intra_matrix",
+  "inter_matrix",
+];
+
+/// Rate control statistics collected by a first pass encoder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PassStats {
+  /// Lines of the codec context `stats_out`, accumulated over the encoded frames.
+  Log(String),
+  /// File written by encoders keeping their statistics themselves, like libx264.
+  File(PathBuf),
+}
+
+/// Rate control pass of an encoder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodingPass {
+  Single,
+  /// Collect the statistics, available with `get_stats` once the encoder is flushed.
+  First,
+  /// Encode using the statistics of the first pass.
+  Second {
+    stats: PassStats,
+  },
+}
+
+/// Rate control of an encoder, bitrates in bits per second and buffer sizes in bits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RateControl {
+  pub bitrate: Option<i64>,
+  pub min_rate: Option<i64>,
+  pub max_rate: Option<i64>,
+  pub buffer_size: Option<i64>,
+  pub initial_buffer_occupancy: Option<i64>,
+  pub qmin: Option<i64>,
+  pub qmax: Option<i64>,
+}
+
+impl RateControl {
+  pub fn from_parameters(parameters: &HashMap<String, ParameterValue>) -> Result<Self, String> {
+    let get = |key: &str| match parameters.get(key) {
+      None => Ok(None),
+      Some(ParameterValue::Int64(value)) if *value >= 0 => Ok(Some(*value)),
+      Some(value) => Err(format!("'{value:?}' is not a valid value for {key}")),
+    };
+
+    let rate_control = RateControl {
+      bitrate: get("bitrate")?,
+      min_rate: get("minrate")?,
+      max_rate: get("maxrate")?,
+      buffer_size: get("bufsize")?,
+      initial_buffer_occupancy: get("rc_init_occupancy")?,
+      qmin: get("qmin")?,
+      qmax: get("qmax")?,
+    };
+    rate_control.validate()?;
+    Ok(rate_control)
+  }
+
+  fn validate(&self) -> Result<(), String> {
+    if let (Some(min_rate), Some(max_rate)) = (self.min_rate, self.max_rate) {
+      if min_rate > max_rate {
+        return Err(format!("minrate {min_rate} is above maxrate {max_rate}"));
+      }
+    }
+    if let Some(bitrate) = self.bitrate {
+      if let Some(min_rate) = self.min_rate.filter(|min_rate| bitrate < *min_rate) {
+        return Err(format!("bitrate {bitrate} is below minrate {min_rate}"));
+      }
+      if let Some(max_rate) = self.max_rate.filter(|max_rate| bitrate > *max_rate) {
+        return Err(format!("bitrate {bitrate} is above maxrate {max_rate}"));
+      }
+    }
+    if self.max_rate.is_some() && self.buffer_size.is_none() {
+      return Err("maxrate requires a bufsize".to_string());
+    }
+    if let (Some(occupancy), Some(buffer_size)) = (self.initial_buffer_occupancy, self.buffer_size)
+    {
+      if occupancy > buffer_size {
+        return Err(format!(
+          "rc_init_occupancy {occupancy} is above bufsize {buffer_size}"
+        ));
+      }
+    }
+    if let (Some(qmin), Some(qmax)) = (self.qmin, self.qmax) {
+      if qmin > qmax {
+        return Err(format!("qmin {qmin} is above qmax {qmax}"));
+      }
+    }
+    Ok(())
+  }
+
+  /// # Safety
+  pub unsafe fn set_to_codec_context(&self, codec_context: *mut AVCodecContext) {
+    if let Some(bitrate) = self.bitrate {
+      (*codec_context).bit_rate = bitrate;
+    }
+    if let Some(min_rate) = self.min_rate {
+      (*codec_context).rc_min_rate = min_rate;
+    }
+    if let Some(max_rate) = self.max_rate {
+      (*codec_context).rc_max_rate = max_rate;
+    }
+    if let Some(buffer_size) = self.buffer_size {
+      (*codec_context).rc_buffer_size = buffer_size as i32;
+    }
+    if let Some(occupancy) = self.initial_buffer_occupancy {
+      (*codec_context).rc_initial_buffer_occupancy = occupancy as i32;
+    }
+    if let Some(qmin) = self.qmin {
+      (*codec_context).qmin = qmin as i32;
+    }
+    if let Some(qmax) = self.qmax {
+      (*codec_context).qmax = qmax as i32;
+    }
+  }
+}
 
 #[derive(Debug)]
 pub struct VideoEncoder {
@@ -16,7 +177,18 @@ pub struct VideoEncoder {
   pub stream_index: isize,
   pub codec_context: *mut AVCodecContext,
   pub codec: *const AVCodec,
-  pub pts: i64,
+  pub hdr_metadata: HdrMetadata,
+  pub timestamp_mode: TimestampMode,
+  pub field_order: FieldOrder,
+  /// Time base of the frames sent to the encoder, the one of the filter graph output.
+  input_time_base: AVRational,
+  last_pts: Option<i64>,
+  /// Owns the first pass statistics pointed by the codec context `stats_in`.
+  stats_in: Option<CString>,
+  /// Statistics collected by a first pass encoder.
+  stats: Option<PassStats>,
+  /// First pass statistics file, removed once the second pass is done.
+  stats_file: Option<PathBuf>,
 }
 
 impl VideoEncoder {
@@ -24,6 +196,20 @@ impl VideoEncoder {
     identifier: String,
     stream_index: isize,
     output_stream: &OutputStream,
+  ) -> Result<Self, String> {
+    VideoEncoder::new_with_pass(
+      identifier,
+      stream_index,
+      output_stream,
+      EncodingPass::Single,
+    )
+  }
+
+  pub fn new_with_pass(
+    identifier: String,
+    stream_index: isize,
+    output_stream: &OutputStream,
+    pass: EncodingPass,
   ) -> Result<Self, String> {
     unsafe {
       let codec = tools::get_codec(&output_stream.codec);
@@ -41,9 +227,12 @@ impl VideoEncoder {
         (*codec_context).sample_aspect_ratio = data.clone().into();
       }
 
-      if let Some(ParameterValue::String(data)) = parameters.get("pixel_format") {
-        let format: PixelFormat = data.parse().unwrap();
-        (*codec_context).pix_fmt = format.into();
+      match VideoEncoder::select_pixel_format(&Codec { codec }, parameters) {
+        Ok(format) => (*codec_context).pix_fmt = format.into(),
+        Err(msg) => {
+          avcodec_free_context(&mut codec_context);
+          return Err(msg);
+        }
       }
 
       if let Some(ParameterValue::Int64(data)) = parameters.get("width") {
@@ -54,8 +243,12 @@ impl VideoEncoder {
         (*codec_context).height = *data as i32;
       }
 
-      if let Some(ParameterValue::Int64(data)) = parameters.get("bitrate") {
-        (*codec_context).bit_rate = *data;
+      match RateControl::from_parameters(parameters) {
+        Ok(rate_control) => rate_control.set_to_codec_context(codec_context),
+        Err(msg) => {
+          avcodec_free_context(&mut codec_context);
+          return Err(msg);
+        }
       }
 
       if let Some(ParameterValue::Int64(data)) = parameters.get("gop_size") {
@@ -75,15 +268,224 @@ impl VideoEncoder {
       }
 
       if let Some(ParameterValue::String(data)) = parameters.get("colorspace") {
-        let colorspace: Colorspace = data.parse().unwrap();
-        (*codec_context).colorspace = colorspace.into();
+        match data.parse::<Colorspace>() {
+          Ok(colorspace) => (*codec_context).colorspace = colorspace.into(),
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        }
       }
 
       if let Some(ParameterValue::String(data)) = parameters.get("color_range") {
-        let color_range: ColorRange = data.parse().unwrap();
-        (*codec_context).color_range = color_range.into();
+        match data.parse::<ColorRange>() {
+          Ok(color_range) => (*codec_context).color_range = color_range.into(),
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        }
+      }
+
+      if let Some(ParameterValue::String(data)) = parameters.get("color_primaries") {
+        match data.parse::<ColorPrimaries>() {
+          Ok(color_primaries) => (*codec_context).color_primaries = color_primaries.into(),
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        }
+      }
+
+      if let Some(ParameterValue::String(data)) = parameters.get("color_trc") {
+        match data.parse::<ColorTransfer>() {
+          Ok(color_trc) => (*codec_context).color_trc = color_trc.into(),
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        }
+      }
+
+      if let Some(ParameterValue::String(data)) = parameters.get("chroma_location") {
+        match data.parse::<ChromaLocation>() {
+          Ok(chroma_location) => (*codec_context).chroma_sample_location = chroma_location.into(),
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        }
+      }
+
+      for key in ["intra_matrix", "inter_matrix"] {
+        if let Some(ParameterValue::String(data)) = parameters.get(key) {
+          let matrix = match parse_matrix(key, data) {
+            Ok(matrix) => matrix,
+            Err(msg) => {
+              avcodec_free_context(&mut codec_context);
+              return Err(msg);
+            }
+          };
+          // freed with the codec context
+          let coefficients = av_malloc(size_of::<[u16; 64]>()) as *mut u16;
+          if coefficients.is_null() {
+            avcodec_free_context(&mut codec_context);
+            return Err(format!("Unable to allocate the {key}"));
+          }
+          coefficients.copy_from_nonoverlapping(matrix.as_ptr(), matrix.len());
+          if key == "intra_matrix" {
+            (*codec_context).intra_matrix = coefficients;
+          } else {
+            (*codec_context).inter_matrix = coefficients;
+          }
+        }
+      }
+
+      if let Some(ParameterValue::String(data)) = parameters.get("codec_tag") {
+        match tools::codec_tag_from_fourcc(data) {
+          Ok(codec_tag) => (*codec_context).codec_tag = codec_tag,
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        }
+      }
+
+      let field_order = match parameters.get("field_order") {
+        Some(ParameterValue::String(data)) => match data.parse::<FieldOrder>() {
+          Ok(field_order) => field_order,
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        },
+        _ => FieldOrder::default(),
+      };
+      (*codec_context).field_order = field_order.into();
+
+      // interlaced encoding codes the fields separately, unless disabled explicitly
+      let interlaced_flag = |key: &str| match parameters.get(key) {
+        Some(ParameterValue::Bool(data)) => *data,
+        _ => field_order.is_interlaced(),
+      };
+      if interlaced_flag("interlaced_dct") {
+        (*codec_context).flags |= AV_CODEC_FLAG_INTERLACED_DCT as i32;
+      }
+      if interlaced_flag("interlaced_me") {
+        (*codec_context).flags |= AV_CODEC_FLAG_INTERLACED_ME as i32;
+      }
+
+      let timestamp_mode = match parameters.get("timestamp_mode") {
+        Some(ParameterValue::String(data)) => match data.parse() {
+          Ok(timestamp_mode) => timestamp_mode,
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        },
+        _ => TimestampMode::default(),
+      };
+
+      let mut hdr_metadata = HdrMetadata::default();
+      if let Some(ParameterValue::String(data)) = parameters.get("master_display") {
+        match data.parse::<MasteringDisplayMetadata>() {
+          Ok(mastering_display) => hdr_metadata.mastering_display = Some(mastering_display),
+          Err(msg) => {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+        }
       }
 
+      match (parameters.get("max_cll"), parameters.get("max_fall")) {
+        (Some(ParameterValue::Int64(max_cll)), max_fall) => {
+          let max_fall = match max_fall {
+            Some(ParameterValue::Int64(max_fall)) => *max_fall,
+            _ => 0,
+          };
+          let content_light_level = parse_light_level("max_cll", *max_cll).and_then(|max_cll| {
+            parse_light_level("max_fall", max_fall)
+              .map(|max_fall| ContentLightLevel { max_cll, max_fall })
+          });
+          match content_light_level {
+            Ok(content_light_level) => hdr_metadata.content_light_level = Some(content_light_level),
+            Err(msg) => {
+              avcodec_free_context(&mut codec_context);
+              return Err(msg);
+            }
+          }
+        }
+        (None, Some(_)) => {
+          avcodec_free_context(&mut codec_context);
+          return Err("max_fall requires a max_cll".to_string());
+        }
+        _ => {}
+      }
+
+      if let Err(msg) = set_codec_options(codec_context, parameters, &VIDEO_ENCODER_PARAMETERS) {
+        avcodec_free_context(&mut codec_context);
+        return Err(msg);
+      }
+
+      let stats_option = CString::new("stats").unwrap();
+      let has_stats_file = !av_opt_find(
+        codec_context as *mut c_void,
+        stats_option.as_ptr(),
+        null(),
+        0,
+        AV_OPT_SEARCH_CHILDREN,
+      )
+      .is_null();
+
+      let (stats_in, stats, stats_file) = match pass {
+        EncodingPass::Single => (None, None, None),
+        EncodingPass::First => {
+          (*codec_context).flags |= AV_CODEC_FLAG_PASS1 as i32;
+          if has_stats_file {
+            let path = std::env::temp_dir()
+              .join(format!("stainless_ffmpeg_{}.log", tools::random_string(8)));
+            if let Err(msg) = set_stats_file(codec_context, &path) {
+              avcodec_free_context(&mut codec_context);
+              return Err(msg);
+            }
+            (None, Some(PassStats::File(path)), None)
+          } else {
+            (None, Some(PassStats::Log(String::new())), None)
+          }
+        }
+        EncodingPass::Second {
+          stats: PassStats::Log(stats),
+        } => {
+          let stats = match CString::new(stats) {
+            Ok(stats) => stats,
+            Err(_) => {
+              avcodec_free_context(&mut codec_context);
+              return Err("Invalid first pass statistics".to_string());
+            }
+          };
+          (*codec_context).flags |= AV_CODEC_FLAG_PASS2 as i32;
+          (*codec_context).stats_in = stats.as_ptr() as *mut _;
+          (Some(stats), None, None)
+        }
+        EncodingPass::Second {
+          stats: PassStats::File(path),
+        } => {
+          if !has_stats_file {
+            avcodec_free_context(&mut codec_context);
+            return Err(format!(
+              "The {} encoder does not read first pass statistics files",
+              output_stream.codec
+            ));
+          }
+          if let Err(msg) = set_stats_file(codec_context, &path) {
+            avcodec_free_context(&mut codec_context);
+            return Err(msg);
+          }
+          (*codec_context).flags |= AV_CODEC_FLAG_PASS2 as i32;
+          (None, None, Some(path))
+        }
+      };
+
       check_result!(avcodec_open2(codec_context, codec, null_mut()), {
         avcodec_free_context(&mut codec_context);
       });
@@ -93,7 +495,14 @@ impl VideoEncoder {
         stream_index,
         codec_context,
         codec,
-        pts: 0,
+        hdr_metadata,
+        timestamp_mode,
+        field_order,
+        input_time_base: (*codec_context).time_base,
+        last_pts: None,
+        stats_in,
+        stats,
+        stats_file,
       })
     }
   }
@@ -117,6 +526,122 @@ impl VideoEncoder {
     }
   }
 
+  pub fn get_frame_rate(&self) -> AVRational {
+    unsafe {
+      AVRational {
+        num: (*self.codec_context).time_base.den,
+        den: (*self.codec_context).time_base.num,
+      }
+    }
+  }
+
+  /// Set the time base of the incoming frames, by default the encoder time base.
+  pub fn set_input_time_base(&mut self, time_base: AVRational) {
+    if time_base.num > 0 && time_base.den > 0 {
+      self.input_time_base = time_base;
+    }
+  }
+
+  /// Rescale a frame timestamp to the encoder time base, keeping timestamps increasing.
+  fn rescale_pts(&mut self, pts: i64) -> i64 {
+    let pts = match (self.timestamp_mode.clone(), self.last_pts) {
+      (TimestampMode::Cfr, Some(last_pts)) => last_pts + 1,
+      (_, last_pts) if pts == AV_NOPTS_VALUE => last_pts.map_or(0, |last_pts| last_pts + 1),
+      (_, last_pts) => {
+        let pts =
+          unsafe { av_rescale_q(pts, self.input_time_base, (*self.codec_context).time_base) };
+        match last_pts {
+          Some(last_pts) if pts <= last_pts => {
+            warn!(
+              "non monotonic timestamp {} after {} on {}",
+              pts, last_pts, self.identifier
+            );
+            last_pts + 1
+          }
+          _ => pts,
+        }
+      }
+    };
+    self.last_pts = Some(pts);
+    pts
+  }
+
+  /// The requested pixel format checked against the codec, or the first one it supports.
+  fn select_pixel_format(
+    codec: &Codec,
+    parameters: &HashMap<String, ParameterValue>,
+  ) -> Result<PixelFormat, String> {
+    let supported_formats = codec.get_pixel_formats();
+    match parameters.get("pixel_format") {
+      Some(ParameterValue::String(data)) => {
+        let format: PixelFormat = data.parse()?;
+        if !codec.supports_pixel_format(&format) {
+          return Err(format!(
+            "Pixel format {format} is not supported by the {} encoder, supported formats: {}",
+            codec.get_name(),
+            join(&supported_formats)
+          ));
+        }
+        Ok(format)
+      }
+      _ => {
+        let format = supported_formats.first().copied().ok_or_else(|| {
+          format!(
+            "A pixel_format parameter is required by the {} encoder",
+            codec.get_name()
+          )
+        })?;
+        info!(
+          "Use {format} pixel format for the {} encoder",
+          codec.get_name()
+        );
+        Ok(format)
+      }
+    }
+  }
+
+  /// Parameters of the `format` filter converting frames to the encoder pixel format.
+  pub fn get_input_format(&self) -> HashMap<String, ParameterValue> {
+    let mut parameters = HashMap::new();
+    let pix_fmt = unsafe { (*self.codec_context).pix_fmt as i32 };
+    if let Ok(format) = PixelFormat::try_from(pix_fmt) {
+      parameters.insert(
+        "pix_fmts".to_string(),
+        ParameterValue::String(format.to_string()),
+      );
+    }
+    parameters
+  }
+
+  /// Parameters of the `scale` filter converting frames to the encoder size, when it is set.
+  pub fn get_input_size(&self) -> Option<HashMap<String, ParameterValue>> {
+    let (width, height) = unsafe { ((*self.codec_context).width, (*self.codec_context).height) };
+    if width <= 0 || height <= 0 {
+      return None;
+    }
+    let mut parameters = HashMap::new();
+    parameters.insert("w".to_string(), ParameterValue::Int64(i64::from(width)));
+    parameters.insert("h".to_string(), ParameterValue::Int64(i64::from(height)));
+    Some(parameters)
+  }
+
+  /// Rate control statistics written by a first pass encoder, complete once it is flushed.
+  /// Statistics files are only complete once the encoder is dropped.
+  pub fn get_stats(&self) -> Option<PassStats> {
+    self.stats.clone()
+  }
+
+  /// Keep the statistics of the last encoded frame, as `stats_out` only holds those.
+  fn collect_stats(&mut self) {
+    unsafe {
+      if let Some(PassStats::Log(stats)) = &mut self.stats {
+        if !(*self.codec_context).stats_out.is_null() {
+          stats.push_str(&tools::to_string((*self.codec_context).stats_out));
+        }
+      }
+    }
+  }
+
   pub fn get_aspect_ratio(&self, num: i32, den: i32) {
     unsafe {
       (*self.codec_context).sample_aspect_ratio.num = num;
@@ -124,12 +649,82 @@ impl VideoEncoder {
     }
   }
 
+  /// Set the HDR static metadata on frames which do not carry it, as encoders read it there.
+  fn attach_hdr_metadata(&self, frame: &Frame) -> Result<(), String> {
+    unsafe {
+      if let Some(mastering_display) = &self.hdr_metadata.mastering_display {
+        let side_data_type = AVFrameSideDataType::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA;
+        if av_frame_get_side_data(frame.frame, side_data_type).is_null() {
+          let side_data = av_frame_new_side_data(
+            frame.frame,
+            side_data_type,
+            size_of::<AVMasteringDisplayMetadata>() as _,
+          );
+          if side_data.is_null() {
+            return Err("Unable to allocate mastering display metadata".to_string());
+          }
+          let metadata = (*side_data).data as *mut AVMasteringDisplayMetadata;
+          *metadata = std::mem::zeroed();
+          mastering_display.write_to(metadata);
+        }
+      }
+
+      if let Some(content_light_level) = &self.hdr_metadata.content_light_level {
+        let side_data_type = AVFrameSideDataType::AV_FRAME_DATA_CONTENT_LIGHT_LEVEL;
+        if av_frame_get_side_data(frame.frame, side_data_type).is_null() {
+          let side_data = av_frame_new_side_data(
+            frame.frame,
+            side_data_type,
+            size_of::<AVContentLightMetadata>() as _,
+          );
+          if side_data.is_null() {
+            return Err("Unable to allocate content light level metadata".to_string());
+          }
+          content_light_level.write_to((*side_data).data as *mut AVContentLightMetadata);
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Mark the frame with the encoder field order, returning its previous one.
+  #[cfg(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+  unsafe fn set_field_flags(&self, frame: &Frame) -> i32 {
+    let flags = (*frame.frame).flags;
+    let field_flags = AV_FRAME_FLAG_INTERLACED | AV_FRAME_FLAG_TOP_FIELD_FIRST;
+    if self.field_order.is_interlaced() {
+      (*frame.frame).flags &= !field_flags;
+      (*frame.frame).flags |= AV_FRAME_FLAG_INTERLACED;
+      if self.field_order.is_top_field_first() {
+        (*frame.frame).flags |= AV_FRAME_FLAG_TOP_FIELD_FIRST;
+      }
+    }
+    flags & field_flags
+  }
+
+  /// Mark the frame with the encoder field order, returning its previous one.
+  #[cfg(not(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+  unsafe fn set_field_flags(&self, frame: &Frame) -> i32 {
+    let flags = (*frame.frame).interlaced_frame | (*frame.frame).top_field_first << 1;
+    if self.field_order.is_interlaced() {
+      (*frame.frame).interlaced_frame = 1;
+      (*frame.frame).top_field_first = self.field_order.is_top_field_first() as i32;
+    }
+    flags
+  }
+
   pub fn encode(&mut self, frame: &Frame, packet: &Packet) -> Result<bool, String> {
     unsafe {
-      (*frame.frame).pts = self.pts;
-      self.pts += 1;
+      let input_pts = (*frame.frame).pts;
+      (*frame.frame).pts = self.rescale_pts(input_pts);
+      self.attach_hdr_metadata(frame)?;
+      let field_flags = self.set_field_flags(frame);
 
-      check_result!(avcodec_send_frame(self.codec_context, frame.frame));
+      let ret = avcodec_send_frame(self.codec_context, frame.frame);
+      // the frame can be shared with other encoders
+      (*frame.frame).pts = input_pts;
+      restore_field_flags(frame, field_flags);
+      check_result!(ret);
       let ret = avcodec_receive_packet(self.codec_context, packet.packet as *mut _);
 
       if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
@@ -145,9 +740,32 @@ impl VideoEncoder {
         "received encoded packet with {} bytes",
         (*packet.packet).size
       );
+      self.collect_stats();
       Ok(true)
     }
   }
+
+  /// Drain the frames delayed by the encoder.
+  pub fn flush(&mut self) -> Result<Vec<Packet>, String> {
+    let mut packets = vec![];
+    unsafe {
+      check_result!(avcodec_send_frame(self.codec_context, null_mut()));
+      loop {
+        let packet = Packet {
+          name: None,
+          packet: av_packet_alloc(),
+        };
+        let ret = avcodec_receive_packet(self.codec_context, packet.packet);
+        if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
+          break;
+        }
+        check_result!(ret);
+        self.collect_stats();
+        packets.push(packet);
+      }
+    }
+    Ok(packets)
+  }
 }
 
 impl Drop for VideoEncoder {
@@ -158,5 +776,290 @@ impl Drop for VideoEncoder {
         avcodec_free_context(&mut self.codec_context);
       }
     }
+    if let Some(stats_file) = &self.stats_file {
+      let mut mbtree_file = stats_file.clone().into_os_string();
+      mbtree_file.push(".mbtree");
+      for path in [stats_file.as_os_str(), mbtree_file.as_os_str()] {
+        if let Err(error) = std::fs::remove_file(path) {
+          warn!("Unable to remove {}: {error}", path.to_string_lossy());
+        }
+      }
+    }
+  }
+}
+
+/// Parse the 64 comma separated coefficients of a quantization matrix, in natural order.
+fn parse_matrix(key: &str, data: &str) -> Result<[u16; 64], String> {
+  let error = || format!("'{data}' is not a valid value for {key}");
+  let coefficients = data
+    .split(',')
+    .map(|coefficient| match coefficient.trim().parse::<u16>() {
+      Ok(coefficient) if (1..=255).contains(&coefficient) => Ok(coefficient),
+      _ => Err(error()),
+    })
+    .collect::<Result<Vec<u16>, String>>()?;
+  coefficients.try_into().map_err(|_| error())
+}
+
+/// Light levels are coded on 16 bits in the content light level side data.
+fn parse_light_level(key: &str, value: i64) -> Result<u32, String> {
+  match u16::try_from(value) {
+    Ok(value) => Ok(u32::from(value)),
+    Err(_) => Err(format!("'{value}' is not a valid value for {key}")),
+  }
+}
+
+/// Point the `stats` option of encoders keeping their statistics themselves at `path`.
+unsafe fn set_stats_file(codec_context: *mut AVCodecContext, path: &Path) -> Result<(), String> {
+  ParameterValue::String(path.to_string_lossy().to_string())
+    .set("stats", codec_context as *mut c_void)
+}
+
+#[cfg(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1))]
+unsafe fn restore_field_flags(frame: &Frame, flags: i32) {
+  let field_flags = AV_FRAME_FLAG_INTERLACED | AV_FRAME_FLAG_TOP_FIELD_FIRST;
+  (*frame.frame).flags = ((*frame.frame).flags & !field_flags) | flags;
+}
+
+#[cfg(not(any(ffmpeg_6_1, ffmpeg_7_0, ffmpeg_7_1)))]
+unsafe fn restore_field_flags(frame: &Frame, flags: i32) {
+  (*frame.frame).interlaced_frame = flags & 1;
+  (*frame.frame).top_field_first = flags >> 1 & 1;
+}
+
+#[test]
+fn rate_control_parameters() {
+  let mut parameters = HashMap::new();
+  parameters.insert("bitrate".to_string(), ParameterValue::Int64(50_000_000));
+  parameters.insert("maxrate".to_string(), ParameterValue::Int64(50_000_000));
+  parameters.insert("qmin".to_string(), ParameterValue::Int64(1));
+  parameters.insert("qmax".to_string(), ParameterValue::Int64(12));
+  assert_eq!(
+    RateControl::from_parameters(&parameters),
+    Err("maxrate requires a bufsize".to_string())
+  );
+
+  parameters.insert("bufsize".to_string(), ParameterValue::Int64(17_825_792));
+  let rate_control = RateControl::from_parameters(&parameters).unwrap();
+  assert_eq!(rate_control.buffer_size, Some(17_825_792));
+  assert_eq!(rate_control.min_rate, None);
+
+  parameters.insert("minrate".to_string(), ParameterValue::Int64(60_000_000));
+  assert!(RateControl::from_parameters(&parameters).is_err());
+  parameters.remove("minrate");
+
+  parameters.insert(
+    "rc_init_occupancy".to_string(),
+    ParameterValue::String("full".to_string()),
+  );
+  assert!(RateControl::from_parameters(&parameters).is_err());
+
+  assert_eq!("tff".parse(), Ok(FieldOrder::TopFirst));
+  assert!(FieldOrder::BottomCodedFirst.is_interlaced());
+  assert!(!FieldOrder::BottomCodedFirst.is_top_field_first());
+  assert!("interlaced".parse::<FieldOrder>().is_err());
+}
+
+#[test]
+fn color_parameters() {
+  let mut parameters = HashMap::new();
+  parameters.insert("width".to_string(), ParameterValue::Int64(64));
+  parameters.insert("height".to_string(), ParameterValue::Int64(48));
+  parameters.insert(
+    "frame_rate".to_string(),
+    ParameterValue::Rational(tools::rational::Rational::new(25, 1)),
+  );
+  parameters.insert(
+    "color_primaries".to_string(),
+    ParameterValue::String("bt2020".to_string()),
+  );
+  parameters.insert(
+    "color_trc".to_string(),
+    ParameterValue::String("smpte2084".to_string()),
+  );
+  parameters.insert(
+    "chroma_location".to_string(),
+    ParameterValue::String("topleft".to_string()),
+  );
+  let mut output_stream = OutputStream {
+    label: None,
+    codec: "mpeg2video".to_string(),
+    parameters,
+    timing: vec![],
+    two_pass: false,
+  };
+  let encoder = VideoEncoder::new("video".to_string(), 0, &output_stream).unwrap();
+  unsafe {
+    assert_eq!(
+      (*encoder.codec_context).color_primaries,
+      AVColorPrimaries::AVCOL_PRI_BT2020
+    );
+    assert_eq!(
+      (*encoder.codec_context).color_trc,
+      AVColorTransferCharacteristic::AVCOL_TRC_SMPTE2084
+    );
+    assert_eq!(
+      (*encoder.codec_context).chroma_sample_location,
+      AVChromaLocation::AVCHROMA_LOC_TOPLEFT
+    );
   }
+
+  output_stream.parameters.insert(
+    "colorspace".to_string(),
+    ParameterValue::String("bt2100".to_string()),
+  );
+  assert_eq!(
+    VideoEncoder::new("video".to_string(), 0, &output_stream).err(),
+    Some("'bt2100' is not a valid value for Colorspace".to_string())
+  );
+  output_stream.parameters.remove("colorspace");
+
+  output_stream
+    .parameters
+    .insert("max_fall".to_string(), ParameterValue::Int64(400));
+  assert_eq!(
+    VideoEncoder::new("video".to_string(), 0, &output_stream).err(),
+    Some("max_fall requires a max_cll".to_string())
+  );
+
+  output_stream
+    .parameters
+    .insert("max_cll".to_string(), ParameterValue::Int64(-1));
+  assert_eq!(
+    VideoEncoder::new("video".to_string(), 0, &output_stream).err(),
+    Some("'-1' is not a valid value for max_cll".to_string())
+  );
+  output_stream
+    .parameters
+    .insert("max_cll".to_string(), ParameterValue::Int64(1000));
+  output_stream
+    .parameters
+    .insert("max_fall".to_string(), ParameterValue::Int64(65536));
+  assert_eq!(
+    VideoEncoder::new("video".to_string(), 0, &output_stream).err(),
+    Some("'65536' is not a valid value for max_fall".to_string())
+  );
+}
+
+#[test]
+fn rescale_timestamps() {
+  assert_eq!("vfr".parse(), Ok(TimestampMode::Passthrough));
+  assert_eq!("cfr".parse(), Ok(TimestampMode::Cfr));
+  assert!("auto".parse::<TimestampMode>().is_err());
+
+  let mut parameters = HashMap::new();
+  parameters.insert("width".to_string(), ParameterValue::Int64(64));
+  parameters.insert("height".to_string(), ParameterValue::Int64(48));
+  parameters.insert(
+    "frame_rate".to_string(),
+    ParameterValue::Rational(tools::rational::Rational::new(25, 1)),
+  );
+  let mut output_stream = OutputStream {
+    label: None,
+    codec: "mpeg2video".to_string(),
+    parameters,
+    timing: vec![],
+    two_pass: false,
+  };
+  let milliseconds = AVRational { num: 1, den: 1000 };
+
+  let mut encoder = VideoEncoder::new("video".to_string(), 0, &output_stream).unwrap();
+  assert_eq!(encoder.timestamp_mode, TimestampMode::Passthrough);
+  encoder.set_input_time_base(milliseconds);
+  assert_eq!(encoder.rescale_pts(0), 0);
+  assert_eq!(encoder.rescale_pts(40), 1);
+  // repeated and missing timestamps keep increasing
+  assert_eq!(encoder.rescale_pts(40), 2);
+  assert_eq!(encoder.rescale_pts(AV_NOPTS_VALUE), 3);
+  assert_eq!(encoder.rescale_pts(200), 5);
+
+  output_stream.parameters.insert(
+    "timestamp_mode".to_string(),
+    ParameterValue::String("cfr".to_string()),
+  );
+  let mut encoder = VideoEncoder::new("video".to_string(), 0, &output_stream).unwrap();
+  encoder.set_input_time_base(milliseconds);
+  assert_eq!(encoder.rescale_pts(1000), 25);
+  assert_eq!(encoder.rescale_pts(1400), 26);
+  assert_eq!(encoder.rescale_pts(1440), 27);
+}
+
+#[test]
+fn encode_two_passes() {
+  let mut parameters = HashMap::new();
+  parameters.insert("width".to_string(), ParameterValue::Int64(64));
+  parameters.insert("height".to_string(), ParameterValue::Int64(48));
+  parameters.insert("bitrate".to_string(), ParameterValue::Int64(200_000));
+  parameters.insert("max_b_frames".to_string(), ParameterValue::Int64(2));
+  parameters.insert(
+    "frame_rate".to_string(),
+    ParameterValue::Rational(tools::rational::Rational::new(25, 1)),
+  );
+  let output_stream = OutputStream {
+    label: None,
+    codec: "mpeg2video".to_string(),
+    parameters,
+    timing: vec![],
+    two_pass: true,
+  };
+
+  let encode = |encoder: &mut VideoEncoder| {
+    let mut nb_packets = 0;
+    for index in 0..10 {
+      let frame = unsafe {
+        let frame = av_frame_alloc();
+        (*frame).width = 64;
+        (*frame).height = 48;
+        (*frame).format = AVPixelFormat::AV_PIX_FMT_YUV420P as i32;
+        (*frame).pts = index;
+        assert_eq!(av_frame_get_buffer(frame, 0), 0);
+        let luma =
+          std::slice::from_raw_parts_mut((*frame).data[0], (*frame).linesize[0] as usize * 48);
+        for (offset, sample) in luma.iter_mut().enumerate() {
+          *sample = (offset as i64 * (index + 1)) as u8;
+        }
+        for plane in 1..3 {
+          std::ptr::write_bytes(
+            (*frame).data[plane],
+            128,
+            (*frame).linesize[plane] as usize * 24,
+          );
+        }
+        Frame {
+          name: Some("video".to_string()),
+          frame,
+          index: 0,
+        }
+      };
+      let packet = Packet {
+        name: None,
+        packet: unsafe { av_packet_alloc() },
+      };
+      if encoder.encode(&frame, &packet).unwrap() {
+        nb_packets += 1;
+      }
+    }
+    nb_packets + encoder.flush().unwrap().len()
+  };
+
+  let mut first_pass =
+    VideoEncoder::new_with_pass("video".to_string(), 0, &output_stream, EncodingPass::First)
+      .unwrap();
+  assert_eq!(encode(&mut first_pass), 10);
+  // the statistics of every frame are kept, not only the ones of the last frame
+  let stats = first_pass.get_stats().unwrap();
+  match &stats {
+    PassStats::Log(log) => assert_eq!(log.matches("in:").count(), 10),
+    PassStats::File(path) => panic!("unexpected statistics file {}", path.display()),
+  }
+
+  let mut second_pass = VideoEncoder::new_with_pass(
+    "video".to_string(),
+    0,
+    &output_stream,
+    EncodingPass::Second { stats },
+  )
+  .unwrap();
+  assert_eq!(encode(&mut second_pass), 10);
+  assert_eq!(second_pass.get_stats(), None);
 }